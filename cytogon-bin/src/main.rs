@@ -17,6 +17,38 @@ fn main() {
     cave.apply_rule(&rule);
     //println!("{}", export_txt2(cave.size, &cave.data));
     //println!("{}", export_txt3(cave.size, &cave.data));
+
+    // Optionally save the mesh of the cave to an OBJ, glTF, GLB, PLY or STL file
+    if let Some(path) = std::env::args().nth(1) {
+        let mut mesh = cave.marching_cubes(&MeshOptions::default());
+        mesh.compute_box_uvs(0.25);
+        let result = if path.ends_with(".glb") {
+            mesh.save_glb(&path, &GltfOptions::default())
+        } else if path.ends_with(".gltf") {
+            mesh.save_gltf(&path, &GltfOptions::default())
+        } else if path.ends_with(".ply") {
+            mesh.save_ply(&path, &PlyOptions::default())
+        } else if path.ends_with(".stl") {
+            mesh.save_stl(&path, &StlOptions::default())
+                .map(|validity| {
+                    if !validity.is_valid() {
+                        eprintln!("Warning: the mesh is not watertight and manifold: {validity:?}");
+                    }
+                })
+        } else {
+            let options = ObjOptions {
+                name: "cave".to_string(),
+                groups: true,
+                ..Default::default()
+            };
+            mesh.save_obj(&path, &options)
+        };
+        if let Err(err) = result {
+            eprintln!("Failed to save {path}: {err}");
+            std::process::exit(1);
+        }
+        println!("Saved {} triangles to {path}", mesh.triangle_count());
+    }
 }
 
 fn export_txt2(size: UVec2, data: &[bool]) -> String {
@@ -1,11 +1,27 @@
+//! AVX2 implementation of the separable neighbor counting and the rule
+//! application of [`Grid3`].
+//!
+//! All functions require the `avx2` target feature. Callers must check its
+//! availability at runtime with `is_x86_feature_detected!("avx2")` before
+//! calling any of them.
+//!
+//! The counts of a 4x4x4 bit block are stored as 64 bytes, one per cell, in
+//! the same order as the bits of the block. They're loaded into two 256-bit
+//! registers, the first one holding the cells at Z=0 (low 128-bit lane) and
+//! Z=1 (high lane), and the second one the cells at Z=2 and Z=3. Each 128-bit
+//! lane holds a single Z layer of 4x4 cells, with 4 bytes per row along Y.
+//!
+//! [`Grid3`]: crate::Grid3
+
 #[cfg(target_arch = "x86")]
 use std::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-use glam::UVec3;
+use glam::IVec3;
 
 #[inline]
+#[target_feature(enable = "avx2")]
 unsafe fn expand_b2b_32(
     bits: __m256i,
     mask_b2b_load: __m256i,
@@ -27,121 +43,257 @@ unsafe fn expand_b2b_32(
     _mm256_and_si256(a, mask_bit1)
 }
 
-pub unsafe fn count_neighbors(size: UVec3, data: &[u64], default: bool) -> Vec<u8> {
-    let capacity = size.x as usize * size.y as usize * size.z as usize;
-    let mut counts = Vec::with_capacity(capacity);
-    counts.resize(capacity, 0u8);
-
-    // AVX2 has 16 registers of 256 bits.
-    // -
-
-    // Grab 4 blocks of 4x4x4 cells (64 bits) into one 256-bit register.
-    {
-        let src = data.as_ptr();
-        let num_blocks = (size.x / 4) as usize;
-        for iblock in 0..num_blocks {
-            // Load 4 blocks
-            let a = _mm256_lddqu_si256(src.add(iblock * 4) as *const __m256i);
-
-            // Left: x--
-            let a_xm = {
-                // Clear the left face, that is top bit of each group of 4 bits
-                let mask_x234 = 0x7777_7777_7777_7777i64;
-                let mask_x234 = _mm256_set_epi64x(mask_x234, mask_x234, mask_x234, mask_x234);
-                let b = _mm256_and_si256(a, mask_x234);
-                // Move everything 1 bit left; this gives the correct result for the top 3 bits
-                // along X. Note that we move within a block of 64 bits (epi64),
-                // so bits cannot overflow into an adjacent block.
-                let b = _mm256_slli_epi64::<1>(b);
-
-                // For the bottom (rightmost) X bit, it comes from the next block, so things are
-                // a bit more complicated. Grab the left face (top bit), and
-                // shift it 3 bits right to move it to be the right face.
-                let c = _mm256_andnot_si256(a, mask_x234);
-                let c = _mm256_srli_epi64::<3>(c);
-                // Now we need to move each left face to the next block. This would mean a shift
-                // by 64 bits. Unfortunately, _mm256_slli_si256() despite the
-                // name works on 2 lanes of 128 bits, so would block the move in the middle.
-                // Instead, since we move by 64, we can use _mm256_permute4x64_epi64() to
-                // simulate a shift.
-                let c = _mm256_permute4x64_epi64::<0b10_01_00_00>(c);
-                // Clear the last block, for which we don't have data loaded, and was unchanged
-                // by previous op.
-                let mask_bbb0 = _mm256_set_epi64x(-1i64, -1i64, -1i64, 0i64);
-                let c = _mm256_and_si256(c, mask_bbb0);
-
-                // Combine. This contains the proper value for all but the low bit of the last
-                // block, which requires some data from the next block (not
-                // loaded yet).
-                _mm256_or_si256(b, c)
-            };
-
-            // Right: x++
-            let a_xp = {
-                // Clear the right face, that is bottom bit of each group of 4 bits
-                let mask_x123 = 0x1111_1111_1111_1111i64;
-                let mask_x123 = _mm256_set_epi64x(mask_x123, mask_x123, mask_x123, mask_x123);
-                let b = _mm256_and_si256(a, mask_x123);
-                // Move everything 1 bit right; this gives the correct result for the bottom 3
-                // bits along X. Note that we move within a block of 64 bits
-                // (epi64), so bits cannot overflow into an adjacent block.
-                let b = _mm256_srli_epi64::<1>(b);
-
-                // For the top (leftmost) X bit, it comes from the previous block, so things are
-                // a bit more complicated. Grab the right face (bottom bit), and
-                // shift it 3 bits left to move it to be the left face.
-                let c = _mm256_andnot_si256(a, mask_x123);
-                let c = _mm256_slli_epi64::<3>(c);
-                // Now we need to move each right face to the previous block. This would mean a
-                // shift by 64 bits. Unfortunately, _mm256_srli_si256() despite
-                // the name works on 2 lanes of 128 bits, so would block the move in the middle.
-                // Instead, since we move by 64, we can use _mm256_permute4x64_epi64() to
-                // simulate a shift.
-                let c = _mm256_permute4x64_epi64::<0b11_11_10_01>(c);
-                // Clear the first block, for which we don't have data loaded, and was unchanged
-                // by previous op.
-                let mask_0bbb = _mm256_set_epi64x(0i64, -1i64, -1i64, -1i64);
-                let c = _mm256_and_si256(c, mask_0bbb);
-
-                // Combine. This contains the proper value for all but the high bit of the first
-                // block, which requires some data from the previous block (not
-                // loaded yet).
-                _mm256_or_si256(b, c)
-            };
-
-            let mask_b2b_mask = _mm256_set1_epi64x(bytemuck::cast(0x8040201008040201u64));
-            let mask_bit1 = _mm256_set1_epi8(1);
-
-            // Accumulate. There's up to 26 neighbors in 3D, so we need 5 bits per cell. For
-            // simplicity we use 8 bits. This means we need to convert one
-            // 256-bit 4-block result into 8 registers of 256-bit (32 values of 8 bits).
-            let mask_b2b_load = _mm256_set_epi64x(
-                0x0303030303030303,
-                0x0202020202020202,
-                0x0101010101010101,
-                0x0000000000000000,
-            );
-            // Expand 32 bits to 32 bytes (lowest bit set of each byte)
-            let acc0 = expand_b2b_32(a, mask_b2b_load, mask_b2b_mask, mask_bit1);
-            // Same for x-, and accumulate
-            let a_xm0 = expand_b2b_32(a_xm, mask_b2b_load, mask_b2b_mask, mask_bit1);
-            let acc0 = _mm256_adds_epu8(acc0, a_xm0);
-            // Same for x+, and accumulate
-            let a_xp0 = expand_b2b_32(a_xp, mask_b2b_load, mask_b2b_mask, mask_bit1);
-            let acc0 = _mm256_adds_epu8(acc0, a_xp0);
-
-            // Next, bits [32:63]
-            let inc_mask = _mm256_set1_epi64x(0x0404040404040404);
-            let mask_b2b_load = _mm256_add_epi8(mask_b2b_load, inc_mask);
-            let acc1 = expand_b2b_32(a, mask_b2b_load, mask_b2b_mask, mask_bit1);
-            // Same for x-, and accumulate
-            let a_xm0 = expand_b2b_32(a_xm, mask_b2b_load, mask_b2b_mask, mask_bit1);
-            let acc1 = _mm256_adds_epu8(acc1, a_xm0);
-            // Same for x+, and accumulate
-            let a_xp0 = expand_b2b_32(a_xp, mask_b2b_load, mask_b2b_mask, mask_bit1);
-            let acc1 = _mm256_adds_epu8(acc1, a_xp0);
+/// Expand a bit block into 64 bytes, each byte having its lowest bit set or
+/// not.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn expand_block(b: u64) -> [__m256i; 2] {
+    // Broadcast the block into the 4 lanes of 64 bits, so that each 128-bit lane
+    // can shuffle any of the 8 bytes of the block.
+    let bits = _mm256_set1_epi64x(bytemuck::cast(b));
+    let mask_b2b_mask = _mm256_set1_epi64x(bytemuck::cast(0x8040201008040201u64));
+    let mask_bit1 = _mm256_set1_epi8(1);
+
+    // Bits [0:31]
+    let mask_b2b_load = _mm256_set_epi64x(
+        0x0303030303030303,
+        0x0202020202020202,
+        0x0101010101010101,
+        0x0000000000000000,
+    );
+    let lo = expand_b2b_32(bits, mask_b2b_load, mask_b2b_mask, mask_bit1);
+
+    // Bits [32:63]
+    let mask_b2b_load = _mm256_add_epi8(mask_b2b_load, _mm256_set1_epi8(4));
+    let hi = expand_b2b_32(bits, mask_b2b_load, mask_b2b_mask, mask_bit1);
+
+    [lo, hi]
+}
+
+/// Load the 64 byte counts of the block at index `ib`.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn load_counts(counts: &[u8], ib: usize) -> [__m256i; 2] {
+    let src = counts[ib * 64..ib * 64 + 64].as_ptr();
+    [
+        _mm256_loadu_si256(src as *const __m256i),
+        _mm256_loadu_si256(src.add(32) as *const __m256i),
+    ]
+}
+
+/// Store the 64 byte counts of a single block.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn store_counts(dst: &mut [u8], counts: [__m256i; 2]) {
+    let dst = dst[..64].as_mut_ptr();
+    _mm256_storeu_si256(dst as *mut __m256i, counts[0]);
+    _mm256_storeu_si256(dst.add(32) as *mut __m256i, counts[1]);
+}
+
+/// Sum 3 pairs of registers of byte counts.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn add3(a: [__m256i; 2], b: [__m256i; 2], c: [__m256i; 2]) -> [__m256i; 2] {
+    [
+        _mm256_add_epi8(_mm256_add_epi8(a[0], b[0]), c[0]),
+        _mm256_add_epi8(_mm256_add_epi8(a[1], b[1]), c[1]),
+    ]
+}
+
+/// Advance the position of a block to the next one in X-major order.
+#[inline]
+fn next_block(bpos: &mut IVec3, block_count: IVec3) {
+    bpos.x += 1;
+    if bpos.x >= block_count.x {
+        bpos.x = 0;
+        bpos.y += 1;
+        if bpos.y >= block_count.y {
+            bpos.y = 0;
+            bpos.z += 1;
+        }
+    }
+}
+
+/// Accumulate each cell and its X neighbors of the blocks of `src` into
+/// `counts`.
+///
+/// The `counts` slice covers all the blocks starting at the first block of
+/// the Z slab `z`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn acc_x(src: &[u64], counts: &mut [u8], block_count: IVec3, z: usize) {
+    let dz = block_count.x as usize * block_count.y as usize;
+    let mut bpos = IVec3::new(0, 0, z as i32);
+    for (ib, dst) in (z * dz..).zip(counts.chunks_exact_mut(64)) {
+        let b = src[ib];
+
+        // Cells at X-1, moved to X. The left face comes from the right face of the
+        // previous block.
+        let mut xm = (b << 1) & 0xEEEE_EEEE_EEEE_EEEEu64;
+        if bpos.x > 0 {
+            xm |= (src[ib - 1] & 0x8888_8888_8888_8888u64) >> 3;
+        }
+
+        // Cells at X+1, moved to X. The right face comes from the left face of the
+        // next block.
+        let mut xp = (b >> 1) & 0x7777_7777_7777_7777u64;
+        if bpos.x + 1 < block_count.x {
+            xp |= (src[ib + 1] & 0x1111_1111_1111_1111u64) << 3;
+        }
+
+        // Expand to bytes and accumulate
+        let acc = add3(expand_block(b), expand_block(xm), expand_block(xp));
+        store_counts(dst, acc);
+
+        next_block(&mut bpos, block_count);
+    }
+}
+
+/// Accumulate each cell and its Y neighbors of the blocks of `src` into `dst`.
+///
+/// The `dst` slice covers all the blocks starting at the first block of the Z
+/// slab `z`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn acc_y(src: &[u8], dst: &mut [u8], block_count: IVec3, z: usize) {
+    let dy = block_count.x as usize;
+    let dz = block_count.x as usize * block_count.y as usize;
+    let mut bpos = IVec3::new(0, 0, z as i32);
+    for (ib, dst) in (z * dz..).zip(dst.chunks_exact_mut(64)) {
+        let b = load_counts(src, ib);
+
+        // Rows are 4 bytes, and each 128-bit lane is a single Z layer, so a shift by
+        // one row is a byte shift by 4 within each lane.
+
+        // Cells at Y-1, moved to Y. The first row comes from the last row of the
+        // previous block.
+        let mut ym = [_mm256_slli_si256::<4>(b[0]), _mm256_slli_si256::<4>(b[1])];
+        if bpos.y > 0 {
+            let prev = load_counts(src, ib - dy);
+            ym[0] = _mm256_or_si256(ym[0], _mm256_srli_si256::<12>(prev[0]));
+            ym[1] = _mm256_or_si256(ym[1], _mm256_srli_si256::<12>(prev[1]));
+        }
+
+        // Cells at Y+1, moved to Y. The last row comes from the first row of the
+        // next block.
+        let mut yp = [_mm256_srli_si256::<4>(b[0]), _mm256_srli_si256::<4>(b[1])];
+        if bpos.y + 1 < block_count.y {
+            let next = load_counts(src, ib + dy);
+            yp[0] = _mm256_or_si256(yp[0], _mm256_slli_si256::<12>(next[0]));
+            yp[1] = _mm256_or_si256(yp[1], _mm256_slli_si256::<12>(next[1]));
         }
+
+        store_counts(dst, add3(b, ym, yp));
+
+        next_block(&mut bpos, block_count);
     }
+}
 
-    counts
+/// Accumulate each cell and its Z neighbors of the blocks of `src` into `dst`.
+///
+/// The `dst` slice covers all the blocks starting at the first block of the Z
+/// slab `z`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn acc_z(src: &[u8], dst: &mut [u8], block_count: IVec3, z: usize) {
+    let dz = block_count.x as usize * block_count.y as usize;
+    let mut bpos = IVec3::new(0, 0, z as i32);
+    for (ib, dst) in (z * dz..).zip(dst.chunks_exact_mut(64)) {
+        let b = load_counts(src, ib);
+
+        // Last layer (Z=3) of the previous block, in the high lane
+        let prev = if bpos.z > 0 {
+            load_counts(src, ib - dz)[1]
+        } else {
+            _mm256_setzero_si256()
+        };
+
+        // First layer (Z=0) of the next block, in the low lane
+        let next = if bpos.z + 1 < block_count.z {
+            load_counts(src, ib + dz)[0]
+        } else {
+            _mm256_setzero_si256()
+        };
+
+        // Layers are 128-bit lanes, so a shift by one layer concatenates the high
+        // lane of a register with the low lane of the next one.
+        let z12 = _mm256_permute2x128_si256::<0x21>(b[0], b[1]);
+        let zm = [_mm256_permute2x128_si256::<0x21>(prev, b[0]), z12];
+        let zp = [z12, _mm256_permute2x128_si256::<0x21>(b[1], next)];
+
+        store_counts(dst, add3(b, zm, zp));
+
+        next_block(&mut bpos, block_count);
+    }
+}
+
+/// Remove each cell itself from the counts of its block.
+///
+/// The `counts` slice holds the counts of the `blocks`, in the same order.
+#[target_feature(enable = "avx2")]
+pub unsafe fn remove_self(blocks: &[u64], counts: &mut [u8]) {
+    for (b, dst) in blocks.iter().zip(counts.chunks_exact_mut(64)) {
+        let acc = load_counts(dst, 0);
+        let b = expand_block(*b);
+        store_counts(
+            dst,
+            [_mm256_sub_epi8(acc[0], b[0]), _mm256_sub_epi8(acc[1], b[1])],
+        );
+    }
+}
+
+/// Build a lookup table of 32 counts for [`_mm256_shuffle_epi8()`], as two
+/// registers for counts [0:15] and [16:31]. Each byte is 0xFF if the count
+/// is set in `bits`, or zero otherwise.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn rule_lut(bits: u32) -> [__m256i; 2] {
+    let mut lut = [0u8; 32];
+    for (i, v) in lut.iter_mut().enumerate() {
+        if bits & (1u32 << i) != 0 {
+            *v = 0xFF;
+        }
+    }
+    // The shuffle works within each 128-bit lane, so duplicate the table in both.
+    let lo = _mm_loadu_si128(lut.as_ptr() as *const __m128i);
+    let hi = _mm_loadu_si128(lut.as_ptr().add(16) as *const __m128i);
+    [
+        _mm256_broadcastsi128_si256(lo),
+        _mm256_broadcastsi128_si256(hi),
+    ]
+}
+
+/// Look up 32 counts into a table built with [`rule_lut()`].
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn lookup(lut: [__m256i; 2], counts: __m256i) -> __m256i {
+    // The shuffle only uses the 4 lowest bits of the count, so look up both
+    // tables and select based on whether the count is above 15.
+    let lo = _mm256_shuffle_epi8(lut[0], counts);
+    let hi = _mm256_shuffle_epi8(lut[1], counts);
+    let is_hi = _mm256_cmpgt_epi8(counts, _mm256_set1_epi8(15));
+    _mm256_blendv_epi8(lo, hi, is_hi)
+}
+
+/// Apply a rule to the given blocks, given the neighbor counts of their cells.
+///
+/// The next state of the blocks of `src` is written into `dst`. The `counts`
+/// slice holds the counts of the `src` blocks, in the same order. The padding
+/// bits of the blocks are not cleared, and should be cleared afterward.
+#[target_feature(enable = "avx2")]
+pub unsafe fn apply_rule(src: &[u64], dst: &mut [u64], counts: &[u8], birth: u32, survive: u32) {
+    let birth = rule_lut(birth);
+    let survive = rule_lut(survive);
+    let mask_bit1 = _mm256_set1_epi8(1);
+    for ((b, dst), counts) in src.iter().zip(dst.iter_mut()).zip(counts.chunks_exact(64)) {
+        let alive = expand_block(*b);
+        let counts = load_counts(counts, 0);
+        let mut next = 0u64;
+        for k in 0..2 {
+            let born = lookup(birth, counts[k]);
+            let survived = lookup(survive, counts[k]);
+            let is_alive = _mm256_cmpeq_epi8(alive[k], mask_bit1);
+            let cells = _mm256_blendv_epi8(born, survived, is_alive);
+            // Collect the top bit of each byte
+            next |= (_mm256_movemask_epi8(cells) as u32 as u64) << (k * 32);
+        }
+        *dst = next;
+    }
 }
@@ -0,0 +1,284 @@
+//! Bit-sliced implementation of the neighbor counting and the rule application
+//! of [`Grid3`].
+//!
+//! Instead of expanding each bit block into 64 byte counts, the counts of the
+//! 64 cells of a block are stored as bit-planes, that is a set of `u64` where
+//! plane `k` holds the `k`-th bit of the count of each cell, with the same bit
+//! layout as the block itself. Counts are summed with full-adder logic, and
+//! rules are evaluated as boolean logic on the planes, so each operation
+//! processes all 64 cells of a block at once.
+//!
+//! [`Grid3`]: crate::Grid3
+
+use glam::IVec3;
+
+/// Number of bit-planes needed to store a count of up to 27, that is the 26
+/// Moore neighbors of a cell and the cell itself.
+pub const PLANE_COUNT: usize = 5;
+
+/// Bit-planes of the counts of the 64 cells of a block.
+pub type Planes = [u64; PLANE_COUNT];
+
+/// Move the cells at X-1 to X. The left face comes from the block `prev`.
+#[inline]
+pub fn shift_xm(w: u64, prev: u64) -> u64 {
+    ((w << 1) & 0xEEEE_EEEE_EEEE_EEEEu64) | ((prev >> 3) & 0x1111_1111_1111_1111u64)
+}
+
+/// Move the cells at X+1 to X. The right face comes from the block `next`.
+#[inline]
+pub fn shift_xp(w: u64, next: u64) -> u64 {
+    ((w >> 1) & 0x7777_7777_7777_7777u64) | ((next << 3) & 0x8888_8888_8888_8888u64)
+}
+
+/// Move the cells at Y-1 to Y. The bottom face comes from the block `prev`.
+#[inline]
+pub fn shift_ym(w: u64, prev: u64) -> u64 {
+    ((w << 4) & 0xFFF0_FFF0_FFF0_FFF0u64) | ((prev >> 12) & 0x000F_000F_000F_000Fu64)
+}
+
+/// Move the cells at Y+1 to Y. The top face comes from the block `next`.
+#[inline]
+pub fn shift_yp(w: u64, next: u64) -> u64 {
+    ((w >> 4) & 0x0FFF_0FFF_0FFF_0FFFu64) | ((next << 12) & 0xF000_F000_F000_F000u64)
+}
+
+/// Move the cells at Z-1 to Z. The back face comes from the block `prev`.
+#[inline]
+pub fn shift_zm(w: u64, prev: u64) -> u64 {
+    (w << 16) | (prev >> 48)
+}
+
+/// Move the cells at Z+1 to Z. The front face comes from the block `next`.
+#[inline]
+pub fn shift_zp(w: u64, next: u64) -> u64 {
+    (w >> 16) | (next << 48)
+}
+
+/// Add the bit-sliced counts `x` to the bit-sliced counts `acc`.
+///
+/// The sum must fit in the planes of `acc`.
+#[inline]
+fn add<const N: usize, const M: usize>(acc: &mut [u64; N], x: &[u64; M]) {
+    let mut carry = 0u64;
+    for k in 0..N {
+        let x = if k < M { x[k] } else { 0 };
+        let a = acc[k];
+        acc[k] = a ^ x ^ carry;
+        carry = (a & x) | (carry & (a ^ x));
+    }
+}
+
+/// Sum three bit-sliced counts into bit-sliced counts.
+///
+/// The planes of the inputs are first summed with one full adder each, which
+/// gives a sum and a carry per plane. The carries are then added to the sums
+/// with a single ripple-carry pass.
+#[inline]
+fn add3<const N: usize, const M: usize>(a: &[u64; M], b: &[u64; M], c: &[u64; M]) -> [u64; N] {
+    let mut sum = [0u64; N];
+    let mut carry = [0u64; N];
+    for k in 0..M {
+        sum[k] = a[k] ^ b[k] ^ c[k];
+        carry[k + 1] = (a[k] & b[k]) | (c[k] & (a[k] ^ b[k]));
+    }
+    add(&mut sum, &carry);
+    sum
+}
+
+/// Apply a shift function to all planes of a block, taking the missing data
+/// from the planes of an adjacent block, or zero if there's none.
+#[inline]
+fn shift<const N: usize>(
+    w: &[u64; N],
+    adjacent: Option<&[u64; N]>,
+    f: impl Fn(u64, u64) -> u64,
+) -> [u64; N] {
+    let adjacent = adjacent.copied().unwrap_or([0; N]);
+    std::array::from_fn(|k| f(w[k], adjacent[k]))
+}
+
+/// Advance the position of a block to the next one in X-major order.
+#[inline]
+fn next_block(bpos: &mut IVec3, block_count: IVec3) {
+    bpos.x += 1;
+    if bpos.x >= block_count.x {
+        bpos.x = 0;
+        bpos.y += 1;
+        if bpos.y >= block_count.y {
+            bpos.y = 0;
+            bpos.z += 1;
+        }
+    }
+}
+
+/// Sum each cell and its X neighbors of the block at index `ib` into 2 planes.
+#[inline]
+fn sum_x(src: &[u64], ib: usize, bx: i32, block_count: IVec3) -> [u64; 2] {
+    let b = [src[ib]];
+    let prev = (bx > 0).then(|| [src[ib - 1]]);
+    let next = (bx + 1 < block_count.x).then(|| [src[ib + 1]]);
+    let xm = shift(&b, prev.as_ref(), shift_xm);
+    let xp = shift(&b, next.as_ref(), shift_xp);
+    add3(&b, &xm, &xp)
+}
+
+/// Sum each cell and its X and Y neighbors of the blocks of `src` into 4
+/// planes.
+///
+/// The X sums are cheap enough that they're computed again for each of the
+/// adjacent blocks along Y, instead of being stored.
+///
+/// The `dst` slice covers all the blocks starting at the first block of the Z
+/// slab `z`.
+pub fn acc_xy(src: &[u64], dst: &mut [[u64; 4]], block_count: IVec3, z: usize) {
+    let dy = block_count.x as usize;
+    let dz = block_count.x as usize * block_count.y as usize;
+    let mut bpos = IVec3::new(0, 0, z as i32);
+    for (ib, dst) in (z * dz..).zip(dst.iter_mut()) {
+        let b = sum_x(src, ib, bpos.x, block_count);
+        let prev = (bpos.y > 0).then(|| sum_x(src, ib - dy, bpos.x, block_count));
+        let next = (bpos.y + 1 < block_count.y).then(|| sum_x(src, ib + dy, bpos.x, block_count));
+        let ym = shift(&b, prev.as_ref(), shift_ym);
+        let yp = shift(&b, next.as_ref(), shift_yp);
+        *dst = add3(&b, &ym, &yp);
+        next_block(&mut bpos, block_count);
+    }
+}
+
+/// Sum the X and Y sums of each cell and its Z neighbors into 5 planes, and
+/// remove the cell itself to only keep the count of its neighbors.
+///
+/// The `dst` slice covers all the blocks starting at the first block of the Z
+/// slab `z`.
+pub fn acc_z(blocks: &[u64], src: &[[u64; 4]], dst: &mut [Planes], block_count: IVec3, z: usize) {
+    let dz = block_count.x as usize * block_count.y as usize;
+    let mut bpos = IVec3::new(0, 0, z as i32);
+    for (ib, dst) in (z * dz..).zip(dst.iter_mut()) {
+        let b = &src[ib];
+        let prev = (bpos.z > 0).then(|| &src[ib - dz]);
+        let next = (bpos.z + 1 < block_count.z).then(|| &src[ib + dz]);
+        let zm = shift(b, prev, shift_zm);
+        let zp = shift(b, next, shift_zp);
+        let mut acc: Planes = add3(b, &zm, &zp);
+
+        // Subtract self with borrow propagation
+        let mut borrow = blocks[ib];
+        for plane in acc.iter_mut() {
+            let p = *plane;
+            *plane = p ^ borrow;
+            borrow &= !p;
+        }
+
+        *dst = acc;
+        next_block(&mut bpos, block_count);
+    }
+}
+
+/// Count the von Neumann neighbors of the blocks of `src` into 5 planes.
+///
+/// The `dst` slice covers all the blocks starting at the first block of the Z
+/// slab `z`.
+pub fn count_vn(src: &[u64], dst: &mut [Planes], block_count: IVec3, z: usize) {
+    let dy = block_count.x as usize;
+    let dz = block_count.x as usize * block_count.y as usize;
+    let mut bpos = IVec3::new(0, 0, z as i32);
+    for (ib, dst) in (z * dz..).zip(dst.iter_mut()) {
+        let b = src[ib];
+        let get = |valid: bool, index: usize| if valid { src[index] } else { 0 };
+        let neighbors = [
+            shift_xm(b, get(bpos.x > 0, ib.wrapping_sub(1))),
+            shift_xp(b, get(bpos.x + 1 < block_count.x, ib + 1)),
+            shift_ym(b, get(bpos.y > 0, ib.wrapping_sub(dy))),
+            shift_yp(b, get(bpos.y + 1 < block_count.y, ib + dy)),
+            shift_zm(b, get(bpos.z > 0, ib.wrapping_sub(dz))),
+            shift_zp(b, get(bpos.z + 1 < block_count.z, ib + dz)),
+        ];
+        let mut acc = [0u64; PLANE_COUNT];
+        for n in neighbors {
+            add(&mut acc, &[n]);
+        }
+        *dst = acc;
+        next_block(&mut bpos, block_count);
+    }
+}
+
+/// Add a count to a single cell of bit-sliced counts.
+pub fn add_to_cell(planes: &mut Planes, bit: u8, count: u8) {
+    let mut x = [0u64; PLANE_COUNT];
+    for (k, x) in x.iter_mut().enumerate() {
+        if (count >> k) & 1 != 0 {
+            *x = 1u64 << bit;
+        }
+    }
+    add(planes, &x);
+}
+
+/// Get the count of a single cell from bit-sliced counts.
+#[cfg(test)]
+pub fn get_cell(planes: &Planes, bit: u8) -> u8 {
+    let mut c = 0;
+    for (k, plane) in planes.iter().enumerate() {
+        c |= (((*plane >> bit) & 1) as u8) << k;
+    }
+    c
+}
+
+/// Decoded bit-sliced counts, to test them against sets of counts.
+///
+/// The 2 lowest bits of the counts are decoded into 4 masks, one for each
+/// value, and the 3 highest bits into 8 masks. The cells with a count `n` are
+/// then `lo[n & 3] & hi[n >> 2]`.
+struct Decoded {
+    lo: [u64; 4],
+    hi: [u64; 8],
+}
+
+impl Decoded {
+    #[inline]
+    fn new(planes: &Planes) -> Self {
+        let [p0, p1, p2, p3, p4] = *planes;
+        let lo = [!p0 & !p1, p0 & !p1, !p0 & p1, p0 & p1];
+        let hi34 = [!p3 & !p4, p3 & !p4, !p3 & p4, p3 & p4];
+        let hi = std::array::from_fn(|h| {
+            let p2 = if h & 1 != 0 { p2 } else { !p2 };
+            p2 & hi34[h >> 1]
+        });
+        Self { lo, hi }
+    }
+}
+
+/// Apply a rule to the given blocks, given the bit-sliced neighbor counts of
+/// their cells.
+///
+/// The next state of the blocks of `src` is written into `dst`. The `planes`
+/// slice holds the counts of the `src` blocks, in the same order. The padding
+/// bits of the blocks are not cleared.
+pub fn apply_rule(src: &[u64], dst: &mut [u64], planes: &[Planes], birth: u32, survive: u32) {
+    // Expand each bit of the rule into a full mask, grouped by the 3 highest
+    // bits of the count like the decoded masks.
+    let expand = |bits: u32| -> [[u64; 4]; 8] {
+        std::array::from_fn(|h| {
+            std::array::from_fn(|l| 0u64.wrapping_sub(((bits >> (h * 4 + l)) & 1) as u64))
+        })
+    };
+    let birth_masks = expand(birth);
+    let survive_masks = expand(survive);
+
+    for ((b, dst), planes) in src.iter().zip(dst.iter_mut()).zip(planes.iter()) {
+        let decoded = Decoded::new(planes);
+        let mut born = 0;
+        let mut survived = 0;
+        for h in 0..8 {
+            let mut born_lo = 0;
+            let mut survived_lo = 0;
+            for l in 0..4 {
+                born_lo |= decoded.lo[l] & birth_masks[h][l];
+                survived_lo |= decoded.lo[l] & survive_masks[h][l];
+            }
+            born |= born_lo & decoded.hi[h];
+            survived |= survived_lo & decoded.hi[h];
+        }
+        *dst = (*b & survived) | (!*b & born);
+    }
+}
@@ -0,0 +1,490 @@
+//! Marching squares contour extraction for [`Grid2`].
+//!
+//! The grid is sampled at the center of each cell, and each square of the
+//! sampling lattice made of 4 adjacent cell centers is crossed by the contour
+//! between its alive and dead corners. The segments of all squares are then
+//! chained into closed contours.
+
+use glam::{IVec2, Vec2};
+
+use crate::Grid2;
+
+/// Options controlling the contours extracted from a [`Grid2`].
+///
+/// The cell at position `(i, j)` in the grid covers the rectangle from `origin
+/// + (i, j) * cell_size` to `origin + (i + 1, j + 1) * cell_size`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContourOptions {
+    /// Size of a single cell, in output units.
+    pub cell_size: Vec2,
+    /// Position of the minimum corner of the cell (0,0), in output units.
+    pub origin: Vec2,
+    /// Interpolate the position of the contour points.
+    ///
+    /// By default, points are at the middle between the centers of an alive
+    /// and a dead cell, so contours follow the grid with 45 degree corners.
+    /// With interpolation, the grid is first smoothed by averaging each cell
+    /// with its 8 neighbors, and points are linearly interpolated where the
+    /// smoothed value crosses 0.5, which gives rounder contours. The topology
+    /// of the contours is always the one of the grid.
+    pub interpolate: bool,
+    /// Simplify the contours with the Ramer-Douglas-Peucker algorithm, removing
+    /// points which deviate less than this tolerance, in output units.
+    ///
+    /// A tolerance of zero only removes collinear points. Large tolerances
+    /// may make contours intersect.
+    pub simplify: Option<f32>,
+}
+
+impl Default for ContourOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: Vec2::ONE,
+            origin: Vec2::ZERO,
+            interpolate: false,
+            simplify: None,
+        }
+    }
+}
+
+/// Closed polyline separating alive cells from dead ones.
+///
+/// The last point is implicitly joined to the first one. Contours always have
+/// the alive cells on their left side, so that outer boundaries are
+/// counter-clockwise, and holes are clockwise.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Contour {
+    /// Points of the contour.
+    pub points: Vec<Vec2>,
+}
+
+impl Contour {
+    /// Get the signed area enclosed by the contour, which is positive for
+    /// outer boundaries and negative for holes.
+    pub fn signed_area(&self) -> f32 {
+        let n = self.points.len();
+        (0..n)
+            .map(|i| self.points[i].perp_dot(self.points[(i + 1) % n]))
+            .sum::<f32>()
+            / 2.
+    }
+
+    /// Check if the contour is a hole in an outer boundary, that is if it's
+    /// clockwise.
+    #[inline]
+    pub fn is_hole(&self) -> bool {
+        self.signed_area() < 0.
+    }
+
+    /// Check if a point is inside the contour, with the even-odd rule.
+    pub fn contains(&self, p: Vec2) -> bool {
+        let n = self.points.len();
+        let mut inside = false;
+        for i in 0..n {
+            let a = self.points[i];
+            let b = self.points[(i + 1) % n];
+            if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+/// Region of alive cells, made of an outer boundary and the holes inside it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Polygon {
+    /// Counter-clockwise outer boundary.
+    pub outer: Contour,
+    /// Clockwise holes.
+    pub holes: Vec<Contour>,
+}
+
+impl Grid2 {
+    /// Extract the contours of the alive cells with the marching squares
+    /// algorithm.
+    ///
+    /// Cells outside the grid are considered dead, so contours are always
+    /// closed. Where two alive cells only touch by a corner, they're separated
+    /// into two contours.
+    ///
+    /// ```
+    /// # use cytogon::{ContourOptions, Grid2, IVec2, UVec2};
+    /// let mut grid = Grid2::new(UVec2::new(8, 8));
+    /// grid.fill(false);
+    /// for y in 2..5 {
+    ///     for x in 2..6 {
+    ///         grid.set_cell(IVec2::new(x, y), true);
+    ///     }
+    /// }
+    ///
+    /// let options = ContourOptions {
+    ///     simplify: Some(0.),
+    ///     ..Default::default()
+    /// };
+    /// let contours = grid.contours(&options);
+    /// assert_eq!(contours.len(), 1);
+    /// // Rectangle with 45 degree cut corners
+    /// assert_eq!(contours[0].points.len(), 8);
+    /// assert!(!contours[0].is_hole());
+    /// ```
+    pub fn contours(&self, options: &ContourOptions) -> Vec<Contour> {
+        self.region_contours(true, options)
+    }
+
+    /// Extract the contours of the regions of cells in the given state.
+    ///
+    /// Cells outside the grid are never part of the regions. Dead regions
+    /// are joined where two dead cells only touch by a corner, so that their
+    /// contours are exactly the ones of the alive regions, reversed, except
+    /// along the edges of the grid.
+    pub(crate) fn region_contours(&self, alive: bool, options: &ContourOptions) -> Vec<Contour> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("contours").entered();
+
+        if self.data.is_empty() {
+            return vec![];
+        }
+
+        // The lattice has one point per cell center, plus one layer of cells
+        // around the grid, outside the regions.
+        let n = self.size.as_ivec2() + 2;
+        let index = |p: IVec2| (p.y * n.x + p.x) as usize;
+        let mut inside = vec![false; (n.x * n.y) as usize];
+        for y in 0..n.y {
+            for x in 0..n.x {
+                let p = IVec2::new(x, y);
+                inside[index(p)] = self.cell(p - 1) == Some(alive);
+            }
+        }
+        // The smoothed field is always the one of the alive cells, so that
+        // alive and dead regions share their contours
+        let field = options.interpolate.then(|| {
+            let alive = |q: IVec2| self.cell(q - 1).unwrap_or(false);
+            let mut field = vec![0f32; inside.len()];
+            for y in 0..n.y {
+                for x in 0..n.x {
+                    let mut count = 0;
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let q = IVec2::new(x + dx, y + dy);
+                            if alive(q) {
+                                count += 1;
+                            }
+                        }
+                    }
+                    field[index(IVec2::new(x, y))] = count as f32 / 9.;
+                }
+            }
+            field
+        });
+
+        // Lattice edges are identified by their start point and axis, and the
+        // contour segments link them. Walking the corners of each square
+        // counter-clockwise, a segment goes from each edge leaving the inside
+        // corners to an edge entering them before, so that inside corners are
+        // on its left. Diagonally opposite inside corners are separated by
+        // taking the closest edge, or joined by taking the other one.
+        let edge_id = |p: IVec2, axis: usize| index(p) * 2 + axis;
+        let mut next = vec![usize::MAX; inside.len() * 2];
+        for y in 0..n.y - 1 {
+            for x in 0..n.x - 1 {
+                let p = IVec2::new(x, y);
+                let corners = [p, p + IVec2::X, p + 1, p + IVec2::Y];
+                let edges = [
+                    edge_id(p, 0),
+                    edge_id(p + IVec2::X, 1),
+                    edge_id(p + IVec2::Y, 0),
+                    edge_id(p, 1),
+                ];
+                let a = corners.map(|c| inside[index(c)]);
+                for i in 0..4 {
+                    if a[i] || !a[(i + 1) % 4] {
+                        continue;
+                    }
+                    let mut exits = (1..4)
+                        .map(|d| (i + d) % 4)
+                        .filter(|j| a[*j] && !a[(*j + 1) % 4]);
+                    let j = if alive {
+                        exits.next()
+                    } else {
+                        exits.next_back()
+                    }
+                    .unwrap();
+                    next[edges[j]] = edges[i];
+                }
+            }
+        }
+
+        let point = |e: usize| {
+            let p0 = IVec2::new((e / 2) as i32 % n.x, (e / 2) as i32 / n.x);
+            let p1 = p0 + [IVec2::X, IVec2::Y][e % 2];
+            let mut t = 0.5;
+            if let Some(field) = &field {
+                let (f0, f1) = (field[index(p0)], field[index(p1)]);
+                if (f0 - 0.5) * (f1 - 0.5) < 0. {
+                    t = (0.5 - f0) / (f1 - f0);
+                }
+            }
+            // Lattice point p is the center of cell p-1
+            let pos = p0.as_vec2() - 0.5 + (p1 - p0).as_vec2() * t;
+            options.origin + pos * options.cell_size
+        };
+
+        let mut contours = vec![];
+        for start in 0..next.len() {
+            if next[start] == usize::MAX {
+                continue;
+            }
+            let mut points = vec![];
+            let mut e = start;
+            while next[e] != usize::MAX {
+                points.push(point(e));
+                e = std::mem::replace(&mut next[e], usize::MAX);
+            }
+            if let Some(tolerance) = options.simplify {
+                points = simplify(&points, tolerance);
+            }
+            contours.push(Contour { points });
+        }
+        contours
+    }
+
+    /// Extract the contours of the alive cells, grouped into polygons made of
+    /// an outer boundary and its holes.
+    ///
+    /// See [`contours()`] for details.
+    ///
+    /// [`contours()`]: Self::contours
+    pub fn polygons(&self, options: &ContourOptions) -> Vec<Polygon> {
+        self.region_polygons(true, options)
+    }
+
+    /// Extract the contours of the regions of cells in the given state,
+    /// grouped into polygons.
+    pub(crate) fn region_polygons(&self, alive: bool, options: &ContourOptions) -> Vec<Polygon> {
+        let (holes, outers): (Vec<_>, Vec<_>) = self
+            .region_contours(alive, options)
+            .into_iter()
+            .partition(Contour::is_hole);
+        let mut polygons: Vec<Polygon> = outers
+            .into_iter()
+            .map(|outer| Polygon {
+                outer,
+                holes: vec![],
+            })
+            .collect();
+
+        // Each hole belongs to the smallest outer boundary containing it
+        let areas: Vec<f32> = polygons.iter().map(|p| p.outer.signed_area()).collect();
+        for hole in holes {
+            let p = hole.points[0];
+            let owner = (0..polygons.len())
+                .filter(|i| polygons[*i].outer.contains(p))
+                .min_by(|a, b| areas[*a].total_cmp(&areas[*b]));
+            if let Some(owner) = owner {
+                polygons[owner].holes.push(hole);
+            }
+        }
+        polygons
+    }
+}
+
+/// Simplify a closed polyline with the Ramer-Douglas-Peucker algorithm.
+fn simplify(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 4 {
+        return points.to_vec();
+    }
+
+    // Split the loop into two open polylines between the first point and the
+    // point farthest from it
+    let far = (1..points.len())
+        .max_by(|a, b| {
+            let da = points[*a].distance_squared(points[0]);
+            let db = points[*b].distance_squared(points[0]);
+            da.total_cmp(&db)
+        })
+        .unwrap();
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[far] = true;
+    let mut closed = points.to_vec();
+    closed.push(points[0]);
+    simplify_range(&closed, 0, far, tolerance, &mut keep);
+    simplify_range(&closed, far, points.len(), tolerance, &mut keep);
+
+    // The first point itself may be collinear with its neighbors
+    let kept: Vec<usize> = (0..points.len()).filter(|i| keep[*i]).collect();
+    if kept.len() > 3 {
+        let prev = points[kept[kept.len() - 1]];
+        let next = points[kept[1]];
+        if distance_to_segment(points[0], prev, next) <= tolerance {
+            keep[0] = false;
+        }
+    }
+    (0..points.len())
+        .filter(|i| keep[*i])
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Simplify the open polyline between `first` and `last` (inclusive), which
+/// are both kept.
+fn simplify_range(points: &[Vec2], first: usize, last: usize, tolerance: f32, keep: &mut [bool]) {
+    if last <= first + 1 {
+        return;
+    }
+    let (a, b) = (points[first], points[last]);
+    let (index, distance) = (first + 1..last)
+        .map(|i| (i, distance_to_segment(points[i], a, b)))
+        .max_by(|x, y| x.1.total_cmp(&y.1))
+        .unwrap();
+    if distance > tolerance {
+        keep[index] = true;
+        simplify_range(points, first, index, tolerance, keep);
+        simplify_range(points, index, last, tolerance, keep);
+    }
+}
+
+/// Get the distance from point `p` to the segment `[a, b]`.
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let len2 = ab.length_squared();
+    if len2 == 0. {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / len2).clamp(0., 1.);
+    p.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec2;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn single_cell() {
+        let mut grid = Grid2::new(UVec2::new(3, 3));
+        grid.fill(false);
+        grid.set_cell(IVec2::ONE, true);
+        let contours = grid.contours(&ContourOptions::default());
+        assert_eq!(contours.len(), 1);
+        let c = &contours[0];
+        assert_eq!(c.points.len(), 4);
+        assert_eq!(c.signed_area(), 0.5);
+        assert!(c.contains(Vec2::splat(1.5)));
+        assert!(!c.contains(Vec2::splat(0.5)));
+    }
+
+    #[test]
+    fn holes() {
+        // Ring around a dead 2x2 center, with an island inside it
+        let mut grid = Grid2::new(UVec2::new(10, 10));
+        grid.fill(false);
+        for y in 1..9 {
+            for x in 1..9 {
+                grid.set_cell(IVec2::new(x, y), true);
+            }
+        }
+        for y in 3..7 {
+            for x in 3..7 {
+                grid.set_cell(IVec2::new(x, y), false);
+            }
+        }
+        grid.set_cell(IVec2::new(4, 4), true);
+
+        let options = ContourOptions {
+            cell_size: Vec2::new(2., 0.5),
+            origin: Vec2::new(-10., 3.),
+            ..Default::default()
+        };
+        let contours = grid.contours(&options);
+        assert_eq!(contours.len(), 3);
+        assert_eq!(contours.iter().filter(|c| c.is_hole()).count(), 1);
+
+        let mut polygons = grid.polygons(&options);
+        assert_eq!(polygons.len(), 2);
+        polygons.sort_by(|a, b| b.outer.signed_area().total_cmp(&a.outer.signed_area()));
+        assert_eq!(polygons[0].holes.len(), 1);
+        assert!(polygons[1].holes.is_empty());
+        let hole = &polygons[0].holes[0];
+        assert!(polygons[0].outer.contains(hole.points[0]));
+        assert!(hole.contains(polygons[1].outer.points[0]));
+
+        // The island is a diamond around the center of cell (4,4)
+        let center = options.origin + Vec2::splat(4.5) * options.cell_size;
+        assert!(polygons[1].outer.contains(center));
+        assert_eq!(polygons[1].outer.signed_area(), 0.5 * 2. * 0.5);
+    }
+
+    #[test]
+    fn simplify() {
+        let mut grid = Grid2::new(UVec2::new(37, 21));
+        grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+        let raw = grid.contours(&ContourOptions::default());
+
+        for tolerance in [0., 0.5] {
+            let options = ContourOptions {
+                simplify: Some(tolerance),
+                ..Default::default()
+            };
+            let simplified = grid.contours(&options);
+            assert_eq!(simplified.len(), raw.len());
+            for (s, r) in simplified.iter().zip(&raw) {
+                assert!(s.points.len() <= r.points.len());
+                assert!(s.points.iter().all(|p| r.points.contains(p)));
+                if tolerance == 0. {
+                    // Removing collinear points doesn't change the area
+                    assert!((s.signed_area() - r.signed_area()).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interpolate() {
+        // Disc of cells
+        let mut grid = Grid2::new(UVec2::new(24, 24));
+        grid.fill(false);
+        let center = Vec2::splat(12.);
+        for y in 0..24 {
+            for x in 0..24 {
+                let pos = IVec2::new(x, y);
+                if (pos.as_vec2() + 0.5).distance(center) < 9. {
+                    grid.set_cell(pos, true);
+                }
+            }
+        }
+
+        let raw = grid.contours(&ContourOptions::default());
+        let options = ContourOptions {
+            interpolate: true,
+            ..Default::default()
+        };
+        let smooth = grid.contours(&options);
+        assert_eq!(smooth.len(), 1);
+        assert_eq!(smooth[0].points.len(), raw[0].points.len());
+        assert!(!smooth[0].is_hole());
+
+        // Interpolated points are closer to the circle on average
+        let error = |c: &Contour| {
+            c.points
+                .iter()
+                .map(|p| (p.distance(center) - 9.).abs())
+                .sum::<f32>()
+                / c.points.len() as f32
+        };
+        assert!(error(&smooth[0]) < error(&raw[0]));
+    }
+
+    #[test]
+    fn empty() {
+        let grid = Grid2::new(UVec2::ONE * 8);
+        assert!(grid.contours(&ContourOptions::default()).is_empty());
+        let mut grid = Grid2::new(UVec2::ONE * 8);
+        grid.fill(false);
+        assert!(grid.polygons(&ContourOptions::default()).is_empty());
+    }
+}
@@ -0,0 +1,484 @@
+//! Quadric error metric decimation of [`Mesh`]es.
+//!
+//! Each vertex accumulates the quadric of the planes of its original faces,
+//! which measures the sum of the squared distances of a point to those planes.
+//! Edges are then collapsed one by one in order of increasing error, each into
+//! the point minimizing the sum of the quadrics of its two vertices, following
+//! Garland and Heckbert.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use glam::{DMat3, DVec3};
+
+use crate::Mesh;
+
+/// Options controlling the decimation of a [`Mesh`].
+///
+/// The decimation stops when either limit is reached. By default, only the
+/// collapses which don't change the shape of the mesh are done, which merges
+/// the coplanar triangles of flat areas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecimateOptions {
+    /// Number of triangles to reduce the mesh to.
+    pub target_triangles: usize,
+    /// Maximum error of a single collapse, as a sum of squared distances to the
+    /// planes of the original triangles, in squared mesh units.
+    pub max_error: f32,
+}
+
+impl Default for DecimateOptions {
+    fn default() -> Self {
+        Self {
+            target_triangles: 0,
+            max_error: 0.,
+        }
+    }
+}
+
+/// Cosine of the maximum angle a triangle can rotate by during a collapse,
+/// here 45 degrees.
+const MIN_NORMAL_DOT: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// Symmetric 4x4 matrix of a quadric error, stored as its upper triangle.
+#[derive(Debug, Default, Clone, Copy)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Quadric of the squared distance to the plane `n.p + d = 0`.
+    fn plane(n: DVec3, d: f64) -> Self {
+        Self([
+            n.x * n.x,
+            n.x * n.y,
+            n.x * n.z,
+            n.x * d,
+            n.y * n.y,
+            n.y * n.z,
+            n.y * d,
+            n.z * n.z,
+            n.z * d,
+            d * d,
+        ])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a += b;
+        }
+    }
+
+    fn error(&self, p: DVec3) -> f64 {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+        let e = p.x * (a2 * p.x + 2. * (ab * p.y + ac * p.z + ad))
+            + p.y * (b2 * p.y + 2. * (bc * p.z + bd))
+            + p.z * (c2 * p.z + 2. * cd)
+            + d2;
+        e.max(0.)
+    }
+
+    /// Find the point minimizing the error, if it's well defined.
+    fn minimum(&self) -> Option<DVec3> {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, _] = self.0;
+        let m = DMat3::from_cols_array(&[a2, ab, ac, ab, b2, bc, ac, bc, c2]);
+        if m.determinant().abs() < 1e-9 {
+            return None;
+        }
+        Some(m.inverse() * -DVec3::new(ad, bd, cd))
+    }
+}
+
+/// Candidate edge collapse, ordered by increasing cost in a max-heap.
+#[derive(Debug, Clone, Copy)]
+struct Collapse {
+    cost: f64,
+    u: u32,
+    v: u32,
+    /// Versions of the two vertices when the collapse was evaluated.
+    versions: [u32; 2],
+    pos: DVec3,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Decimator {
+    positions: Vec<DVec3>,
+    quadrics: Vec<Quadric>,
+    /// Triangles using each vertex, possibly including removed ones.
+    faces: Vec<Vec<u32>>,
+    triangles: Vec<[u32; 3]>,
+    removed: Vec<bool>,
+    /// Vertices on a boundary or non-manifold edge, which are never moved.
+    locked: Vec<bool>,
+    /// Version of each vertex, incremented each time it changes, or
+    /// `u32::MAX` once removed.
+    versions: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl Decimator {
+    fn new(mesh: &Mesh) -> Self {
+        let n = mesh.positions.len();
+        let positions: Vec<DVec3> = mesh.positions.iter().map(|p| p.as_dvec3()).collect();
+        let triangles: Vec<[u32; 3]> = mesh.triangles().collect();
+        let mut quadrics = vec![Quadric::default(); n];
+        let mut faces = vec![vec![]; n];
+        let mut edges = std::collections::HashMap::new();
+        for (t, tri) in triangles.iter().enumerate() {
+            let [a, b, c] = tri.map(|i| positions[i as usize]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            let q = Quadric::plane(normal, -normal.dot(a));
+            for (k, i) in tri.iter().enumerate() {
+                quadrics[*i as usize].add(&q);
+                faces[*i as usize].push(t as u32);
+                let j = tri[(k + 1) % 3];
+                *edges.entry((*i.min(&j), *i.max(&j))).or_insert(0) += 1;
+            }
+        }
+        let mut locked = vec![false; n];
+        for ((u, v), count) in &edges {
+            if *count != 2 {
+                locked[*u as usize] = true;
+                locked[*v as usize] = true;
+            }
+        }
+
+        let mut decimator = Self {
+            positions,
+            quadrics,
+            faces,
+            removed: vec![false; triangles.len()],
+            triangles,
+            locked,
+            versions: vec![0; n],
+            heap: BinaryHeap::new(),
+        };
+        // Sort the edges so that collapses of equal cost are done in a
+        // deterministic order
+        let mut edges: Vec<(u32, u32)> = edges.into_keys().collect();
+        edges.sort_unstable();
+        for (u, v) in edges {
+            decimator.push(u, v);
+        }
+        decimator
+    }
+
+    /// Evaluate the collapse of an edge, and push it into the heap.
+    fn push(&mut self, u: u32, v: u32) {
+        if self.locked[u as usize] || self.locked[v as usize] {
+            return;
+        }
+        let mut q = self.quadrics[u as usize];
+        q.add(&self.quadrics[v as usize]);
+        let (pu, pv) = (self.positions[u as usize], self.positions[v as usize]);
+        let mid = (pu + pv) / 2.;
+
+        // Only use the optimal point if it stays close to the edge, otherwise
+        // the best of the end points and the middle
+        let (cost, pos) = q
+            .minimum()
+            .filter(|p| p.distance(mid) <= pu.distance(pv))
+            .map(|p| (q.error(p), p))
+            .unwrap_or_else(|| {
+                [pu, pv, mid]
+                    .into_iter()
+                    .map(|p| (q.error(p), p))
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .unwrap()
+            });
+        self.heap.push(Collapse {
+            cost,
+            u,
+            v,
+            versions: [self.versions[u as usize], self.versions[v as usize]],
+            pos,
+        });
+    }
+
+    /// Get the vertices adjacent to a vertex.
+    fn neighbors(&self, u: u32) -> Vec<u32> {
+        let mut list: Vec<u32> = self.faces[u as usize]
+            .iter()
+            .filter(|t| !self.removed[**t as usize])
+            .flat_map(|t| self.triangles[*t as usize])
+            .filter(|i| *i != u)
+            .collect();
+        list.sort_unstable();
+        list.dedup();
+        list
+    }
+
+    /// Check that collapsing the edge `uv` into `pos` keeps the mesh manifold,
+    /// and doesn't flip or fold any triangle.
+    fn can_collapse(&self, u: u32, v: u32, pos: DVec3) -> bool {
+        // Link condition: the only common neighbors of U and V are the
+        // opposite vertices of the two triangles sharing the edge
+        let nu = self.neighbors(u);
+        let common = self.neighbors(v).iter().filter(|i| nu.contains(i)).count();
+        if common != 2 {
+            return false;
+        }
+
+        for w in [u, v] {
+            for t in &self.faces[w as usize] {
+                let tri = self.triangles[*t as usize];
+                if self.removed[*t as usize] || (tri.contains(&u) && tri.contains(&v)) {
+                    continue;
+                }
+                let [a, b, c] = tri.map(|i| self.positions[i as usize]);
+                let before = (b - a).cross(c - a);
+                let [a, b, c] = tri.map(|i| {
+                    if i == w {
+                        pos
+                    } else {
+                        self.positions[i as usize]
+                    }
+                });
+                let after = (b - a).cross(c - a);
+                // Also reject nearly flipped triangles, which fold the surface
+                if after.normalize_or_zero().dot(before.normalize_or_zero()) < MIN_NORMAL_DOT {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Collapse V into U, moved to `pos`, and get the number of removed
+    /// triangles.
+    fn collapse(&mut self, u: u32, v: u32, pos: DVec3) -> usize {
+        let mut count = 0;
+        for t in std::mem::take(&mut self.faces[v as usize]) {
+            if self.removed[t as usize] {
+                continue;
+            }
+            let tri = &mut self.triangles[t as usize];
+            if tri.contains(&u) {
+                self.removed[t as usize] = true;
+                count += 1;
+            } else {
+                for i in tri.iter_mut() {
+                    if *i == v {
+                        *i = u;
+                    }
+                }
+                self.faces[u as usize].push(t);
+            }
+        }
+        self.faces[u as usize].retain(|t| !self.removed[*t as usize]);
+        self.positions[u as usize] = pos;
+        let q = self.quadrics[v as usize];
+        self.quadrics[u as usize].add(&q);
+        self.versions[u as usize] += 1;
+        self.versions[v as usize] = u32::MAX;
+        for w in self.neighbors(u) {
+            self.push(u, w);
+        }
+        count
+    }
+}
+
+impl Mesh {
+    /// Decimate the mesh by collapsing edges in order of increasing quadric
+    /// error, then recompute smooth normals.
+    ///
+    /// The mesh must be welded, since edges are only collapsed between shared
+    /// vertices. Vertices on the boundary of open meshes, or on non-manifold
+    /// edges, are kept in place. Collapses which would flip a triangle, fold it
+    /// by rotating its normal by more than 45 degrees, or make the mesh
+    /// non-manifold are skipped, so the target triangle count may not be
+    /// reached. Each remaining vertex keeps its other attributes.
+    ///
+    /// ```
+    /// # use cytogon::{DecimateOptions, Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 8);
+    /// grid.fill(true);
+    ///
+    /// let mut mesh = grid.marching_cubes(&MeshOptions::default());
+    /// let before = mesh.triangle_count();
+    /// // Merge the coplanar triangles of the sides of the box
+    /// mesh.decimate(&DecimateOptions::default());
+    /// assert!(mesh.triangle_count() < before / 4);
+    /// ```
+    pub fn decimate(&mut self, options: &DecimateOptions) {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("decimate").entered();
+
+        let mut decimator = Decimator::new(self);
+        let mut count = self.triangle_count();
+        let max_error = options.max_error as f64;
+        while count > options.target_triangles {
+            let Some(Collapse {
+                cost,
+                u,
+                v,
+                versions,
+                pos,
+            }) = decimator.heap.pop()
+            else {
+                break;
+            };
+            if versions
+                != [
+                    decimator.versions[u as usize],
+                    decimator.versions[v as usize],
+                ]
+            {
+                continue;
+            }
+            if cost > max_error {
+                break;
+            }
+            if decimator.can_collapse(u, v, pos) {
+                count -= decimator.collapse(u, v, pos);
+            }
+        }
+
+        // Compact the remaining vertices and triangles
+        let mut remap = vec![u32::MAX; self.positions.len()];
+        let mut source = vec![];
+        let mut indices = Vec::with_capacity(count * 3);
+        for (t, tri) in decimator.triangles.iter().enumerate() {
+            if decimator.removed[t] {
+                continue;
+            }
+            for i in tri {
+                if remap[*i as usize] == u32::MAX {
+                    remap[*i as usize] = source.len() as u32;
+                    source.push(*i);
+                }
+                indices.push(remap[*i as usize]);
+            }
+        }
+        self.copy_attributes(&source);
+        self.positions = source
+            .iter()
+            .map(|i| decimator.positions[*i as usize].as_vec3())
+            .collect();
+        self.indices = indices;
+        self.compute_normals();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::*;
+    use crate::{Grid3, MeshOptions};
+
+    fn ball(radius: f32) -> Mesh {
+        let mut grid = Grid3::new(UVec3::splat(20));
+        grid.fill(false);
+        let center = Vec3::splat(10.);
+        for z in 0..20 {
+            for y in 0..20 {
+                for x in 0..20 {
+                    let pos = IVec3::new(x, y, z);
+                    if (pos.as_vec3() + 0.5).distance(center) < radius {
+                        grid.set_cell(pos, true);
+                    }
+                }
+            }
+        }
+        grid.marching_cubes(&MeshOptions::default())
+    }
+
+    #[test]
+    fn lossless() {
+        let mut grid = Grid3::new(UVec3::new(9, 6, 13));
+        grid.fill(true);
+        let mut mesh = grid.marching_cubes(&MeshOptions::default());
+        let volume = mesh.volume();
+        let before = mesh.triangle_count();
+        mesh.decimate(&DecimateOptions::default());
+        assert!(mesh.triangle_count() < before / 4);
+        assert!(mesh.is_closed());
+        assert!((mesh.volume() - volume).abs() < volume * 1e-5);
+        assert_eq!(mesh.normals.len(), mesh.vertex_count());
+    }
+
+    #[test]
+    fn target() {
+        let mesh = ball(8.);
+        let center = Vec3::splat(10.);
+        let volume = mesh.volume();
+        let before = mesh.triangle_count();
+
+        let mut lod = mesh.clone();
+        lod.decimate(&DecimateOptions {
+            target_triangles: before / 4,
+            max_error: f32::INFINITY,
+        });
+        assert!(lod.triangle_count() <= before / 4);
+        assert!(lod.is_closed());
+        assert!((lod.volume() - volume).abs() < volume * 0.05);
+        // No triangle is flipped inward
+        for [a, b, c] in lod.triangles() {
+            let [a, b, c] = [a, b, c].map(|i| lod.positions[i as usize]);
+            let n = (b - a).cross(c - a);
+            assert!(n.dot((a + b + c) / 3. - center) > 0.);
+        }
+
+        // A small error threshold stops earlier
+        let mut fine = mesh.clone();
+        fine.decimate(&DecimateOptions {
+            target_triangles: before / 4,
+            max_error: 0.01,
+        });
+        assert!(fine.triangle_count() > lod.triangle_count());
+        assert!(fine.triangle_count() < before);
+        assert!(fine.is_closed());
+    }
+
+    #[test]
+    fn boundary() {
+        let boundary = |mesh: &Mesh| {
+            let mut edges = std::collections::HashMap::new();
+            for [a, b, c] in mesh.triangles() {
+                for (u, v) in [(a, b), (b, c), (c, a)] {
+                    *edges.entry((u.min(v), u.max(v))).or_insert(0) += 1;
+                }
+            }
+            let mut points: Vec<[u32; 3]> = edges
+                .iter()
+                .filter(|(_, n)| **n == 1)
+                .flat_map(|((u, v), _)| [*u, *v])
+                .map(|i| mesh.positions[i as usize].to_array().map(f32::to_bits))
+                .collect();
+            points.sort_unstable();
+            points.dedup();
+            points
+        };
+
+        // Open a hole in the ball
+        let mut mesh = ball(6.);
+        mesh.indices.drain(..3 * 20);
+        let before = boundary(&mesh);
+        assert!(!before.is_empty());
+        let count = mesh.triangle_count();
+        mesh.decimate(&DecimateOptions {
+            target_triangles: 0,
+            max_error: f32::INFINITY,
+        });
+        assert!(mesh.triangle_count() < count / 4);
+        assert_eq!(boundary(&mesh), before);
+    }
+}
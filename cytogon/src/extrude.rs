@@ -0,0 +1,277 @@
+//! Extrusion of the contours of [`Grid2`] into 3D meshes.
+//!
+//! The 2D plane of the contours is the XY plane of the mesh, and the walls are
+//! extruded along +Z, from Z=0 up to the height of the walls.
+
+use glam::{Vec2, Vec3};
+
+use crate::{Contour, ContourOptions, Grid2, Mesh, Polygon};
+
+/// Options controlling the extrusion of 2D polygons into walls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtrudeOptions {
+    /// Options of the contours extracted from a [`Grid2`].
+    pub contour: ContourOptions,
+    /// Height of the walls, in mesh units.
+    pub height: f32,
+    /// Close the bottom of the walls with a floor, facing -Z.
+    pub floor: bool,
+    /// Close the top of the walls with a ceiling, facing +Z.
+    pub ceiling: bool,
+    /// Size of the 45 degree bevel between the walls and the ceiling, in mesh
+    /// units, or zero for no bevel.
+    ///
+    /// The top of the walls is moved down, and the ceiling is inset into the
+    /// polygons by this size. Bevels larger than the thinnest parts of the
+    /// polygons make the mesh self-intersect.
+    pub bevel: f32,
+}
+
+impl Default for ExtrudeOptions {
+    fn default() -> Self {
+        Self {
+            contour: ContourOptions::default(),
+            height: 1.,
+            floor: false,
+            ceiling: true,
+            bevel: 0.,
+        }
+    }
+}
+
+impl Mesh {
+    /// Extrude 2D polygons into walls, with optional floor, ceiling and bevel.
+    ///
+    /// Each wall quad has its own vertices, and each cap shares the vertices of
+    /// its triangles, with flat normals pointing out of the polygons. With both
+    /// a floor and a ceiling, the mesh encloses the polygons. The [`contour`]
+    /// options are ignored.
+    ///
+    /// [`contour`]: ExtrudeOptions::contour
+    pub fn extrude(polygons: &[Polygon], options: &ExtrudeOptions) -> Mesh {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("extrude").entered();
+
+        let mut mesh = Mesh::default();
+        let bevel = options.bevel.clamp(0., options.height);
+        let top = options.height - bevel;
+        for polygon in polygons {
+            let contours = || std::iter::once(&polygon.outer).chain(&polygon.holes);
+            for contour in contours() {
+                push_band(&mut mesh, &contour.points, 0., &contour.points, top);
+            }
+
+            if options.floor {
+                push_cap(&mut mesh, polygon, 0., -Vec3::Z);
+            }
+
+            if bevel > 0. {
+                let inset = Polygon {
+                    outer: inset(&polygon.outer, bevel),
+                    holes: polygon.holes.iter().map(|h| inset(h, bevel)).collect(),
+                };
+                for (contour, inset) in
+                    contours().zip(std::iter::once(&inset.outer).chain(&inset.holes))
+                {
+                    push_band(
+                        &mut mesh,
+                        &contour.points,
+                        top,
+                        &inset.points,
+                        options.height,
+                    );
+                }
+                if options.ceiling {
+                    push_cap(&mut mesh, &inset, options.height, Vec3::Z);
+                }
+            } else if options.ceiling {
+                push_cap(&mut mesh, polygon, options.height, Vec3::Z);
+            }
+        }
+        mesh
+    }
+}
+
+impl Grid2 {
+    /// Extrude the polygons of the alive cells into a 3D mesh of walls.
+    ///
+    /// See [`polygons()`] and [`Mesh::extrude()`] for details.
+    ///
+    /// ```
+    /// # use cytogon::{ContourOptions, ExtrudeOptions, Grid2, UVec2};
+    /// let mut grid = Grid2::new(UVec2::new(8, 8));
+    /// grid.fill(true);
+    ///
+    /// let options = ExtrudeOptions {
+    ///     contour: ContourOptions {
+    ///         simplify: Some(0.),
+    ///         ..Default::default()
+    ///     },
+    ///     height: 3.,
+    ///     floor: true,
+    ///     ..Default::default()
+    /// };
+    /// let mesh = grid.extrude(&options);
+    /// // 8 sides, and 6 triangles per octagonal cap
+    /// assert_eq!(mesh.triangle_count(), 8 * 2 + 6 * 2);
+    /// ```
+    ///
+    /// [`polygons()`]: Self::polygons
+    pub fn extrude(&self, options: &ExtrudeOptions) -> Mesh {
+        Mesh::extrude(&self.polygons(&options.contour), options)
+    }
+}
+
+/// Offset a contour toward its left side, that is into the alive cells, by
+/// moving each point along the bisector of its two edges.
+fn inset(contour: &Contour, distance: f32) -> Contour {
+    let points = &contour.points;
+    let n = points.len();
+    let points = (0..n)
+        .map(|i| {
+            let p = points[i];
+            let l0 = (p - points[(i + n - 1) % n]).normalize_or_zero().perp();
+            let l1 = (points[(i + 1) % n] - p).normalize_or_zero().perp();
+            let m = (l0 + l1).normalize_or_zero();
+            // Limit the offset at sharp corners
+            p + m * distance / m.dot(l0).max(0.5)
+        })
+        .collect();
+    Contour { points }
+}
+
+/// Push one quad for each edge of a contour, joining the contour `bottom` at
+/// height `z0` to the contour `top` with the same number of points at height
+/// `z1`.
+fn push_band(mesh: &mut Mesh, bottom: &[Vec2], z0: f32, top: &[Vec2], z1: f32) {
+    let n = bottom.len();
+    for i in 0..n {
+        let j = (i + 1) % n;
+        // The polygons are on the left side of the contours, so the quad is
+        // counter-clockwise seen from the right side
+        let quad = [
+            bottom[i].extend(z0),
+            bottom[j].extend(z0),
+            top[j].extend(z1),
+            top[i].extend(z1),
+        ];
+        let normal = (quad[1] - quad[0])
+            .cross(quad[3] - quad[0])
+            .normalize_or_zero();
+        if normal == Vec3::ZERO {
+            continue;
+        }
+        let base = mesh.positions.len() as u32;
+        mesh.positions.extend_from_slice(&quad);
+        mesh.normals.extend_from_slice(&[normal; 4]);
+        mesh.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
+
+/// Push the triangulation of a polygon at height `z`, facing +Z or -Z
+/// depending on `normal`.
+fn push_cap(mesh: &mut Mesh, polygon: &Polygon, z: f32, normal: Vec3) {
+    let base = mesh.positions.len() as u32;
+    mesh.positions.extend(polygon.points().map(|p| p.extend(z)));
+    mesh.normals.resize(mesh.positions.len(), normal);
+    for [a, b, c] in polygon.triangulate() {
+        let tri = if normal.z < 0. { [a, c, b] } else { [a, b, c] };
+        mesh.indices.extend(tri.map(|i| base + i));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec2, UVec2};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn closed_solid() {
+        let mut grid = Grid2::new(UVec2::new(29, 23));
+        grid.fill_rand(0.6, StdRng::seed_from_u64(42));
+        for interpolate in [false, true] {
+            let options = ExtrudeOptions {
+                contour: ContourOptions {
+                    cell_size: Vec2::new(0.5, 2.),
+                    interpolate,
+                    ..Default::default()
+                },
+                height: 3.,
+                floor: true,
+                ceiling: true,
+                bevel: 0.,
+            };
+            let polygons = grid.polygons(&options.contour);
+            let area: f32 = polygons
+                .iter()
+                .flat_map(|p| std::iter::once(&p.outer).chain(&p.holes))
+                .map(|c| c.signed_area())
+                .sum();
+            let mesh = Mesh::extrude(&polygons, &options);
+            assert_eq!(mesh, grid.extrude(&options));
+            assert!((mesh.volume() - area * 3.).abs() < 1e-2 * area);
+
+            // Flat normals are consistent with the triangles
+            for [a, b, c] in mesh.triangles() {
+                let [pa, pb, pc] = [a, b, c].map(|i| mesh.positions[i as usize]);
+                let n = (pb - pa).cross(pc - pa);
+                assert!(n.dot(mesh.normals[a as usize]) > 0.);
+                assert!((mesh.normals[a as usize].length() - 1.).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn bevel() {
+        // Room of 10x6 cells with a 2x2 pillar
+        let mut grid = Grid2::new(UVec2::new(12, 8));
+        grid.fill(true);
+        for y in 1..7 {
+            for x in 1..11 {
+                grid.set_cell(IVec2::new(x, y), false);
+            }
+        }
+        grid.set_cell(IVec2::new(5, 3), true);
+        grid.set_cell(IVec2::new(6, 3), true);
+        grid.set_cell(IVec2::new(5, 4), true);
+        grid.set_cell(IVec2::new(6, 4), true);
+
+        let flat = ExtrudeOptions {
+            contour: ContourOptions {
+                simplify: Some(0.),
+                ..Default::default()
+            },
+            height: 2.,
+            floor: true,
+            ..Default::default()
+        };
+        let beveled = ExtrudeOptions { bevel: 0.2, ..flat };
+        let flat_mesh = grid.extrude(&flat);
+        let mesh = grid.extrude(&beveled);
+        assert!(mesh.triangle_count() > flat_mesh.triangle_count());
+
+        // The bevel cuts some volume off the top of the walls
+        let volume = mesh.volume();
+        assert!(volume < flat_mesh.volume());
+        assert!(volume > flat_mesh.volume() - 0.2 * 0.2 * 60.);
+        assert!(mesh.positions.iter().all(|p| p.z >= 0. && p.z <= 2.));
+
+        // Bevel faces point up and out of the walls
+        let bevels = mesh.normals.iter().filter(|n| n.z > 0. && n.z < 1.);
+        assert!(bevels.clone().count() > 0);
+        for n in bevels {
+            assert!((n.z - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.2);
+        }
+
+        // Only walls, without caps
+        let walls = grid.extrude(&ExtrudeOptions {
+            ceiling: false,
+            floor: false,
+            ..flat
+        });
+        assert!(walls.normals.iter().all(|n| n.z == 0.));
+    }
+}
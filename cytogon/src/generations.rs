@@ -0,0 +1,505 @@
+//! Multi-state "Generations" cellular automata.
+//!
+//! In a Generations automaton, a cell is either dead (state `0`), alive (state
+//! `1`), or dying (states `2` to `states - 1`). Dead cells are born and alive
+//! cells survive according to the birth and survive rules, like in a 2-state
+//! automaton. However an alive cell which fails the survive test doesn't die
+//! immediately, but instead decays through all the dying states, one per
+//! generation, before finally dying. Only alive cells count as neighbors.
+
+use std::ops::RangeInclusive;
+
+use rand::{Rng, RngCore};
+#[cfg(feature = "trace")]
+use tracing::info_span;
+
+use crate::{
+    Boundary, Grid2, Grid3, IVec2, IVec3, Neighborhood, RuleBitset2, RuleBitset3, UVec2, UVec3,
+};
+
+/// Compute the next state of a single cell of a Generations automaton.
+#[inline]
+fn next_state(state: u8, states: u8, count: u8, birth: u32, survive: u32) -> u8 {
+    let b = 1u32 << count;
+    match state {
+        0 => (birth & b != 0) as u8,
+        1 => {
+            if survive & b != 0 {
+                1
+            } else if states > 2 {
+                2
+            } else {
+                0
+            }
+        }
+        s if s >= states - 1 => 0,
+        s => s + 1,
+    }
+}
+
+/// 2D Generations cellular automaton rule.
+///
+/// A rule can be parsed from a rule string like `/2/3` or `B2/S/C3` (Brian's
+/// Brain), and formatted back with [`Display`](std::fmt::Display).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationsRule2 {
+    /// Birth rule, applied to dead cells to determine if they become alive.
+    pub birth: RuleBitset2,
+    /// Survive rule, applied to alive cells to determine if they remain alive.
+    pub survive: RuleBitset2,
+    /// Total number of cell states, including the dead and alive ones. Must be
+    /// at least 2.
+    pub states: u8,
+    /// Neighborhood of each cell, used to count its neighbors.
+    pub neighborhood: Neighborhood,
+}
+
+impl GenerationsRule2 {
+    /// Brian's Brain rule /2/3.
+    pub const BRIANS_BRAIN: GenerationsRule2 = GenerationsRule2 {
+        birth: RuleBitset2::from_bits(0x4u16), // 2
+        survive: RuleBitset2::from_bits(0),
+        states: 3,
+        neighborhood: Neighborhood::Moore,
+    };
+
+    /// Create a Generations rule with a Moore neighborhood from a pair of birth
+    /// and survive rules and a number of states.
+    pub fn new(birth: impl Into<RuleBitset2>, survive: impl Into<RuleBitset2>, states: u8) -> Self {
+        assert!(states >= 2);
+        Self {
+            birth: birth.into(),
+            survive: survive.into(),
+            states,
+            neighborhood: Neighborhood::Moore,
+        }
+    }
+
+    /// Change the neighborhood of the rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the birth or survive rules contain a neighbor count larger
+    /// than the maximum number of neighbors in the new neighborhood.
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.neighborhood = neighborhood;
+        assert!(
+            self.is_valid(),
+            "Rule neighbor count exceeds the maximum of {} neighbors.",
+            neighborhood.max_count2()
+        );
+        self
+    }
+
+    /// Check that the birth and survive rules only contain neighbor counts
+    /// possible in the [`neighborhood`] of the rule.
+    ///
+    /// Rules changed with [`with_neighborhood()`] are always valid, but the
+    /// check is bypassed when setting the fields directly.
+    ///
+    /// [`neighborhood`]: Self::neighborhood
+    /// [`with_neighborhood()`]: Self::with_neighborhood
+    pub fn is_valid(&self) -> bool {
+        (self.birth.to_bits() as u32 | self.survive.to_bits() as u32)
+            >> (self.neighborhood.max_count2() + 1)
+            == 0
+    }
+}
+
+/// 2D Generations cellular automaton grid.
+///
+/// Each cell in the grid is encoded as a byte storing its state.
+#[derive(Clone)]
+pub struct GenerationsGrid2 {
+    /// Grid size, in number of cells.
+    pub size: UVec2,
+    /// Boundary condition applied to the edges of the grid when applying a
+    /// rule.
+    pub boundary: Boundary,
+    /// State of all cells in the grid.
+    ///
+    /// The cells are laid out in X-major order, that is all X cells for Y=0,
+    /// then all X cells for Y=1, etc.
+    pub cells: Vec<u8>,
+}
+
+impl GenerationsGrid2 {
+    /// Create a new grid of the given size, with all cells dead.
+    pub fn new(size: UVec2) -> Self {
+        let capacity = size.x as usize * size.y as usize;
+        Self {
+            size,
+            boundary: Boundary::Dead,
+            cells: vec![0; capacity],
+        }
+    }
+
+    #[inline]
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        if pos.x < 0 || pos.y < 0 || pos.x as u32 >= self.size.x || pos.y as u32 >= self.size.y {
+            None
+        } else {
+            Some(pos.y as usize * self.size.x as usize + pos.x as usize)
+        }
+    }
+
+    /// Fill the grid with the given `state`.
+    pub fn fill(&mut self, state: u8) {
+        self.cells.fill(state);
+    }
+
+    /// Fill the grid with random alive and dead cells.
+    ///
+    /// The fill ratio determines how "full" the grid is, that is the proportion
+    /// of alive cells.
+    pub fn fill_rand(&mut self, fill_ratio: f32, mut prng: impl RngCore) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("fill_rand_gen2").entered();
+
+        for c in &mut self.cells {
+            let p: f32 = prng.gen_range(0.0..=1.0);
+            *c = (p < fill_ratio) as u8;
+        }
+    }
+
+    #[inline]
+    pub fn cell(&self, pos: IVec2) -> Option<u8> {
+        self.index(pos).map(|index| self.cells[index])
+    }
+
+    #[inline]
+    pub fn set_cell(&mut self, pos: IVec2, state: u8) {
+        if let Some(index) = self.index(pos) {
+            self.cells[index] = state;
+        }
+    }
+
+    /// Get a 2-state grid where only cells with a state in the given range are
+    /// alive.
+    ///
+    /// For example, `mask(1..=1)` returns the alive cells, while
+    /// `mask(1..=u8::MAX)` returns all alive and dying cells. The returned grid
+    /// has the same boundary condition as this grid.
+    pub fn mask(&self, states: RangeInclusive<u8>) -> Grid2 {
+        let mut grid = Grid2::new(self.size);
+        grid.boundary = self.boundary;
+        grid.fill(false);
+        for j in 0..self.size.y as i32 {
+            for i in 0..self.size.x as i32 {
+                let pos = IVec2::new(i, j);
+                if states.contains(&self.cell(pos).unwrap()) {
+                    grid.set_cell(pos, true);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Apply the given Generations rule once to the entire grid.
+    ///
+    /// The rule must be [valid] for its neighborhood, which is only checked in
+    /// debug builds.
+    ///
+    /// [valid]: GenerationsRule2::is_valid
+    pub fn apply_rule(&mut self, rule: &GenerationsRule2) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("apply_rule_gen2").entered();
+        debug_assert!(
+            rule.is_valid(),
+            "Rule neighbor count exceeds the neighborhood."
+        );
+
+        let alive = self.mask(1..=1);
+        let counts = alive.count_neighbors(rule.neighborhood, self.boundary);
+        let birth = rule.birth.to_bits() as u32;
+        let survive = rule.survive.to_bits() as u32;
+        for j in 0..self.size.y as i32 {
+            for i in 0..self.size.x as i32 {
+                let pos = IVec2::new(i, j);
+                let index = self.index(pos).unwrap();
+                let (block, offset) = alive.resolve(pos).unwrap();
+                let count = counts[block * 64 + offset as usize];
+                self.cells[index] =
+                    next_state(self.cells[index], rule.states, count, birth, survive);
+            }
+        }
+    }
+}
+
+/// 3D Generations cellular automaton rule.
+///
+/// A rule can be parsed from a rule string like `4-7/6-8/10/M` (Pyroclastic),
+/// and formatted back with [`Display`](std::fmt::Display).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationsRule3 {
+    /// Birth rule, applied to dead cells to determine if they become alive.
+    pub birth: RuleBitset3,
+    /// Survive rule, applied to alive cells to determine if they remain alive.
+    pub survive: RuleBitset3,
+    /// Total number of cell states, including the dead and alive ones. Must be
+    /// at least 2.
+    pub states: u8,
+    /// Neighborhood of each cell, used to count its neighbors.
+    pub neighborhood: Neighborhood,
+}
+
+impl GenerationsRule3 {
+    /// Pyroclastic rule S4-7/B6-8/10/M.
+    pub const PYROCLASTIC: GenerationsRule3 = GenerationsRule3 {
+        birth: RuleBitset3::from_bits(0x1C0u32),  // 6..=8
+        survive: RuleBitset3::from_bits(0xF0u32), // 4..=7
+        states: 10,
+        neighborhood: Neighborhood::Moore,
+    };
+
+    /// Clouds rule S13-26/B13-14,17-19/2/M, the Generations equivalent of
+    /// [`Rule3::SMOOTH`](crate::Rule3::SMOOTH).
+    pub const CLOUDS: GenerationsRule3 = GenerationsRule3 {
+        birth: RuleBitset3::from_bits(0xE_6000u32), // 13..=14, 17..=19
+        survive: RuleBitset3::from_bits(0x7FF_E000u32), // 13..=26
+        states: 2,
+        neighborhood: Neighborhood::Moore,
+    };
+
+    /// Create a Generations rule with a Moore neighborhood from a pair of birth
+    /// and survive rules and a number of states.
+    pub fn new(birth: impl Into<RuleBitset3>, survive: impl Into<RuleBitset3>, states: u8) -> Self {
+        assert!(states >= 2);
+        Self {
+            birth: birth.into(),
+            survive: survive.into(),
+            states,
+            neighborhood: Neighborhood::Moore,
+        }
+    }
+
+    /// Change the neighborhood of the rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the birth or survive rules contain a neighbor count larger
+    /// than the maximum number of neighbors in the new neighborhood.
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.neighborhood = neighborhood;
+        assert!(
+            self.is_valid(),
+            "Rule neighbor count exceeds the maximum of {} neighbors.",
+            neighborhood.max_count3()
+        );
+        self
+    }
+
+    /// Check that the birth and survive rules only contain neighbor counts
+    /// possible in the [`neighborhood`] of the rule.
+    ///
+    /// Rules changed with [`with_neighborhood()`] are always valid, but the
+    /// check is bypassed when setting the fields directly.
+    ///
+    /// [`neighborhood`]: Self::neighborhood
+    /// [`with_neighborhood()`]: Self::with_neighborhood
+    pub fn is_valid(&self) -> bool {
+        (self.birth.to_bits() | self.survive.to_bits()) >> (self.neighborhood.max_count3() + 1) == 0
+    }
+}
+
+/// 3D Generations cellular automaton grid.
+///
+/// Each cell in the grid is encoded as a byte storing its state.
+#[derive(Clone)]
+pub struct GenerationsGrid3 {
+    /// Grid size, in number of cells.
+    pub size: UVec3,
+    /// Boundary condition applied to the edges of the grid when applying a
+    /// rule.
+    pub boundary: Boundary,
+    /// State of all cells in the grid.
+    ///
+    /// The cells are laid out in X-major and Z-minor order, that is all X cells
+    /// for Y=Z=0, then all X cells for Z=0 and Y=1, etc.
+    pub cells: Vec<u8>,
+}
+
+impl GenerationsGrid3 {
+    /// Create a new grid of the given size, with all cells dead.
+    pub fn new(size: UVec3) -> Self {
+        let capacity = size.x as usize * size.y as usize * size.z as usize;
+        Self {
+            size,
+            boundary: Boundary::Dead,
+            cells: vec![0; capacity],
+        }
+    }
+
+    #[inline]
+    fn index(&self, pos: IVec3) -> Option<usize> {
+        if pos.x < 0
+            || pos.y < 0
+            || pos.z < 0
+            || pos.x as u32 >= self.size.x
+            || pos.y as u32 >= self.size.y
+            || pos.z as u32 >= self.size.z
+        {
+            None
+        } else {
+            let (sx, sy) = (self.size.x as usize, self.size.y as usize);
+            Some((pos.z as usize * sy + pos.y as usize) * sx + pos.x as usize)
+        }
+    }
+
+    /// Fill the grid with the given `state`.
+    pub fn fill(&mut self, state: u8) {
+        self.cells.fill(state);
+    }
+
+    /// Fill the grid with random alive and dead cells.
+    ///
+    /// The fill ratio determines how "full" the grid is, that is the proportion
+    /// of alive cells.
+    pub fn fill_rand(&mut self, fill_ratio: f32, mut prng: impl RngCore) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("fill_rand_gen3").entered();
+
+        for c in &mut self.cells {
+            let p: f32 = prng.gen_range(0.0..=1.0);
+            *c = (p < fill_ratio) as u8;
+        }
+    }
+
+    #[inline]
+    pub fn cell(&self, pos: IVec3) -> Option<u8> {
+        self.index(pos).map(|index| self.cells[index])
+    }
+
+    #[inline]
+    pub fn set_cell(&mut self, pos: IVec3, state: u8) {
+        if let Some(index) = self.index(pos) {
+            self.cells[index] = state;
+        }
+    }
+
+    /// Get a 2-state grid where only cells with a state in the given range are
+    /// alive.
+    ///
+    /// For example, `mask(1..=1)` returns the alive cells, while
+    /// `mask(1..=u8::MAX)` returns all alive and dying cells. The returned grid
+    /// has the same boundary condition as this grid.
+    pub fn mask(&self, states: RangeInclusive<u8>) -> Grid3 {
+        let mut grid = Grid3::new(self.size);
+        grid.boundary = self.boundary;
+        grid.fill(false);
+        let mut index = 0;
+        for k in 0..self.size.z as i32 {
+            for j in 0..self.size.y as i32 {
+                for i in 0..self.size.x as i32 {
+                    if states.contains(&self.cells[index]) {
+                        grid.set_cell(IVec3::new(i, j, k), true);
+                    }
+                    index += 1;
+                }
+            }
+        }
+        grid
+    }
+
+    /// Apply the given Generations rule once to the entire grid.
+    ///
+    /// The rule must be [valid] for its neighborhood, which is only checked in
+    /// debug builds.
+    ///
+    /// [valid]: GenerationsRule3::is_valid
+    pub fn apply_rule(&mut self, rule: &GenerationsRule3) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("apply_rule_gen3").entered();
+        debug_assert!(
+            rule.is_valid(),
+            "Rule neighbor count exceeds the neighborhood."
+        );
+
+        let alive = self.mask(1..=1);
+        let counts = alive.count_neighbors(rule.neighborhood, self.boundary);
+        let birth = rule.birth.to_bits();
+        let survive = rule.survive.to_bits();
+        let mut index = 0;
+        for k in 0..self.size.z as i32 {
+            for j in 0..self.size.y as i32 {
+                for i in 0..self.size.x as i32 {
+                    let (block, offset) = alive.resolve(IVec3::new(i, j, k)).unwrap();
+                    let count = counts[block * 64 + offset as usize];
+                    self.cells[index] =
+                        next_state(self.cells[index], rule.states, count, birth, survive);
+                    index += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_state_decay() {
+        // Alive cell failing survive decays through dying states
+        assert_eq!(next_state(1, 4, 0, 0, 0), 2);
+        assert_eq!(next_state(2, 4, 0, !0, !0), 3);
+        assert_eq!(next_state(3, 4, 0, !0, !0), 0);
+        // Dying cells never get born again nor survive
+        assert_eq!(next_state(2, 4, 5, !0, !0), 3);
+        // 2 states behaves like a life-like rule
+        assert_eq!(next_state(1, 2, 0, 0, 0), 0);
+        assert_eq!(next_state(0, 2, 3, 1 << 3, 0), 1);
+        assert_eq!(next_state(1, 2, 3, 0, 1 << 3), 1);
+    }
+
+    #[test]
+    fn brians_brain() {
+        // 2x1 pair of alive cells, which in Brian's Brain moves sideways
+        let mut grid = GenerationsGrid2::new(UVec2::new(6, 6));
+        grid.set_cell(IVec2::new(2, 2), 1);
+        grid.set_cell(IVec2::new(2, 3), 1);
+
+        grid.apply_rule(&GenerationsRule2::BRIANS_BRAIN);
+        assert_eq!(grid.cell(IVec2::new(2, 2)), Some(2));
+        assert_eq!(grid.cell(IVec2::new(2, 3)), Some(2));
+        for j in 1..=4 {
+            for i in [1, 3] {
+                let expected = (j == 2 || j == 3) as u8;
+                assert_eq!(grid.cell(IVec2::new(i, j)), Some(expected));
+            }
+        }
+
+        grid.apply_rule(&GenerationsRule2::BRIANS_BRAIN);
+        assert_eq!(grid.cell(IVec2::new(2, 2)), Some(0));
+        assert_eq!(grid.cell(IVec2::new(2, 3)), Some(0));
+        assert_eq!(grid.cell(IVec2::new(1, 2)), Some(2));
+        assert_eq!(grid.cell(IVec2::new(3, 3)), Some(2));
+    }
+
+    #[test]
+    fn clouds_matches_rule3() {
+        let size = UVec3::ONE * 8;
+        let mut grid = Grid3::new(size);
+        grid.fill(true);
+        let mut gen = GenerationsGrid3::new(size);
+        gen.fill(1);
+
+        grid.apply_rule(&crate::Rule3::SMOOTH);
+        gen.apply_rule(&GenerationsRule3::CLOUDS);
+        assert_eq!(gen.mask(1..=1).data, grid.data);
+    }
+
+    #[test]
+    fn decay3() {
+        // Single alive cell has no neighbor, so doesn't survive and decays
+        let mut gen = GenerationsGrid3::new(UVec3::ONE * 4);
+        gen.set_cell(IVec3::ONE, 1);
+        let rule = GenerationsRule3::new(27u8..27u8, 1u8..=26u8, 4);
+        for state in [2, 3, 0] {
+            gen.apply_rule(&rule);
+            assert_eq!(gen.cell(IVec3::ONE), Some(state));
+        }
+        assert!(gen.cells.iter().all(|&c| c == 0));
+        assert_eq!(gen.mask(1..=3).data, vec![0u64]);
+    }
+}
@@ -0,0 +1,483 @@
+//! Export of [`Mesh`]es to the glTF 2.0 format, either as a JSON `.gltf` file
+//! with a separate `.bin` buffer, or as a single binary `.glb` file.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use glam::Vec3;
+
+use crate::Mesh;
+
+/// Buffer view target of vertex attributes.
+const ARRAY_BUFFER: u32 = 34962;
+/// Buffer view target of vertex indices.
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+/// Accessor component type of `f32` values.
+const FLOAT: u32 = 5126;
+/// Accessor component type of `u32` values.
+const UNSIGNED_INT: u32 = 5125;
+
+/// Magic number at the start of GLB files, `glTF` in ASCII.
+const GLB_MAGIC: u32 = 0x4654_6C67;
+/// Type of the GLB chunk holding the JSON document, `JSON` in ASCII.
+const GLB_JSON: u32 = 0x4E4F_534A;
+/// Type of the GLB chunk holding the binary buffer, `BIN\0` in ASCII.
+const GLB_BIN: u32 = 0x004E_4942;
+
+/// Options controlling the export of meshes to glTF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GltfOptions {
+    /// Write the texture coordinates of the meshes, if any.
+    pub uvs: bool,
+    /// Write the vertex colors of the meshes, if any.
+    pub colors: bool,
+}
+
+impl Default for GltfOptions {
+    fn default() -> Self {
+        Self {
+            uvs: true,
+            colors: true,
+        }
+    }
+}
+
+/// Scene made of several named meshes, each in its own node.
+///
+/// This allows exporting for example one mesh per chunk or per region of a
+/// grid into a single glTF file.
+///
+/// ```
+/// # use cytogon::{GltfOptions, GltfScene, Grid3, MeshOptions, UVec3};
+/// let mut grid = Grid3::new(UVec3::ONE * 4);
+/// grid.fill(true);
+/// let floor = grid.greedy_mesh(&MeshOptions::default());
+/// let wall = grid.marching_cubes(&MeshOptions::default());
+///
+/// let mut scene = GltfScene::new();
+/// scene.add_mesh("floor", &floor).add_mesh("wall", &wall);
+/// let mut glb = vec![];
+/// scene.write_glb(&mut glb, &GltfOptions::default()).unwrap();
+/// assert_eq!(&glb[..4], b"glTF");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct GltfScene<'a> {
+    nodes: Vec<(String, &'a Mesh)>,
+}
+
+impl<'a> GltfScene<'a> {
+    /// Create an empty scene.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a mesh in a new node with the given name.
+    pub fn add_mesh(&mut self, name: impl Into<String>, mesh: &'a Mesh) -> &mut Self {
+        self.nodes.push((name.into(), mesh));
+        self
+    }
+
+    /// Write the scene as a binary GLB file, embedding its buffer.
+    pub fn write_glb<W: Write>(&self, writer: W, options: &GltfOptions) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write_glb").entered();
+
+        let (json, bin) = self.build(options, None);
+        let json_len = json.len().next_multiple_of(4);
+        let bin_len = bin.len().next_multiple_of(4);
+        let mut len = 12 + 8 + json_len;
+        if !bin.is_empty() {
+            len += 8 + bin_len;
+        }
+        let len = u32::try_from(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "GLB file too large"))?;
+
+        let mut w = BufWriter::new(writer);
+        w.write_all(&GLB_MAGIC.to_le_bytes())?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&len.to_le_bytes())?;
+        // The JSON chunk is padded with spaces, and the binary one with zeros
+        w.write_all(&(json_len as u32).to_le_bytes())?;
+        w.write_all(&GLB_JSON.to_le_bytes())?;
+        w.write_all(json.as_bytes())?;
+        w.write_all(&b"   "[..json_len - json.len()])?;
+        if !bin.is_empty() {
+            w.write_all(&(bin_len as u32).to_le_bytes())?;
+            w.write_all(&GLB_BIN.to_le_bytes())?;
+            w.write_all(&bin)?;
+            w.write_all(&[0; 3][..bin_len - bin.len()])?;
+        }
+        w.flush()
+    }
+
+    /// Write the scene as a JSON glTF document to `json`, and its buffer to
+    /// `bin`. The document references the buffer by `uri`, relative to the
+    /// document itself.
+    pub fn write_gltf<J: Write, B: Write>(
+        &self,
+        mut json: J,
+        mut bin: B,
+        uri: &str,
+        options: &GltfOptions,
+    ) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write_gltf").entered();
+
+        let (document, buffer) = self.build(options, Some(uri));
+        json.write_all(document.as_bytes())?;
+        json.flush()?;
+        bin.write_all(&buffer)?;
+        bin.flush()
+    }
+
+    /// Save the scene to a GLB file.
+    ///
+    /// See [`write_glb()`] for details.
+    ///
+    /// [`write_glb()`]: Self::write_glb
+    pub fn save_glb(&self, path: impl AsRef<Path>, options: &GltfOptions) -> io::Result<()> {
+        self.write_glb(File::create(path)?, options)
+    }
+
+    /// Save the scene to a glTF file, and its buffer to a `.bin` file with the
+    /// same name next to it.
+    ///
+    /// See [`write_gltf()`] for details.
+    ///
+    /// [`write_gltf()`]: Self::write_gltf
+    pub fn save_gltf(&self, path: impl AsRef<Path>, options: &GltfOptions) -> io::Result<()> {
+        let path = path.as_ref();
+        let bin_path = path.with_extension("bin");
+        let uri = bin_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid glTF path"))?;
+        self.write_gltf(File::create(path)?, File::create(&bin_path)?, uri, options)
+    }
+
+    /// Build the JSON document and the binary buffer of the scene.
+    ///
+    /// The buffer is referenced by `uri` if any, or is expected to be embedded
+    /// in a GLB file otherwise.
+    fn build(&self, options: &GltfOptions, uri: Option<&str>) -> (String, Vec<u8>) {
+        let mut bin = vec![];
+        let mut views = vec![];
+        let mut accessors = vec![];
+        let mut meshes = vec![];
+        let mut nodes = vec![];
+
+        for (name, mesh) in &self.nodes {
+            let name = json_string(name);
+            // Accessors can't be empty, so empty meshes are left out
+            if mesh.is_empty() {
+                nodes.push(format!(r#"{{"name":{name}}}"#));
+                continue;
+            }
+            let count = mesh.vertex_count();
+
+            let (min, max) = mesh
+                .positions
+                .iter()
+                .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+                    (min.min(*p), max.max(*p))
+                });
+            let view = push_view(
+                &mut bin,
+                &mut views,
+                mesh.positions.iter().flat_map(|p| p.to_array()),
+                ARRAY_BUFFER,
+            );
+            let mut attributes = format!(r#""POSITION":{}"#, accessors.len());
+            accessors.push(format!(
+                r#"{{"bufferView":{view},"componentType":{FLOAT},"count":{count},"type":"VEC3","min":{},"max":{}}}"#,
+                json_floats(&min.to_array()),
+                json_floats(&max.to_array())
+            ));
+
+            if mesh.normals.len() == count {
+                let view = push_view(
+                    &mut bin,
+                    &mut views,
+                    mesh.normals.iter().flat_map(|n| n.to_array()),
+                    ARRAY_BUFFER,
+                );
+                write!(attributes, r#","NORMAL":{}"#, accessors.len()).unwrap();
+                accessors.push(accessor(view, FLOAT, count, "VEC3"));
+            }
+            if options.uvs && mesh.uvs.len() == count {
+                // glTF texture coordinates start from the top of the image
+                let view = push_view(
+                    &mut bin,
+                    &mut views,
+                    mesh.uvs.iter().flat_map(|uv| [uv.x, 1. - uv.y]),
+                    ARRAY_BUFFER,
+                );
+                write!(attributes, r#","TEXCOORD_0":{}"#, accessors.len()).unwrap();
+                accessors.push(accessor(view, FLOAT, count, "VEC2"));
+            }
+            if options.colors && mesh.colors.len() == count {
+                let view = push_view(
+                    &mut bin,
+                    &mut views,
+                    mesh.colors.iter().flat_map(|c| c.to_array()),
+                    ARRAY_BUFFER,
+                );
+                write!(attributes, r#","COLOR_0":{}"#, accessors.len()).unwrap();
+                accessors.push(accessor(view, FLOAT, count, "VEC4"));
+            }
+
+            let offset = bin.len();
+            for i in &mesh.indices {
+                bin.extend_from_slice(&i.to_le_bytes());
+            }
+            let length = bin.len() - offset;
+            let view = views.len();
+            views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{length},"target":{ELEMENT_ARRAY_BUFFER}}}"#
+            ));
+            let indices = accessors.len();
+            accessors.push(accessor(view, UNSIGNED_INT, mesh.indices.len(), "SCALAR"));
+
+            nodes.push(format!(r#"{{"name":{name},"mesh":{}}}"#, meshes.len()));
+            meshes.push(format!(
+                r#"{{"name":{name},"primitives":[{{"attributes":{{{attributes}}},"indices":{indices},"mode":4}}]}}"#
+            ));
+        }
+
+        let mut buffers = vec![];
+        if !bin.is_empty() {
+            match uri {
+                Some(uri) => buffers.push(format!(
+                    r#"{{"byteLength":{},"uri":{}}}"#,
+                    bin.len(),
+                    json_string(uri)
+                )),
+                None => buffers.push(format!(r#"{{"byteLength":{}}}"#, bin.len())),
+            }
+        }
+        let scene_nodes: Vec<String> = (0..nodes.len()).map(|i| i.to_string()).collect();
+        let scene = if scene_nodes.is_empty() {
+            "{}".to_string()
+        } else {
+            format!(r#"{{"nodes":[{}]}}"#, scene_nodes.join(","))
+        };
+
+        let mut json = r#"{"asset":{"version":"2.0","generator":"cytogon"}"#.to_string();
+        write!(json, r#","scene":0,"scenes":[{scene}]"#).unwrap();
+        for (key, items) in [
+            ("nodes", nodes),
+            ("meshes", meshes),
+            ("accessors", accessors),
+            ("bufferViews", views),
+            ("buffers", buffers),
+        ] {
+            // Arrays must not be empty if present
+            if !items.is_empty() {
+                write!(json, r#","{key}":[{}]"#, items.join(",")).unwrap();
+            }
+        }
+        json.push('}');
+        (json, bin)
+    }
+}
+
+impl Mesh {
+    /// Write the mesh as a binary GLB file, in a single node.
+    ///
+    /// See [`GltfScene`] to export several meshes into the same file.
+    ///
+    /// ```
+    /// # use cytogon::{GltfOptions, Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    /// let mesh = grid.greedy_mesh(&MeshOptions::default());
+    ///
+    /// let mut glb = vec![];
+    /// mesh.write_glb(&mut glb, &GltfOptions::default()).unwrap();
+    /// assert_eq!(&glb[..4], b"glTF");
+    /// ```
+    pub fn write_glb<W: Write>(&self, writer: W, options: &GltfOptions) -> io::Result<()> {
+        let mut scene = GltfScene::new();
+        scene.add_mesh("mesh", self);
+        scene.write_glb(writer, options)
+    }
+
+    /// Save the mesh to a GLB file, in a single node.
+    pub fn save_glb(&self, path: impl AsRef<Path>, options: &GltfOptions) -> io::Result<()> {
+        self.write_glb(File::create(path)?, options)
+    }
+
+    /// Save the mesh to a glTF file, in a single node, and its buffer to a
+    /// `.bin` file with the same name next to it.
+    pub fn save_gltf(&self, path: impl AsRef<Path>, options: &GltfOptions) -> io::Result<()> {
+        let mut scene = GltfScene::new();
+        scene.add_mesh("mesh", self);
+        scene.save_gltf(path, options)
+    }
+}
+
+/// Append `f32` values to the buffer, in a new buffer view, and return the
+/// index of that view.
+fn push_view(
+    bin: &mut Vec<u8>,
+    views: &mut Vec<String>,
+    values: impl Iterator<Item = f32>,
+    target: u32,
+) -> usize {
+    let offset = bin.len();
+    for v in values {
+        bin.extend_from_slice(&v.to_le_bytes());
+    }
+    let length = bin.len() - offset;
+    views.push(format!(
+        r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{length},"target":{target}}}"#
+    ));
+    views.len() - 1
+}
+
+/// Format an accessor covering a whole buffer view.
+fn accessor(view: usize, component: u32, count: usize, ty: &str) -> String {
+    format!(r#"{{"bufferView":{view},"componentType":{component},"count":{count},"type":"{ty}"}}"#)
+}
+
+/// Format a JSON array of numbers.
+fn json_floats(values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Format a JSON string, escaping the characters which need to be.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec3, Vec4};
+
+    use super::*;
+    use crate::{Grid3, MeshOptions, TempDir};
+
+    /// Read the `f32` values of a buffer.
+    fn floats(bin: &[u8]) -> Vec<f32> {
+        bin.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    /// Read the `u32` values of a buffer.
+    fn uints(bin: &[u8]) -> Vec<u32> {
+        bin.chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn glb() {
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mut floor = grid.greedy_mesh(&MeshOptions::default());
+        floor.compute_box_uvs(0.5);
+        floor.colors = vec![Vec4::new(0.5, 0.25, 0., 1.); floor.vertex_count()];
+        let wall = grid.marching_cubes(&MeshOptions::default());
+        let empty = Mesh::default();
+
+        let mut scene = GltfScene::new();
+        scene
+            .add_mesh("floor", &floor)
+            .add_mesh("\"empty\"", &empty)
+            .add_mesh("wall", &wall);
+        let mut glb = vec![];
+        scene.write_glb(&mut glb, &GltfOptions::default()).unwrap();
+
+        let header = uints(&glb[..20]);
+        assert_eq!(header[0], GLB_MAGIC);
+        assert_eq!(header[1], 2);
+        assert_eq!(header[2] as usize, glb.len());
+        assert_eq!(header[4], GLB_JSON);
+        let json_len = header[3] as usize;
+        assert_eq!(json_len % 4, 0);
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        let chunk = uints(&glb[20 + json_len..28 + json_len]);
+        assert_eq!(chunk[1], GLB_BIN);
+        let bin = &glb[28 + json_len..];
+        assert_eq!(chunk[0] as usize, bin.len());
+
+        let (document, buffer) = scene.build(&GltfOptions::default(), None);
+        assert_eq!(json.trim_end(), document);
+        assert_eq!(bin, buffer);
+        assert!(json.contains(r#""name":"\"empty\""}"#));
+        assert!(json.contains(r#""scenes":[{"nodes":[0,1,2]}]"#));
+        assert!(json.contains(r#""TEXCOORD_0":2,"COLOR_0":3"#));
+        assert_eq!(json.matches("\"primitives\"").count(), 2);
+        assert!(!json.contains("\"uri\""));
+
+        // The first mesh is written first, and its indices after its vertices
+        let n = floor.vertex_count();
+        let positions: Vec<f32> = floor.positions.iter().flat_map(|p| p.to_array()).collect();
+        assert_eq!(floats(&bin[..n * 12]), positions);
+        let offset = n * (12 + 12 + 8 + 16);
+        let indices = &bin[offset..offset + floor.indices.len() * 4];
+        assert_eq!(uints(indices), floor.indices);
+        let colors = floats(&bin[n * 32..n * 48]);
+        assert_eq!(&colors[..4], &[0.5, 0.25, 0., 1.]);
+    }
+
+    #[test]
+    fn attributes() {
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mut mesh = grid.greedy_mesh(&MeshOptions::default());
+        mesh.compute_box_uvs(1.);
+        let options = GltfOptions {
+            uvs: false,
+            colors: false,
+        };
+        let mut scene = GltfScene::new();
+        scene.add_mesh("mesh", &mesh);
+        let (json, bin) = scene.build(&options, Some("mesh.bin"));
+        assert!(json.contains(r#""attributes":{"POSITION":0,"NORMAL":1},"indices":2"#));
+        assert!(json.contains(r#""min":[0,0,0],"max":[4,4,4]"#));
+        assert!(json.contains(r#""buffers":[{"byteLength":"#));
+        assert!(json.contains(r#""uri":"mesh.bin""#));
+        assert_eq!(bin.len(), mesh.vertex_count() * 24 + mesh.indices.len() * 4);
+
+        // Without any mesh, only the asset and the scene are written
+        let (json, bin) = GltfScene::new().build(&options, None);
+        assert_eq!(
+            json,
+            r#"{"asset":{"version":"2.0","generator":"cytogon"},"scene":0,"scenes":[{}]}"#
+        );
+        assert!(bin.is_empty());
+    }
+
+    #[test]
+    fn save() {
+        let dir = TempDir::new("gltf");
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mesh = grid.greedy_mesh(&MeshOptions::default());
+        mesh.save_gltf(dir.join("box.gltf"), &GltfOptions::default())
+            .unwrap();
+        let json = std::fs::read_to_string(dir.join("box.gltf")).unwrap();
+        let bin = std::fs::read(dir.join("box.bin")).unwrap();
+        assert!(json.contains(&format!(r#""byteLength":{},"uri":"box.bin""#, bin.len())));
+        mesh.save_glb(dir.join("box.glb"), &GltfOptions::default())
+            .unwrap();
+        let glb = std::fs::read(dir.join("box.glb")).unwrap();
+        assert!(glb.ends_with(&bin));
+    }
+}
@@ -0,0 +1,272 @@
+//! Greedy meshing of the faces of the alive cells of [`Grid3`].
+//!
+//! The faces between alive and dead cells are first found with bitwise
+//! operations on the bit blocks, for all 64 cells of a block at once. Then in
+//! each slice of the grid perpendicular to an axis, adjacent coplanar faces
+//! with the same orientation are merged into maximal rectangles, each emitted
+//! as a single quad.
+
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::bitslice::{shift_xm, shift_xp, shift_ym, shift_yp, shift_zm, shift_zp};
+use crate::{Grid3, Mesh, MeshOptions};
+
+impl Grid3 {
+    /// Find the faces of the alive cells of each block which are adjacent to a
+    /// dead cell, for each of the 6 directions -X, +X, -Y, +Y, -Z, +Z.
+    pub(crate) fn face_blocks(&self) -> Vec<[u64; 6]> {
+        let dims = Self::get_bitblock_dims(self.size).as_ivec3();
+        let dy = dims.x as usize;
+        let dz = dims.x as usize * dims.y as usize;
+        let mut faces = Vec::with_capacity(self.data.len());
+        let mut ib = 0;
+        for bz in 0..dims.z {
+            for by in 0..dims.y {
+                for bx in 0..dims.x {
+                    let b = self.data[ib];
+                    let get = |valid: bool, index: usize| if valid { self.data[index] } else { 0 };
+                    faces.push([
+                        b & !shift_xm(b, get(bx > 0, ib.wrapping_sub(1))),
+                        b & !shift_xp(b, get(bx + 1 < dims.x, ib + 1)),
+                        b & !shift_ym(b, get(by > 0, ib.wrapping_sub(dy))),
+                        b & !shift_yp(b, get(by + 1 < dims.y, ib + dy)),
+                        b & !shift_zm(b, get(bz > 0, ib.wrapping_sub(dz))),
+                        b & !shift_zp(b, get(bz + 1 < dims.z, ib + dz)),
+                    ]);
+                    ib += 1;
+                }
+            }
+        }
+        faces
+    }
+
+    /// Extract a mesh of the faces between alive and dead cells, merging
+    /// adjacent coplanar faces into maximal rectangles.
+    ///
+    /// Cells outside the grid are considered dead, so the mesh always encloses
+    /// the alive cells. Each rectangle is a quad with its own 4 vertices and a
+    /// flat normal, pointing from the alive cells toward the dead ones. Since
+    /// rectangles of adjacent faces don't generally share their corners, the
+    /// mesh has T-junctions.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::new(16, 8, 4));
+    /// grid.fill(true);
+    ///
+    /// // A single quad per side of the box
+    /// let mesh = grid.greedy_mesh(&MeshOptions::default());
+    /// assert_eq!(mesh.vertex_count(), 6 * 4);
+    /// assert_eq!(mesh.triangle_count(), 6 * 2);
+    /// ```
+    pub fn greedy_mesh(&self, options: &MeshOptions) -> Mesh {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("greedy_mesh").entered();
+
+        let mut mesh = Mesh::default();
+        if self.data.is_empty() {
+            return mesh;
+        }
+        let faces = self.face_blocks();
+        let dims = Self::get_bitblock_dims(self.size);
+
+        for axis in 0..3 {
+            let u = (axis + 1) % 3;
+            let v = (axis + 2) % 3;
+            let (w, h) = (self.size[u] as usize, self.size[v] as usize);
+            let mut mask = vec![false; w * h];
+            for dir in 0..2 {
+                for k in 0..self.size[axis] {
+                    // Gather the faces of slice K from the blocks of its layer
+                    let mut any = false;
+                    let mut bpos = UVec3::ZERO;
+                    bpos[axis] = k / 4;
+                    for bv in 0..dims[v] {
+                        bpos[v] = bv;
+                        for bu in 0..dims[u] {
+                            bpos[u] = bu;
+                            let index = (bpos.z * dims.y + bpos.y) * dims.x + bpos.x;
+                            let f = faces[index as usize][axis * 2 + dir];
+                            for j in 0..4 {
+                                for i in 0..4 {
+                                    let mut pos = bpos * 4;
+                                    pos[axis] = k;
+                                    pos[u] += i;
+                                    pos[v] += j;
+                                    if pos[u] as usize >= w || pos[v] as usize >= h {
+                                        continue;
+                                    }
+                                    let bit = (pos.x & 3) | ((pos.y & 3) << 2) | ((pos.z & 3) << 4);
+                                    let face = (f >> bit) & 1 != 0;
+                                    mask[pos[v] as usize * w + pos[u] as usize] = face;
+                                    any |= face;
+                                }
+                            }
+                        }
+                    }
+                    if any {
+                        Self::merge_faces(&mut mask, w, h, |rect| {
+                            push_rect(&mut mesh, options, axis, dir, k, rect);
+                        });
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    /// Greedily merge the faces of a slice of `w` by `h` faces into maximal
+    /// rectangles, clearing the mask in the process.
+    ///
+    /// Rectangles are given as their minimum and maximum (exclusive)
+    /// coordinates in the slice.
+    fn merge_faces(mask: &mut [bool], w: usize, h: usize, mut emit: impl FnMut([usize; 4])) {
+        for y in 0..h {
+            let mut x = 0;
+            while x < w {
+                if !mask[y * w + x] {
+                    x += 1;
+                    continue;
+                }
+                let width = mask[y * w + x..(y + 1) * w]
+                    .iter()
+                    .position(|f| !*f)
+                    .unwrap_or(w - x);
+                let mut height = 1;
+                while y + height < h {
+                    let row = (y + height) * w + x;
+                    if !mask[row..row + width].iter().all(|f| *f) {
+                        break;
+                    }
+                    height += 1;
+                }
+                for row in y..y + height {
+                    mask[row * w + x..row * w + x + width].fill(false);
+                }
+                emit([x, y, x + width, y + height]);
+                x += width;
+            }
+        }
+    }
+}
+
+/// Push the quad of a rectangle of faces of the slice `k` along `axis`, in
+/// direction `dir` (0 for -axis, 1 for +axis).
+fn push_rect(
+    mesh: &mut Mesh,
+    options: &MeshOptions,
+    axis: usize,
+    dir: usize,
+    k: u32,
+    rect: [usize; 4],
+) {
+    let u = (axis + 1) % 3;
+    let v = (axis + 2) % 3;
+    let corner = |cu: usize, cv: usize| {
+        let mut p = IVec3::ZERO;
+        p[axis] = (k as usize + dir) as i32;
+        p[u] = cu as i32;
+        p[v] = cv as i32;
+        options.to_mesh(p.as_vec3())
+    };
+    let [u0, v0, u1, v1] = rect;
+    let mut quad = [
+        corner(u0, v0),
+        corner(u1, v0),
+        corner(u1, v1),
+        corner(u0, v1),
+    ];
+    let mut normal = Vec3::ZERO;
+    normal[axis] = 1.;
+    if dir == 0 {
+        quad.reverse();
+        normal = -normal;
+    }
+
+    let base = mesh.positions.len() as u32;
+    mesh.positions.extend_from_slice(&quad);
+    mesh.normals.extend_from_slice(&[normal; 4]);
+    mesh.indices
+        .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh;
+
+    #[test]
+    fn random() {
+        for grid in mesh::random_grids() {
+            let options = MeshOptions {
+                voxel_size: Vec3::new(0.5, 1., 2.),
+                origin: Vec3::new(3., -2., 1.),
+            };
+            let mesh = grid.greedy_mesh(&options);
+
+            // The mesh encloses exactly the alive cells
+            let alive = grid.data.iter().map(|b| b.count_ones()).sum::<u32>();
+            assert!((mesh.volume() - alive as f32).abs() < 1e-2);
+
+            // The quads cover exactly the faces between alive and dead cells
+            let mut faces = 0;
+            let mut area = 0.;
+            let size = grid.size.as_ivec3();
+            for z in -1..=size.z {
+                for y in -1..=size.y {
+                    for x in -1..=size.x {
+                        let pos = IVec3::new(x, y, z);
+                        let alive = grid.cell(pos).unwrap_or(false);
+                        for (axis, d) in [IVec3::X, IVec3::Y, IVec3::Z].into_iter().enumerate() {
+                            if alive != grid.cell(pos + d).unwrap_or(false) {
+                                faces += 1;
+                                let mut s = options.voxel_size;
+                                s[axis] = 1.;
+                                area += s.x * s.y * s.z;
+                            }
+                        }
+                    }
+                }
+            }
+            let mesh_area: f32 = mesh
+                .triangles()
+                .map(|[a, b, c]| {
+                    let [a, b, c] = [a, b, c].map(|i| mesh.positions[i as usize]);
+                    (b - a).cross(c - a).length() / 2.
+                })
+                .sum();
+            assert!((mesh_area - area).abs() < 1e-2);
+            assert!(mesh.triangle_count() < faces * 2);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut grid = Grid3::new(UVec3::splat(8));
+        grid.fill(false);
+        // L-shaped wall, 1 cell thick
+        for z in 0..8 {
+            for y in 0..8 {
+                grid.set_cell(IVec3::new(0, y, z), true);
+            }
+            for x in 1..5 {
+                grid.set_cell(IVec3::new(x, 0, z), true);
+            }
+        }
+        let mesh = grid.greedy_mesh(&MeshOptions::default());
+        // -X and -Y as single quads, +X and +Y on two different planes each,
+        // and the L shape of -Z and +Z as 2 quads each
+        assert_eq!(mesh.triangle_count(), 2 * (1 + 1 + 2 + 2 + 2 + 2));
+        assert!((mesh.volume() - (8. + 4.) * 8.).abs() < 1e-3);
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert_eq!(n.length(), 1.);
+            assert!(p.cmpge(Vec3::ZERO).all() && p.cmple(Vec3::splat(8.)).all());
+        }
+    }
+
+    #[test]
+    fn empty() {
+        mesh::assert_empty(Grid3::greedy_mesh);
+    }
+}
@@ -0,0 +1,776 @@
+//! Native binary file format of [`Grid2`] and [`Grid3`].
+//!
+//! All values are little-endian. A file is made of:
+//! - the magic bytes `CYTG`, the format version as a `u16`, the dimension of
+//!   the grid (2 or 3) and the version of the layout of its bit blocks as `u8`;
+//! - the size of the grid, as one `u32` per dimension;
+//! - the boundary condition of the grid as `u8`, followed by a `u8` of flags
+//!   telling which metadata follow: a rule string as a `u16` length and its
+//!   UTF-8 bytes if bit 0 is set, and a `u64` seed if bit 1 is set;
+//! - the compression of the bit blocks as `u8`, the number of blocks as `u32`,
+//!   and the size in bytes of the compressed blocks as `u32`, followed by the
+//!   compressed blocks themselves;
+//! - the CRC-32 checksum of all the previous bytes, as `u32`.
+//!
+//! Blocks are either stored raw, or run-length encoded as a sequence of runs,
+//! each starting with a variable-length integer `n`. If `n` is odd, a single
+//! block value follows, repeated `n >> 1` times. Otherwise, `n >> 1` distinct
+//! block values follow. The writer picks the smallest of both encodings.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use glam::{UVec2, UVec3};
+
+use crate::{Boundary, Grid2, Grid3};
+
+/// Magic bytes at the start of grid files.
+const MAGIC: [u8; 4] = *b"CYTG";
+/// Version of the file format.
+const VERSION: u16 = 1;
+/// Version of the layout of the bit blocks, 8x8 cells for [`Grid2`] and
+/// 4x4x4 cells for [`Grid3`], in X-major order.
+const LAYOUT: u8 = 1;
+/// Compression of raw blocks.
+const COMPRESSION_NONE: u8 = 0;
+/// Compression of run-length encoded blocks.
+const COMPRESSION_RLE: u8 = 1;
+/// Flag of the rule string metadata.
+const FLAG_RULE: u8 = 1;
+/// Flag of the seed metadata.
+const FLAG_SEED: u8 = 2;
+
+/// Optional metadata saved along a grid.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GridMetadata {
+    /// Rule which generated the grid, usually formatted from a [`Rule2`] or
+    /// [`Rule3`].
+    ///
+    /// [`Rule2`]: crate::Rule2
+    /// [`Rule3`]: crate::Rule3
+    pub rule: Option<String>,
+    /// Seed of the random generator used to fill the grid.
+    pub seed: Option<u64>,
+}
+
+/// Error returned when loading a grid fails.
+#[derive(Debug)]
+pub enum GridFileError {
+    /// Reading the file failed.
+    Io(io::Error),
+    /// The file is not a grid file.
+    InvalidMagic,
+    /// The file format version is not supported.
+    UnsupportedVersion(u16),
+    /// The file contains a grid of another dimension.
+    DimensionMismatch {
+        /// The dimension of the grid to load.
+        expected: u8,
+        /// The dimension of the grid in the file.
+        found: u8,
+    },
+    /// The layout of the bit blocks is not supported.
+    UnsupportedLayout(u8),
+    /// The compression of the bit blocks is not supported.
+    UnsupportedCompression(u8),
+    /// The boundary condition is invalid.
+    InvalidBoundary(u8),
+    /// The rule string is not valid UTF-8.
+    InvalidRule,
+    /// The number of bit blocks doesn't match the size of the grid.
+    SizeMismatch {
+        /// The number of blocks of a grid of that size.
+        expected: u64,
+        /// The number of blocks in the file.
+        found: u64,
+    },
+    /// The file ends before the end of the grid.
+    Truncated,
+    /// The compressed blocks can't be decoded, or contain cells outside the
+    /// grid.
+    Corrupted,
+    /// The checksum of the file doesn't match its content.
+    ChecksumMismatch {
+        /// The checksum stored in the file.
+        expected: u32,
+        /// The checksum of the content of the file.
+        found: u32,
+    },
+}
+
+impl fmt::Display for GridFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {}", err),
+            Self::InvalidMagic => write!(f, "not a grid file"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            Self::DimensionMismatch { expected, found } => {
+                write!(f, "expected a {}D grid, found a {}D grid", expected, found)
+            }
+            Self::UnsupportedLayout(l) => write!(f, "unsupported block layout {}", l),
+            Self::UnsupportedCompression(c) => write!(f, "unsupported compression {}", c),
+            Self::InvalidBoundary(b) => write!(f, "invalid boundary condition {}", b),
+            Self::InvalidRule => write!(f, "invalid rule string"),
+            Self::SizeMismatch { expected, found } => write!(
+                f,
+                "expected {} blocks for the grid size, found {}",
+                expected, found
+            ),
+            Self::Truncated => write!(f, "truncated file"),
+            Self::Corrupted => write!(f, "corrupted blocks"),
+            Self::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch, expected {:08x}, found {:08x}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GridFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GridFileError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Get the CRC-32 (IEEE) of some bytes.
+fn crc32(bytes: &[u8]) -> u32 {
+    static TABLE: OnceLock<[u32; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            (0..8).fold(i as u32, |c, _| {
+                if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                }
+            })
+        })
+    });
+    !bytes.iter().fold(!0u32, |c, b| {
+        table[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8)
+    })
+}
+
+/// Append a variable-length integer, 7 bits per byte, lowest bits first.
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Run-length encode bit blocks.
+fn encode_rle(blocks: &[u64]) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut literals = 0..0;
+    let flush = |bytes: &mut Vec<u8>, literals: &std::ops::Range<usize>| {
+        if !literals.is_empty() {
+            push_varint(bytes, (literals.len() as u64) << 1);
+            for b in &blocks[literals.clone()] {
+                bytes.extend_from_slice(&b.to_le_bytes());
+            }
+        }
+    };
+    let mut i = 0;
+    while i < blocks.len() {
+        let run = blocks[i..].iter().take_while(|b| **b == blocks[i]).count();
+        if run >= 2 {
+            flush(&mut bytes, &literals);
+            push_varint(&mut bytes, (run as u64) << 1 | 1);
+            bytes.extend_from_slice(&blocks[i].to_le_bytes());
+            i += run;
+            literals = i..i;
+        } else {
+            i += 1;
+            literals.end = i;
+        }
+    }
+    flush(&mut bytes, &literals);
+    bytes
+}
+
+/// Reader of the values of a grid file.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], GridFileError> {
+        if n > self.bytes.len() {
+            return Err(GridFileError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, GridFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, GridFileError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, GridFileError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, GridFileError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u64, GridFileError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            value |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(GridFileError::Corrupted)
+    }
+}
+
+/// Decode run-length encoded bit blocks.
+fn decode_rle(bytes: &[u8], count: usize) -> Result<Vec<u64>, GridFileError> {
+    let mut cursor = Cursor { bytes };
+    let mut blocks = Vec::with_capacity(count);
+    // Truncated runs are corrupted, since the payload size is known
+    let corrupted = |_| GridFileError::Corrupted;
+    while !cursor.bytes.is_empty() {
+        let n = cursor.varint()?;
+        let len = (n >> 1) as usize;
+        if len == 0 || len > count - blocks.len() {
+            return Err(GridFileError::Corrupted);
+        }
+        if n & 1 != 0 {
+            let b = cursor.u64().map_err(corrupted)?;
+            blocks.resize(blocks.len() + len, b);
+        } else {
+            for _ in 0..len {
+                blocks.push(cursor.u64().map_err(corrupted)?);
+            }
+        }
+    }
+    if blocks.len() != count {
+        return Err(GridFileError::Corrupted);
+    }
+    Ok(blocks)
+}
+
+/// Content of a grid file, common to all dimensions.
+struct GridFile {
+    size: Vec<u32>,
+    boundary: Boundary,
+    blocks: Vec<u64>,
+    metadata: GridMetadata,
+}
+
+impl GridFile {
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.push(self.size.len() as u8);
+        bytes.push(LAYOUT);
+        for s in &self.size {
+            bytes.extend_from_slice(&s.to_le_bytes());
+        }
+        bytes.push(match self.boundary {
+            Boundary::Dead => 0,
+            Boundary::Alive => 1,
+            Boundary::Periodic => 2,
+            Boundary::Mirror => 3,
+        });
+        let mut flags = 0;
+        if self.metadata.rule.is_some() {
+            flags |= FLAG_RULE;
+        }
+        if self.metadata.seed.is_some() {
+            flags |= FLAG_SEED;
+        }
+        bytes.push(flags);
+        if let Some(rule) = &self.metadata.rule {
+            let len = u16::try_from(rule.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "rule string too long"))?;
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(rule.as_bytes());
+        }
+        if let Some(seed) = self.metadata.seed {
+            bytes.extend_from_slice(&seed.to_le_bytes());
+        }
+
+        let rle = encode_rle(&self.blocks);
+        let (compression, payload) = if rle.len() < self.blocks.len() * 8 {
+            (COMPRESSION_RLE, rle)
+        } else {
+            let raw = self.blocks.iter().flat_map(|b| b.to_le_bytes()).collect();
+            (COMPRESSION_NONE, raw)
+        };
+        let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "grid too large");
+        bytes.push(compression);
+        let count = u32::try_from(self.blocks.len()).map_err(too_large)?;
+        bytes.extend_from_slice(&count.to_le_bytes());
+        let len = u32::try_from(payload.len()).map_err(too_large)?;
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Read a grid file, checking that it contains a grid of the given
+    /// dimension, whose number of blocks is given by `block_count` from its
+    /// size.
+    fn read<R: Read>(
+        mut reader: R,
+        dimension: u8,
+        block_count: impl Fn(&[u32]) -> Option<u64>,
+    ) -> Result<Self, GridFileError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let mut cursor = Cursor { bytes: &bytes };
+
+        if cursor.take(4).map_err(|_| GridFileError::InvalidMagic)? != MAGIC {
+            return Err(GridFileError::InvalidMagic);
+        }
+        let version = cursor.u16()?;
+        if version != VERSION {
+            return Err(GridFileError::UnsupportedVersion(version));
+        }
+        let found = cursor.u8()?;
+        if found != dimension {
+            return Err(GridFileError::DimensionMismatch {
+                expected: dimension,
+                found,
+            });
+        }
+        let layout = cursor.u8()?;
+        if layout != LAYOUT {
+            return Err(GridFileError::UnsupportedLayout(layout));
+        }
+        let size = (0..dimension)
+            .map(|_| cursor.u32())
+            .collect::<Result<Vec<_>, _>>()?;
+        let boundary = match cursor.u8()? {
+            0 => Boundary::Dead,
+            1 => Boundary::Alive,
+            2 => Boundary::Periodic,
+            3 => Boundary::Mirror,
+            b => return Err(GridFileError::InvalidBoundary(b)),
+        };
+        let flags = cursor.u8()?;
+        let mut metadata = GridMetadata::default();
+        if flags & FLAG_RULE != 0 {
+            let len = cursor.u16()? as usize;
+            let rule =
+                std::str::from_utf8(cursor.take(len)?).map_err(|_| GridFileError::InvalidRule)?;
+            metadata.rule = Some(rule.to_string());
+        }
+        if flags & FLAG_SEED != 0 {
+            metadata.seed = Some(cursor.u64()?);
+        }
+
+        let compression = cursor.u8()?;
+        let count = cursor.u32()? as u64;
+        // Grids may be saved before being allocated
+        let expected = block_count(&size);
+        if count != 0 && Some(count) != expected {
+            return Err(GridFileError::SizeMismatch {
+                expected: expected.unwrap_or(u64::MAX),
+                found: count,
+            });
+        }
+        let len = cursor.u32()? as usize;
+        let payload = cursor.take(len)?;
+
+        // Check the content before decoding it
+        let checked = bytes.len() - cursor.bytes.len();
+        let found = crc32(&bytes[..checked]);
+        let expected = cursor.u32()?;
+        if found != expected {
+            return Err(GridFileError::ChecksumMismatch { expected, found });
+        }
+        if !cursor.bytes.is_empty() {
+            return Err(GridFileError::Corrupted);
+        }
+
+        let count = count as usize;
+        let blocks = match compression {
+            COMPRESSION_NONE => {
+                if len != count * 8 {
+                    return Err(GridFileError::Corrupted);
+                }
+                payload
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                    .collect()
+            }
+            COMPRESSION_RLE => decode_rle(payload, count)?,
+            c => return Err(GridFileError::UnsupportedCompression(c)),
+        };
+        Ok(Self {
+            size,
+            boundary,
+            blocks,
+            metadata,
+        })
+    }
+}
+
+impl Grid2 {
+    /// Write the grid and its metadata in the native grid file format.
+    ///
+    /// See the [`read()`] function to read it back.
+    ///
+    /// ```
+    /// # use cytogon::{Grid2, GridMetadata, Rule2, UVec2};
+    /// let mut grid = Grid2::new(UVec2::new(100, 30));
+    /// grid.fill(false);
+    /// let metadata = GridMetadata {
+    ///     rule: Some(Rule2::SMOOTH.to_string()),
+    ///     seed: Some(42),
+    /// };
+    ///
+    /// let mut bytes = vec![];
+    /// grid.write(&mut bytes, &metadata).unwrap();
+    /// // Dead blocks are compressed
+    /// assert!(bytes.len() < 100);
+    ///
+    /// let (other, other_metadata) = Grid2::read(&bytes[..]).unwrap();
+    /// assert_eq!(other.size, grid.size);
+    /// assert_eq!(other.data, grid.data);
+    /// assert_eq!(other_metadata, metadata);
+    /// ```
+    ///
+    /// [`read()`]: Self::read
+    pub fn write<W: Write>(&self, writer: W, metadata: &GridMetadata) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write2").entered();
+
+        GridFile {
+            size: self.size.to_array().to_vec(),
+            boundary: self.boundary,
+            blocks: self.data.clone(),
+            metadata: metadata.clone(),
+        }
+        .write(writer)
+    }
+
+    /// Read a grid and its metadata in the native grid file format.
+    ///
+    /// The file is validated, and any error in its content, including a
+    /// grid of another dimension, is reported.
+    pub fn read<R: Read>(reader: R) -> Result<(Self, GridMetadata), GridFileError> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("read2").entered();
+
+        let file = GridFile::read(reader, 2, |size| {
+            (size[0].div_ceil(8) as u64).checked_mul(size[1].div_ceil(8) as u64)
+        })?;
+        let mut grid = Self {
+            size: UVec2::from_slice(&file.size),
+            boundary: file.boundary,
+            data: file.blocks,
+        };
+        if !grid.data.is_empty() {
+            let data = grid.data.clone();
+            grid.clear_padding();
+            if grid.data != data {
+                return Err(GridFileError::Corrupted);
+            }
+        }
+        Ok((grid, file.metadata))
+    }
+
+    /// Save the grid and its metadata to a file.
+    ///
+    /// See [`write()`] for details.
+    ///
+    /// [`write()`]: Self::write
+    pub fn save(&self, path: impl AsRef<Path>, metadata: &GridMetadata) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?), metadata)
+    }
+
+    /// Load a grid and its metadata from a file.
+    ///
+    /// See [`read()`] for details.
+    ///
+    /// [`read()`]: Self::read
+    pub fn load(path: impl AsRef<Path>) -> Result<(Self, GridMetadata), GridFileError> {
+        Self::read(File::open(path)?)
+    }
+}
+
+impl Grid3 {
+    /// Write the grid and its metadata in the native grid file format.
+    ///
+    /// See the [`read()`] function to read it back.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, GridMetadata, Rule3, UVec3};
+    /// let mut grid = Grid3::new(UVec3::new(64, 64, 64));
+    /// grid.fill(true);
+    /// let metadata = GridMetadata {
+    ///     rule: Some(Rule3::SMOOTH.to_string()),
+    ///     seed: None,
+    /// };
+    ///
+    /// let mut bytes = vec![];
+    /// grid.write(&mut bytes, &metadata).unwrap();
+    /// // Alive blocks are compressed
+    /// assert!(bytes.len() < 100);
+    ///
+    /// let (other, other_metadata) = Grid3::read(&bytes[..]).unwrap();
+    /// assert_eq!(other.size, grid.size);
+    /// assert_eq!(other.data, grid.data);
+    /// assert_eq!(other_metadata, metadata);
+    /// ```
+    ///
+    /// [`read()`]: Self::read
+    pub fn write<W: Write>(&self, writer: W, metadata: &GridMetadata) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write3").entered();
+
+        GridFile {
+            size: self.size.to_array().to_vec(),
+            boundary: self.boundary,
+            blocks: self.data.clone(),
+            metadata: metadata.clone(),
+        }
+        .write(writer)
+    }
+
+    /// Read a grid and its metadata in the native grid file format.
+    ///
+    /// The file is validated, and any error in its content, including a
+    /// grid of another dimension, is reported.
+    pub fn read<R: Read>(reader: R) -> Result<(Self, GridMetadata), GridFileError> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("read3").entered();
+
+        let file = GridFile::read(reader, 3, |size| {
+            (size[0].div_ceil(4) as u64)
+                .checked_mul(size[1].div_ceil(4) as u64)?
+                .checked_mul(size[2].div_ceil(4) as u64)
+        })?;
+        let size = UVec3::from_slice(&file.size);
+        let mut data = file.blocks;
+        if !data.is_empty() {
+            let blocks = data.clone();
+            Self::clear_padding_blocks(size, &mut data);
+            if data != blocks {
+                return Err(GridFileError::Corrupted);
+            }
+        }
+        let grid = Self {
+            size,
+            boundary: file.boundary,
+            data,
+        };
+        Ok((grid, file.metadata))
+    }
+
+    /// Save the grid and its metadata to a file.
+    ///
+    /// See [`write()`] for details.
+    ///
+    /// [`write()`]: Self::write
+    pub fn save(&self, path: impl AsRef<Path>, metadata: &GridMetadata) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?), metadata)
+    }
+
+    /// Load a grid and its metadata from a file.
+    ///
+    /// See [`read()`] for details.
+    ///
+    /// [`read()`]: Self::read
+    pub fn load(path: impl AsRef<Path>) -> Result<(Self, GridMetadata), GridFileError> {
+        Self::read(File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{Rule2, Rule3, TempDir};
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn rle() {
+        for blocks in [
+            vec![],
+            vec![7],
+            vec![0, 0, 0, 1, 2, 3, 3, !0, !0, !0, 4],
+            vec![5; 1000],
+            (0..300).collect(),
+        ] {
+            let bytes = encode_rle(&blocks);
+            assert_eq!(decode_rle(&bytes, blocks.len()).unwrap(), blocks);
+        }
+        assert_eq!(encode_rle(&[5; 1000]).len(), 2 + 8);
+
+        // Runs beyond the expected number of blocks, or too few blocks
+        let bytes = encode_rle(&[5; 10]);
+        assert!(matches!(
+            decode_rle(&bytes, 9),
+            Err(GridFileError::Corrupted)
+        ));
+        assert!(matches!(
+            decode_rle(&bytes, 11),
+            Err(GridFileError::Corrupted)
+        ));
+        assert!(matches!(
+            decode_rle(&bytes[..5], 10),
+            Err(GridFileError::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut grid = Grid3::new(UVec3::new(13, 8, 21));
+        grid.boundary = Boundary::Mirror;
+        let metadata = GridMetadata::default();
+        for fill_ratio in [0.5, 0.05] {
+            grid.fill_rand(fill_ratio, StdRng::seed_from_u64(42));
+            let mut bytes = vec![];
+            grid.write(&mut bytes, &metadata).unwrap();
+            assert_eq!(bytes[6], 3);
+            let (other, other_metadata) = Grid3::read(&bytes[..]).unwrap();
+            assert_eq!(other.size, grid.size);
+            assert_eq!(other.boundary, grid.boundary);
+            assert_eq!(other.data, grid.data);
+            assert_eq!(other_metadata, metadata);
+        }
+
+        let mut grid = Grid2::new(UVec2::new(70, 33));
+        grid.boundary = Boundary::Periodic;
+        let metadata = GridMetadata {
+            rule: Some(Rule2::SMOOTH.to_string()),
+            seed: Some(u64::MAX),
+        };
+        grid.fill_rand(0.45, StdRng::seed_from_u64(3));
+        let mut bytes = vec![];
+        grid.write(&mut bytes, &metadata).unwrap();
+        let (other, other_metadata) = Grid2::read(&bytes[..]).unwrap();
+        assert_eq!(other.size, grid.size);
+        assert_eq!(other.boundary, grid.boundary);
+        assert_eq!(other.data, grid.data);
+        assert_eq!(other_metadata, metadata);
+        let rule: Rule2 = other_metadata.rule.unwrap().parse().unwrap();
+        assert_eq!(rule, Rule2::SMOOTH);
+
+        // Unallocated grids
+        let grid = Grid3::new(UVec3::new(4, 5, 6));
+        let mut bytes = vec![];
+        grid.write(&mut bytes, &metadata).unwrap();
+        let other = Grid3::read(&bytes[..]).unwrap().0;
+        assert_eq!(other.size, grid.size);
+        assert!(other.data.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let mut grid = Grid3::new(UVec3::new(8, 8, 8));
+        grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+        let metadata = GridMetadata {
+            rule: Some(Rule3::SMOOTH.to_string()),
+            seed: Some(1),
+        };
+        let mut bytes = vec![];
+        grid.write(&mut bytes, &metadata).unwrap();
+        let read = |bytes: &[u8]| Grid3::read(bytes).map(|_| ()).unwrap_err();
+
+        assert!(matches!(read(b"PNG"), GridFileError::InvalidMagic));
+        assert!(matches!(
+            Grid2::read(&bytes[..]).map(|_| ()).unwrap_err(),
+            GridFileError::DimensionMismatch {
+                expected: 2,
+                found: 3
+            }
+        ));
+        for n in [6, 20, bytes.len() - 1] {
+            assert!(matches!(read(&bytes[..n]), GridFileError::Truncated));
+        }
+        let mut other = bytes.clone();
+        other[4] = 2;
+        assert!(matches!(read(&other), GridFileError::UnsupportedVersion(2)));
+        let mut other = bytes.clone();
+        other[7] = 9;
+        assert!(matches!(read(&other), GridFileError::UnsupportedLayout(9)));
+
+        // Any change to the content is detected
+        let mut other = bytes.clone();
+        let last = other.len() - 5;
+        other[last] ^= 1;
+        assert!(matches!(
+            read(&other),
+            GridFileError::ChecksumMismatch { .. }
+        ));
+        let mut other = bytes.clone();
+        other.push(0);
+        assert!(matches!(read(&other), GridFileError::Corrupted));
+
+        // The number of blocks is validated against the size, before the
+        // checksum
+        let mut other = bytes.clone();
+        other[8] = 9;
+        assert!(matches!(
+            read(&other),
+            GridFileError::SizeMismatch {
+                expected: 12,
+                found: 8
+            }
+        ));
+
+        // Padding bits outside the grid are not valid
+        let mut grid = Grid3::new(UVec3::new(3, 4, 4));
+        grid.fill(false);
+        grid.data[0] = 1 << 3;
+        let mut bytes = vec![];
+        grid.write(&mut bytes, &GridMetadata::default()).unwrap();
+        assert!(matches!(read(&bytes), GridFileError::Corrupted));
+    }
+
+    #[test]
+    fn save() {
+        let dir = TempDir::new("grid");
+        let mut grid = Grid3::new(UVec3::new(32, 16, 8));
+        grid.fill_rand(0.6, StdRng::seed_from_u64(7));
+        let metadata = GridMetadata {
+            rule: None,
+            seed: Some(7),
+        };
+        grid.save(dir.join("cave.cyg"), &metadata).unwrap();
+        let (other, other_metadata) = Grid3::load(dir.join("cave.cyg")).unwrap();
+        assert_eq!(other.data, grid.data);
+        assert_eq!(other_metadata, metadata);
+        assert!(matches!(
+            Grid3::load(dir.join("missing.cyg")),
+            Err(GridFileError::Io(_))
+        ));
+    }
+}
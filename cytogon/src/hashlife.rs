@@ -0,0 +1,926 @@
+//! HashLife engine, advancing patterns by large powers of two of generations.
+//!
+//! The pattern is stored as a tree where each node covers a square (2D) or
+//! cube (3D) of `2^level` cells along each axis, and has 4 (2D) or 8 (3D)
+//! children covering each of its quadrants (octants). Identical nodes are
+//! shared, so repetitive or empty regions are stored only once. The result of
+//! advancing the center of each node is memoized, which lets the engine skip
+//! any region it already computed, in time and in space.
+//!
+//! The tree is embedded in an infinite space of dead cells, so patterns never
+//! reach a boundary.
+
+use std::collections::HashMap;
+
+use glam::{IVec2, IVec3, UVec2, UVec3};
+
+use crate::{Grid2, Grid3, Neighborhood, Rule2, Rule3};
+
+/// Identifier of a dead leaf, that is a single dead cell.
+const DEAD: u32 = 0;
+
+/// Identifier of an alive leaf, that is a single alive cell.
+const ALIVE: u32 = 1;
+
+/// Node of the tree, stored in [`Tree::nodes`].
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    /// Identifiers of the children of the node. Child `i` is at offset
+    /// `(i & 1, (i >> 1) & 1, i >> 2)` in the node. Only the first 4 children
+    /// are used in 2D, and the others are always [`DEAD`].
+    children: [u32; 8],
+    /// Level of the node. A node of level `n` covers `2^n` cells along each
+    /// axis. Leaves are of level 0.
+    level: u8,
+    /// Number of alive cells in the node.
+    population: u64,
+}
+
+/// Memoized quadtree or octree storing a pattern and its evolution under a
+/// rule.
+///
+/// Positions are always 3D, with Z=0 in 2D.
+#[derive(Clone)]
+struct Tree {
+    /// Dimension of the space, 2 or 3.
+    dim: usize,
+    /// Birth rule bits.
+    birth: u32,
+    /// Survive rule bits.
+    survive: u32,
+    /// Neighborhood of the rule.
+    neighborhood: Neighborhood,
+    /// All nodes, indexed by their identifier.
+    nodes: Vec<Node>,
+    /// Identifiers of all nodes, indexed by their children.
+    ids: HashMap<[u32; 8], u32>,
+    /// Identifiers of the empty node of each level.
+    empty: Vec<u32>,
+    /// Memoized results of [`Tree::advance()`], indexed by the node and the
+    /// log2 of the number of generations.
+    results: HashMap<(u32, u8), u32>,
+}
+
+impl Tree {
+    fn new(dim: usize, birth: u32, survive: u32, neighborhood: Neighborhood) -> Self {
+        assert!(
+            birth & 1 == 0,
+            "HashLife does not support rules with birth on 0 neighbor."
+        );
+        let leaf = |population| Node {
+            children: [DEAD; 8],
+            level: 0,
+            population,
+        };
+        Self {
+            dim,
+            birth,
+            survive,
+            neighborhood,
+            nodes: vec![leaf(0), leaf(1)],
+            ids: HashMap::new(),
+            empty: vec![DEAD],
+            results: HashMap::new(),
+        }
+    }
+
+    /// Number of children of a node.
+    #[inline]
+    fn child_count(&self) -> usize {
+        1 << self.dim
+    }
+
+    /// Number of nodes in a 3x3(x3) arrangement.
+    #[inline]
+    fn grid3_count(&self) -> usize {
+        if self.dim == 2 {
+            9
+        } else {
+            27
+        }
+    }
+
+    #[inline]
+    fn level(&self, id: u32) -> u8 {
+        self.nodes[id as usize].level
+    }
+
+    #[inline]
+    fn population(&self, id: u32) -> u64 {
+        self.nodes[id as usize].population
+    }
+
+    #[inline]
+    fn child(&self, id: u32, i: usize) -> u32 {
+        self.nodes[id as usize].children[i]
+    }
+
+    /// Get the node with the given children, creating it if needed.
+    fn node(&mut self, children: [u32; 8]) -> u32 {
+        if let Some(id) = self.ids.get(&children) {
+            return *id;
+        }
+        let n = self.child_count();
+        let level = self.level(children[0]) + 1;
+        let population = children[..n].iter().map(|c| self.population(*c)).sum();
+        let id = self.nodes.len() as u32;
+        self.nodes.push(Node {
+            children,
+            level,
+            population,
+        });
+        self.ids.insert(children, id);
+        id
+    }
+
+    /// Get the node whose child `i` is `f(i)`.
+    #[inline]
+    fn make(&mut self, mut f: impl FnMut(&mut Self, usize) -> u32) -> u32 {
+        let mut children = [DEAD; 8];
+        let n = self.child_count();
+        for (i, child) in children.iter_mut().enumerate().take(n) {
+            *child = f(self, i);
+        }
+        self.node(children)
+    }
+
+    /// Get the empty node of the given level.
+    fn empty(&mut self, level: u8) -> u32 {
+        while self.empty.len() <= level as usize {
+            let e = *self.empty.last().unwrap();
+            let id = self.make(|_, _| e);
+            self.empty.push(id);
+        }
+        self.empty[level as usize]
+    }
+
+    /// Get the grandchild of a node at position `g`, in units of grandchildren
+    /// (0 to 3 along each axis).
+    #[inline]
+    fn grandchild(&self, id: u32, g: [usize; 3]) -> u32 {
+        let c = (g[0] >> 1) | ((g[1] >> 1) << 1) | ((g[2] >> 1) << 2);
+        let gc = (g[0] & 1) | ((g[1] & 1) << 1) | ((g[2] & 1) << 2);
+        self.child(self.child(id, c), gc)
+    }
+
+    /// Get the node of level `n-1` made of the grandchildren of the node of
+    /// level `n`, starting at position `o`, in units of grandchildren (0 to 2
+    /// along each axis).
+    fn sub(&mut self, id: u32, o: [usize; 3]) -> u32 {
+        self.make(|t, i| t.grandchild(id, add(o, bits(i))))
+    }
+
+    /// Get the node of level `n-1` at the center of the node of level `n`.
+    fn center(&mut self, id: u32) -> u32 {
+        let o = if self.dim == 2 { [1, 1, 0] } else { [1, 1, 1] };
+        self.sub(id, o)
+    }
+
+    /// Get the node of level `n+1` with the node of level `n` at its center.
+    fn expand(&mut self, id: u32) -> u32 {
+        let level = self.level(id);
+        let e = self.empty(level - 1);
+        let opposite = self.child_count() - 1;
+        self.make(|t, i| {
+            let c = t.child(id, i);
+            t.make(|_, j| if j == i ^ opposite { c } else { e })
+        })
+    }
+
+    /// Advance the center of a node of level `n >= 2` by `2^k` generations,
+    /// with `k <= n - 2`, and return it as a node of level `n - 1`.
+    fn advance(&mut self, id: u32, k: u8) -> u32 {
+        let level = self.level(id);
+        debug_assert!(level >= 2 && k <= level - 2);
+        if self.population(id) == 0 {
+            return self.empty(level - 1);
+        }
+        if let Some(result) = self.results.get(&(id, k)) {
+            return *result;
+        }
+
+        let result = if level == 2 {
+            self.advance_base(id)
+        } else {
+            // Compute the 3x3(x3) overlapping nodes of level n-2 at the center
+            // of the sub-nodes of level n-1, advanced by half the generations
+            // when advancing at full speed, or not advanced at all otherwise.
+            let mut inter = [DEAD; 27];
+            for (j, inter) in inter.iter_mut().enumerate().take(self.grid3_count()) {
+                let s = self.sub(id, [j % 3, j / 3 % 3, j / 9]);
+                *inter = if k == level - 2 {
+                    self.advance(s, level - 3)
+                } else {
+                    self.center(s)
+                };
+            }
+
+            // Advance the 2x2(x2) nodes of level n-1 made of the intermediate
+            // nodes, and assemble their results
+            let k = k.min(level - 3);
+            self.make(|t, q| {
+                let q = bits(q);
+                let m = t.make(|_, i| {
+                    let p = add(q, bits(i));
+                    inter[p[0] + p[1] * 3 + p[2] * 9]
+                });
+                t.advance(m, k)
+            })
+        };
+
+        self.results.insert((id, k), result);
+        result
+    }
+
+    /// Advance the center of a node of level 2 by one generation, by applying
+    /// the rule to each of its central cells.
+    fn advance_base(&mut self, id: u32) -> u32 {
+        let zmax = if self.dim == 2 { 1 } else { 4 };
+        let mut cells = 0u64;
+        for z in 0..zmax {
+            for y in 0..4 {
+                for x in 0..4 {
+                    if self.grandchild(id, [x, y, z]) == ALIVE {
+                        cells |= 1 << (x | (y << 2) | (z << 4));
+                    }
+                }
+            }
+        }
+        let alive = |p: [i32; 3]| {
+            let valid = p.iter().all(|c| (0..4).contains(c));
+            valid && (cells >> (p[0] | (p[1] << 2) | (p[2] << 4))) & 1 != 0
+        };
+
+        self.make(|t, i| {
+            let b = bits(i);
+            let dz = if t.dim == 2 { 0 } else { 1 };
+            let pos = [b[0] as i32 + 1, b[1] as i32 + 1, b[2] as i32 + dz];
+            let mut count = 0;
+            for z in -dz..=dz {
+                for y in -1i32..=1 {
+                    for x in -1i32..=1 {
+                        let d = x.abs() + y.abs() + z.abs();
+                        let counted = match t.neighborhood {
+                            Neighborhood::Moore => d > 0,
+                            Neighborhood::VonNeumann => d == 1,
+                        };
+                        if counted && alive([pos[0] + x, pos[1] + y, pos[2] + z]) {
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            let rule = if alive(pos) { t.survive } else { t.birth };
+            if (rule >> count) & 1 != 0 {
+                ALIVE
+            } else {
+                DEAD
+            }
+        })
+    }
+
+    /// Get the state of the cell at position `pos` relative to the minimum
+    /// corner of a node.
+    fn cell(&self, mut id: u32, pos: [i64; 3]) -> bool {
+        let mut level = self.level(id);
+        let size = 1i64 << level;
+        if pos[..self.dim].iter().any(|c| !(0..size).contains(c)) {
+            return false;
+        }
+        while level > 0 {
+            level -= 1;
+            id = self.child(id, child_index(pos, level));
+        }
+        id == ALIVE
+    }
+
+    /// Set the state of the cell at position `pos` relative to the minimum
+    /// corner of a node, which must be inside the node, and return the
+    /// modified node.
+    fn set_cell(&mut self, id: u32, pos: [i64; 3], value: bool) -> u32 {
+        let level = self.level(id);
+        if level == 0 {
+            return if value { ALIVE } else { DEAD };
+        }
+        let c = child_index(pos, level - 1);
+        let mut children = self.nodes[id as usize].children;
+        children[c] = self.set_cell(children[c], pos, value);
+        self.node(children)
+    }
+
+    /// Build a node of the given level whose minimum corner is at `origin`,
+    /// taking the state of its cells from `cell()` inside the box from the
+    /// origin to `size`, and dead outside of it.
+    fn build(
+        &mut self,
+        level: u8,
+        origin: [i64; 3],
+        size: [i64; 3],
+        cell: &impl Fn([i64; 3]) -> bool,
+    ) -> u32 {
+        let extent = 1i64 << level;
+        let outside = (0..self.dim).any(|a| origin[a] >= size[a] || origin[a] + extent <= 0);
+        if outside {
+            return self.empty(level);
+        }
+        if level == 0 {
+            return if cell(origin) { ALIVE } else { DEAD };
+        }
+        let half = extent / 2;
+        self.make(|t, i| {
+            let b = bits(i);
+            let o = std::array::from_fn(|a| origin[a] + b[a] as i64 * half);
+            t.build(level - 1, o, size, cell)
+        })
+    }
+
+    /// Call `f()` with the position of all alive cells of the node whose
+    /// minimum corner is at `origin`, which are inside the box from `min` to
+    /// `max` (exclusive).
+    fn for_each_alive(
+        &self,
+        id: u32,
+        origin: [i64; 3],
+        min: [i64; 3],
+        max: [i64; 3],
+        f: &mut impl FnMut([i64; 3]),
+    ) {
+        let level = self.level(id);
+        let extent = 1i64 << level;
+        let outside = (0..self.dim).any(|a| origin[a] >= max[a] || origin[a] + extent <= min[a]);
+        if outside || self.population(id) == 0 {
+            return;
+        }
+        if level == 0 {
+            f(origin);
+            return;
+        }
+        let half = extent / 2;
+        for i in 0..self.child_count() {
+            let b = bits(i);
+            let o = std::array::from_fn(|a| origin[a] + b[a] as i64 * half);
+            self.for_each_alive(self.child(id, i), o, min, max, f);
+        }
+    }
+
+    /// Copy the node `id` of another tree into this one, and return its new
+    /// identifier.
+    fn import(&mut self, other: &Tree, id: u32, map: &mut HashMap<u32, u32>) -> u32 {
+        if id == DEAD || id == ALIVE {
+            return id;
+        }
+        if let Some(new_id) = map.get(&id) {
+            return *new_id;
+        }
+        let new_id = self.make(|t, i| t.import(other, other.child(id, i), map));
+        map.insert(id, new_id);
+        new_id
+    }
+}
+
+/// Offset of child `i` inside its parent, in units of children.
+#[inline]
+fn bits(i: usize) -> [usize; 3] {
+    [i & 1, (i >> 1) & 1, i >> 2]
+}
+
+#[inline]
+fn add(a: [usize; 3], b: [usize; 3]) -> [usize; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+/// Index of the child of level `level` containing the cell at position `pos`
+/// relative to the minimum corner of its parent.
+#[inline]
+fn child_index(pos: [i64; 3], level: u8) -> usize {
+    let b = |c: i64| ((c >> level) & 1) as usize;
+    b(pos[0]) | (b(pos[1]) << 1) | (b(pos[2]) << 2)
+}
+
+/// Pattern state shared by [`HashLife2`] and [`HashLife3`].
+#[derive(Clone)]
+struct Universe {
+    tree: Tree,
+    /// Root node, containing all alive cells.
+    root: u32,
+    /// Position of the minimum corner of the root node.
+    origin: [i64; 3],
+    /// Number of generations the pattern was advanced by.
+    generation: u64,
+}
+
+impl Universe {
+    fn new(tree: Tree) -> Self {
+        let mut universe = Self {
+            tree,
+            root: DEAD,
+            origin: [0; 3],
+            generation: 0,
+        };
+        universe.root = universe.tree.empty(3);
+        universe.origin = universe.axes(-4);
+        universe
+    }
+
+    /// Get a position with the same value on all axes of the space.
+    fn axes(&self, c: i64) -> [i64; 3] {
+        std::array::from_fn(|a| if a < self.tree.dim { c } else { 0 })
+    }
+
+    /// Build the universe from the cells of a box of the given size, with its
+    /// minimum corner at the origin.
+    fn from_cells(mut tree: Tree, size: [i64; 3], cell: impl Fn([i64; 3]) -> bool) -> Self {
+        let max = size.iter().copied().max().unwrap_or(0).max(1) as u64;
+        let level = (max.next_power_of_two().trailing_zeros() as u8).max(3);
+        let root = tree.build(level, [0; 3], size, &cell);
+        Self {
+            tree,
+            root,
+            origin: [0; 3],
+            generation: 0,
+        }
+    }
+
+    fn level(&self) -> u8 {
+        self.tree.level(self.root)
+    }
+
+    fn expand(&mut self) {
+        let half = 1i64 << (self.level() - 1);
+        self.root = self.tree.expand(self.root);
+        let axes = self.axes(half);
+        self.origin = std::array::from_fn(|a| self.origin[a] - axes[a]);
+    }
+
+    fn cell(&self, pos: [i64; 3]) -> bool {
+        let rel = std::array::from_fn(|a| pos[a] - self.origin[a]);
+        self.tree.cell(self.root, rel)
+    }
+
+    fn set_cell(&mut self, pos: [i64; 3], value: bool) {
+        loop {
+            let size = 1i64 << self.level();
+            let inside = (0..self.tree.dim)
+                .all(|a| (self.origin[a]..self.origin[a] + size).contains(&pos[a]));
+            if inside {
+                break;
+            }
+            self.expand();
+        }
+        let rel = std::array::from_fn(|a| pos[a] - self.origin[a]);
+        self.root = self.tree.set_cell(self.root, rel, value);
+    }
+
+    fn step_pow2(&mut self, k: u8) {
+        assert!(k <= 60, "Cannot advance by more than 2^60 generations.");
+
+        // The pattern must fit in the center quarter of the root so that, even
+        // growing at the speed of light, it stays inside the center half after
+        // the root is advanced.
+        loop {
+            if self.level() >= k + 3 {
+                let center = self.tree.center(self.root);
+                let center = self.tree.center(center);
+                if self.tree.population(center) == self.tree.population(self.root) {
+                    break;
+                }
+            }
+            self.expand();
+        }
+
+        let quarter = 1i64 << (self.level() - 2);
+        self.root = self.tree.advance(self.root, k);
+        let axes = self.axes(quarter);
+        self.origin = std::array::from_fn(|a| self.origin[a] + axes[a]);
+        self.generation += 1 << k;
+    }
+
+    fn step(&mut self, n: u64) {
+        for k in 0..64 {
+            if (n >> k) & 1 != 0 {
+                self.step_pow2(k);
+            }
+        }
+    }
+
+    fn for_each_alive(&self, min: [i64; 3], max: [i64; 3], f: &mut impl FnMut([i64; 3])) {
+        self.tree
+            .for_each_alive(self.root, self.origin, min, max, f);
+    }
+
+    fn collect_garbage(&mut self) {
+        let old = &self.tree;
+        let mut tree = Tree::new(old.dim, old.birth, old.survive, old.neighborhood);
+        self.root = tree.import(old, self.root, &mut HashMap::new());
+        self.tree = tree;
+    }
+}
+
+/// HashLife engine for a 2D cellular automaton.
+///
+/// The pattern lives in an infinite space of dead cells, and can be advanced by
+/// a large number of generations at once, which is typically much faster than
+/// applying the rule generation by generation for patterns which are
+/// repetitive in space or in time. Memory grows with the number of distinct
+/// regions encountered, and can be reclaimed with [`collect_garbage()`].
+///
+/// ```
+/// # use cytogon::{HashLife2, IVec2, Rule2};
+/// let life: Rule2 = "B3/S23".parse().unwrap();
+/// let mut hl = HashLife2::new(life);
+/// // Glider moving toward X+ and Y+
+/// for pos in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+///     hl.set_cell(IVec2::new(pos.0, pos.1), true);
+/// }
+///
+/// hl.step_pow2(12);
+/// assert_eq!(hl.generation(), 4096);
+/// assert_eq!(hl.population(), 5);
+/// assert!(hl.cell(IVec2::new(1025, 1024)));
+/// ```
+///
+/// [`collect_garbage()`]: Self::collect_garbage
+#[derive(Clone)]
+pub struct HashLife2 {
+    rule: Rule2,
+    universe: Universe,
+}
+
+impl HashLife2 {
+    /// Create a new engine for the given rule, with all cells dead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rule makes cells with no alive neighbor become alive,
+    /// since this would fill the infinite empty space.
+    pub fn new(rule: Rule2) -> Self {
+        Self {
+            rule,
+            universe: Universe::new(Self::tree(&rule)),
+        }
+    }
+
+    fn tree(rule: &Rule2) -> Tree {
+        Tree::new(
+            2,
+            rule.birth.to_bits() as u32,
+            rule.survive.to_bits() as u32,
+            rule.neighborhood,
+        )
+    }
+
+    /// Create a new engine for the given rule, starting from the cells of
+    /// `grid`, with its cell (0,0) at the origin.
+    ///
+    /// The boundary condition of the grid is ignored, since the pattern lives
+    /// in an infinite space of dead cells.
+    pub fn from_grid(grid: &Grid2, rule: Rule2) -> Self {
+        let size = [grid.size.x as i64, grid.size.y as i64, 1];
+        let cell = |p: [i64; 3]| {
+            grid.cell(IVec2::new(p[0] as i32, p[1] as i32))
+                .unwrap_or(false)
+        };
+        let universe = if grid.data.is_empty() {
+            Universe::new(Self::tree(&rule))
+        } else {
+            Universe::from_cells(Self::tree(&rule), size, cell)
+        };
+        Self { rule, universe }
+    }
+
+    /// Copy the cells of the area of the given size starting at `origin` into
+    /// a new dense grid.
+    pub fn to_grid(&self, origin: IVec2, size: UVec2) -> Grid2 {
+        let mut grid = Grid2::new(size);
+        grid.fill(false);
+        let min = [origin.x as i64, origin.y as i64, 0];
+        let max = [min[0] + size.x as i64, min[1] + size.y as i64, 1];
+        self.universe.for_each_alive(min, max, &mut |p| {
+            grid.set_cell(
+                IVec2::new((p[0] - min[0]) as i32, (p[1] - min[1]) as i32),
+                true,
+            );
+        });
+        grid
+    }
+
+    /// Get the rule applied at each generation.
+    #[inline]
+    pub fn rule(&self) -> &Rule2 {
+        &self.rule
+    }
+
+    /// Get the number of generations the pattern was advanced by.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.universe.generation
+    }
+
+    /// Get the number of alive cells.
+    #[inline]
+    pub fn population(&self) -> u64 {
+        self.universe.tree.population(self.universe.root)
+    }
+
+    /// Get the state of the cell at the given position.
+    pub fn cell(&self, pos: IVec2) -> bool {
+        self.universe.cell([pos.x as i64, pos.y as i64, 0])
+    }
+
+    /// Set the state of the cell at the given position.
+    pub fn set_cell(&mut self, pos: IVec2, value: bool) {
+        self.universe
+            .set_cell([pos.x as i64, pos.y as i64, 0], value);
+    }
+
+    /// Advance the pattern by `2^k` generations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is larger than 60.
+    pub fn step_pow2(&mut self, k: u8) {
+        self.universe.step_pow2(k);
+    }
+
+    /// Advance the pattern by `n` generations.
+    pub fn step(&mut self, n: u64) {
+        self.universe.step(n);
+    }
+
+    /// Free all memoized results and all nodes not part of the current
+    /// pattern.
+    pub fn collect_garbage(&mut self) {
+        self.universe.collect_garbage();
+    }
+}
+
+/// HashLife engine for a 3D cellular automaton.
+///
+/// This is the 3D equivalent of [`HashLife2`], using an octree instead of a
+/// quadtree.
+///
+/// ```
+/// # use cytogon::{Grid3, HashLife3, IVec3, Rule3, UVec3};
+/// # use rand::{rngs::StdRng, SeedableRng};
+/// let mut cave = Grid3::new(UVec3::ONE * 32);
+/// cave.fill_rand(0.5, StdRng::seed_from_u64(42));
+///
+/// let mut hl = HashLife3::from_grid(&cave, Rule3::SMOOTH);
+/// hl.step(1000);
+/// let cave = hl.to_grid(IVec3::ZERO, UVec3::ONE * 32);
+/// ```
+#[derive(Clone)]
+pub struct HashLife3 {
+    rule: Rule3,
+    universe: Universe,
+}
+
+impl HashLife3 {
+    /// Create a new engine for the given rule, with all cells dead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rule makes cells with no alive neighbor become alive,
+    /// since this would fill the infinite empty space.
+    pub fn new(rule: Rule3) -> Self {
+        Self {
+            rule,
+            universe: Universe::new(Self::tree(&rule)),
+        }
+    }
+
+    fn tree(rule: &Rule3) -> Tree {
+        Tree::new(
+            3,
+            rule.birth.to_bits(),
+            rule.survive.to_bits(),
+            rule.neighborhood,
+        )
+    }
+
+    /// Create a new engine for the given rule, starting from the cells of
+    /// `grid`, with its cell (0,0,0) at the origin.
+    ///
+    /// The boundary condition of the grid is ignored, since the pattern lives
+    /// in an infinite space of dead cells.
+    pub fn from_grid(grid: &Grid3, rule: Rule3) -> Self {
+        let size = [grid.size.x as i64, grid.size.y as i64, grid.size.z as i64];
+        let cell = |p: [i64; 3]| {
+            grid.cell(IVec3::new(p[0] as i32, p[1] as i32, p[2] as i32))
+                .unwrap_or(false)
+        };
+        let universe = if grid.data.is_empty() {
+            Universe::new(Self::tree(&rule))
+        } else {
+            Universe::from_cells(Self::tree(&rule), size, cell)
+        };
+        Self { rule, universe }
+    }
+
+    /// Copy the cells of the area of the given size starting at `origin` into
+    /// a new dense grid.
+    pub fn to_grid(&self, origin: IVec3, size: UVec3) -> Grid3 {
+        let mut grid = Grid3::new(size);
+        grid.fill(false);
+        let min = [origin.x as i64, origin.y as i64, origin.z as i64];
+        let max = std::array::from_fn(|a| min[a] + size[a] as i64);
+        self.universe.for_each_alive(min, max, &mut |p| {
+            let pos = IVec3::new(
+                (p[0] - min[0]) as i32,
+                (p[1] - min[1]) as i32,
+                (p[2] - min[2]) as i32,
+            );
+            grid.set_cell(pos, true);
+        });
+        grid
+    }
+
+    /// Get the rule applied at each generation.
+    #[inline]
+    pub fn rule(&self) -> &Rule3 {
+        &self.rule
+    }
+
+    /// Get the number of generations the pattern was advanced by.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.universe.generation
+    }
+
+    /// Get the number of alive cells.
+    #[inline]
+    pub fn population(&self) -> u64 {
+        self.universe.tree.population(self.universe.root)
+    }
+
+    /// Get the state of the cell at the given position.
+    pub fn cell(&self, pos: IVec3) -> bool {
+        self.universe
+            .cell([pos.x as i64, pos.y as i64, pos.z as i64])
+    }
+
+    /// Set the state of the cell at the given position.
+    pub fn set_cell(&mut self, pos: IVec3, value: bool) {
+        self.universe
+            .set_cell([pos.x as i64, pos.y as i64, pos.z as i64], value);
+    }
+
+    /// Advance the pattern by `2^k` generations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is larger than 60.
+    pub fn step_pow2(&mut self, k: u8) {
+        self.universe.step_pow2(k);
+    }
+
+    /// Advance the pattern by `n` generations.
+    pub fn step(&mut self, n: u64) {
+        self.universe.step(n);
+    }
+
+    /// Free all memoized results and all nodes not part of the current
+    /// pattern.
+    pub fn collect_garbage(&mut self) {
+        self.universe.collect_garbage();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn cell2() {
+        let mut hl = HashLife2::new(Rule2::SMOOTH);
+        let positions = [IVec2::new(0, 0), IVec2::new(-1, 100), IVec2::new(-300, -7)];
+        for pos in positions {
+            hl.set_cell(pos, true);
+        }
+        for pos in positions {
+            assert!(hl.cell(pos));
+        }
+        assert!(!hl.cell(IVec2::new(1, 0)));
+        assert!(!hl.cell(IVec2::new(1 << 20, 0)));
+        assert_eq!(hl.population(), 3);
+
+        hl.set_cell(IVec2::new(-1, 100), false);
+        assert_eq!(hl.population(), 2);
+    }
+
+    #[test]
+    fn grid_roundtrip3() {
+        let mut grid = Grid3::new(UVec3::new(21, 7, 18));
+        grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+        let hl = HashLife3::from_grid(&grid, Rule3::SMOOTH);
+        assert_eq!(hl.to_grid(IVec3::ZERO, grid.size).data, grid.data);
+
+        // Extracting a larger area includes the dead cells around the pattern
+        let larger = hl.to_grid(IVec3::splat(-3), grid.size + 6);
+        for pos in [IVec3::new(0, 0, 0), IVec3::new(23, 9, 20)] {
+            assert_eq!(larger.cell(pos), Some(false));
+        }
+        let pos = IVec3::new(4, 5, 6);
+        assert_eq!(larger.cell(pos + 3), grid.cell(pos));
+    }
+
+    #[test]
+    fn step2() {
+        // Pattern at the center of a dense grid large enough that it never
+        // reaches its boundary
+        let mut small = Grid2::new(UVec2::splat(16));
+        small.fill_rand(0.4, StdRng::seed_from_u64(42));
+        let mut dense = Grid2::new(UVec2::splat(256));
+        dense.fill(false);
+        for y in 0..16 {
+            for x in 0..16 {
+                let pos = IVec2::new(x, y);
+                dense.set_cell(pos + 120, small.cell(pos).unwrap());
+            }
+        }
+
+        for rule in ["B3/S23", "B2/S1/V"] {
+            let rule: Rule2 = rule.parse().unwrap();
+            let mut grid = dense.clone();
+            let mut hl = HashLife2::from_grid(&grid, rule);
+            let mut generation = 0;
+            for n in [1, 2, 32, 13, 7] {
+                hl.step(n);
+                for _ in 0..n {
+                    grid.apply_rule(&rule);
+                }
+                generation += n;
+                assert_eq!(hl.generation(), generation);
+                assert_eq!(hl.to_grid(IVec2::ZERO, grid.size).data, grid.data);
+            }
+        }
+    }
+
+    #[test]
+    fn step3() {
+        let mut small = Grid3::new(UVec3::splat(8));
+        small.fill_rand(0.4, StdRng::seed_from_u64(42));
+        let mut dense = Grid3::new(UVec3::splat(48));
+        dense.fill(false);
+        for z in 0..8 {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let pos = IVec3::new(x, y, z);
+                    dense.set_cell(pos + 20, small.cell(pos).unwrap());
+                }
+            }
+        }
+
+        for rule in [
+            Rule3::SMOOTH,
+            "S2-6/B4/M".parse().unwrap(),
+            "S1-6/B1,3/N".parse().unwrap(),
+        ] {
+            let mut grid = dense.clone();
+            let mut hl = HashLife3::from_grid(&grid, rule);
+            for n in [1, 4, 3] {
+                hl.step(n);
+                for _ in 0..n {
+                    grid.apply_rule(&rule);
+                }
+                assert_eq!(hl.to_grid(IVec3::ZERO, grid.size).data, grid.data);
+            }
+        }
+    }
+
+    #[test]
+    fn collect_garbage() {
+        let mut grid = Grid2::new(UVec2::splat(32));
+        grid.fill_rand(0.4, StdRng::seed_from_u64(42));
+        let life: Rule2 = "B3/S23".parse().unwrap();
+        let mut hl = HashLife2::from_grid(&grid, life);
+        hl.step(100);
+        let before = hl.to_grid(IVec2::splat(-128), UVec2::splat(288));
+
+        let node_count = hl.universe.tree.nodes.len();
+        hl.collect_garbage();
+        assert!(hl.universe.tree.nodes.len() < node_count);
+        assert!(hl.universe.tree.results.is_empty());
+        assert_eq!(
+            hl.to_grid(IVec2::splat(-128), UVec2::splat(288)).data,
+            before.data
+        );
+
+        // Stepping again after collection gives the same result
+        let mut other = hl.clone();
+        hl.step(50);
+        other.step(25);
+        other.step(25);
+        let a = hl.to_grid(IVec2::splat(-128), UVec2::splat(288));
+        let b = other.to_grid(IVec2::splat(-128), UVec2::splat(288));
+        assert_eq!(a.data, b.data);
+    }
+
+    #[test]
+    #[should_panic]
+    fn birth_on_zero() {
+        HashLife3::new(Rule3::new(0u8..=0u8, 0u8..=0u8));
+    }
+}
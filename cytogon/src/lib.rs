@@ -1,12 +1,127 @@
 use std::ops::{Range, RangeInclusive};
 
-pub use glam::{IVec2, IVec3, UVec2, UVec3};
+pub use glam::{IVec2, IVec3, UVec2, UVec3, Vec2, Vec3, Vec4};
 use rand::{Rng, RngCore};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 #[cfg(feature = "trace")]
 use tracing::info_span;
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod avx2;
+mod bitslice;
+mod contour;
+mod decimate;
+mod extrude;
+mod generations;
+mod gltf;
+mod greedy;
+mod grid_file;
+mod hashlife;
+mod manifold;
+mod marching_cubes;
+mod mesh;
+mod notation;
+mod obj;
+mod ply;
+mod points;
+mod postprocess;
+mod simulation;
+mod sparse;
+mod stl;
+mod surface_nets;
+mod triangulate;
+
+pub use contour::{Contour, ContourOptions, Polygon};
+pub use decimate::DecimateOptions;
+pub use extrude::ExtrudeOptions;
+pub use generations::{GenerationsGrid2, GenerationsGrid3, GenerationsRule2, GenerationsRule3};
+pub use gltf::{GltfOptions, GltfScene};
+pub use grid_file::{GridFileError, GridMetadata};
+pub use hashlife::{HashLife2, HashLife3};
+pub use manifold::MeshValidity;
+pub use mesh::{Mesh, Mesh2, MeshOptions};
+pub use notation::ParseRuleError;
+pub use obj::{ObjMaterial, ObjOptions};
+pub use ply::PlyOptions;
+pub use points::{PointCloud, PointCloudOptions, PointValues};
+pub use postprocess::SmoothOptions;
+pub use simulation::{Simulation2, Simulation3};
+pub use sparse::{SparseGrid2, SparseGrid3};
+pub use stl::StlOptions;
+
+/// Neighborhood of a cell, that is the set of surrounding cells counted as its
+/// neighbors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Neighborhood {
+    /// Moore neighborhood, made of all cells sharing a face, an edge, or a
+    /// corner with the cell. There are 8 such neighbors in 2D, and 26 in 3D.
+    #[default]
+    Moore,
+    /// Von Neumann neighborhood, made of only the cells sharing a face with the
+    /// cell. There are 4 such neighbors in 2D, and 6 in 3D.
+    VonNeumann,
+}
+
+impl Neighborhood {
+    /// Maximum number of neighbors of a cell in 2D.
+    pub const fn max_count2(self) -> u8 {
+        match self {
+            Self::Moore => 8,
+            Self::VonNeumann => 4,
+        }
+    }
+
+    /// Maximum number of neighbors of a cell in 3D.
+    pub const fn max_count3(self) -> u8 {
+        match self {
+            Self::Moore => 26,
+            Self::VonNeumann => 6,
+        }
+    }
+}
+
+/// Boundary condition of a grid.
+///
+/// The boundary condition defines the state of the virtual cells outside the
+/// grid, which are counted as neighbors of the cells on the edges of the grid
+/// when applying a rule.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Boundary {
+    /// All cells outside the grid are dead.
+    #[default]
+    Dead,
+    /// All cells outside the grid are alive. This acts as a closed wall around
+    /// the grid, and is useful to ensure the resulting geometry is closed.
+    Alive,
+    /// The grid wraps around, like a torus. Cells outside the grid take the
+    /// state of the cell on the opposite side of the grid. This is useful to
+    /// generate seamlessly tiling patterns.
+    Periodic,
+    /// The grid is reflected on its edges. Cells outside the grid take the
+    /// state of the cell symmetric to them with respect to the edge, so that
+    /// the cell just outside the grid takes the state of the edge cell.
+    Mirror,
+}
+
+impl Boundary {
+    /// Map a coordinate outside the grid along an axis of the given size to the
+    /// coordinate of the cell inside the grid it takes its state from.
+    ///
+    /// Returns `None` for constant boundary conditions.
+    #[inline]
+    fn wrap(self, c: i32, size: u32) -> Option<i32> {
+        let size = size as i32;
+        match self {
+            Self::Dead | Self::Alive => None,
+            Self::Periodic => Some(c.rem_euclid(size)),
+            Self::Mirror => {
+                let c = c.rem_euclid(2 * size);
+                Some(if c < size { c } else { 2 * size - 1 - c })
+            }
+        }
+    }
+}
 
 /// Bitset encoding a rule for a 2D cellular automaton.
 ///
@@ -17,27 +132,31 @@ mod avx2;
 ///
 /// # Construction
 ///
-/// A new [`RuleBiset2`] can be constructed from:
+/// A new [`RuleBitset2`] can be constructed from:
 /// - Another instance (`Copy`).
 /// - A `u16` bit representation.
 ///
 ///   ```
-///   let r = RuleBitset2::from(0xF07u32);
+///   # use cytogon::RuleBitset2;
+///   let r = RuleBitset2::from(0x107u16);
 ///   ```
 /// - An array of exactly 9 `bool`.
 ///
 ///   ```
+///   # use cytogon::RuleBitset2;
 ///   let r = RuleBitset2::from([true; 9]);
 ///   ```
 /// - A slice of at most 9 `bool` (all missing elements are assumed `false`).
 ///
 ///   ```
-///   let r = RuleBitset2::from(&[true; 5]);
+///   # use cytogon::RuleBitset2;
+///   let r = RuleBitset2::from(&[true; 5][..]);
 ///   ```
 /// - A `Range<u8>` or `RangeInclusive<u8>` of length up to 9, describing the
 ///   `true` values.
 ///
 ///   ```
+///   # use cytogon::RuleBitset2;
 ///   let r = RuleBitset2::from(3u8..8u8);
 ///   let r = RuleBitset2::from(3u8..=7u8);
 ///   ```
@@ -45,6 +164,7 @@ mod avx2;
 /// - By combining 2 existing rules via the bitwise OR `|` operator.
 ///
 ///   ```
+///   # use cytogon::RuleBitset2;
 ///   let r1 = RuleBitset2::from(1u8..4u8);
 ///   let r2 = RuleBitset2::from(7u8..=8u8);
 ///   let r = r1 | r2;
@@ -169,12 +289,25 @@ impl std::ops::BitOr for RuleBitset2 {
 }
 
 /// 2D cellular automaton rule.
+///
+/// A rule can be parsed from a rule string like `B3/S23` or `S4-8/B5-8/2/M`,
+/// and formatted back with [`Display`](std::fmt::Display). See
+/// [`ParseRuleError`] for the errors reported on invalid strings.
+///
+/// ```
+/// # use cytogon::Rule2;
+/// let rule: Rule2 = "S4-8/B5-8".parse().unwrap();
+/// assert_eq!(rule, Rule2::SMOOTH);
+/// assert_eq!(rule.to_string(), "S4-8/B5-8/2/M");
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rule2 {
     /// Birth rule, applied to dead cells to determine if they become alive.
     pub birth: RuleBitset2,
     /// Survive rule, applied to alive cells to determine if they remain alive.
     pub survive: RuleBitset2,
+    /// Neighborhood of each cell, used to count its neighbors.
+    pub neighborhood: Neighborhood,
 }
 
 impl Rule2 {
@@ -182,15 +315,46 @@ impl Rule2 {
     pub const SMOOTH: Rule2 = Rule2 {
         birth: RuleBitset2::from_bits(0x1E0u16),   // 5..=8
         survive: RuleBitset2::from_bits(0x1F0u16), // 4..=8
+        neighborhood: Neighborhood::Moore,
     };
 
-    /// Create a CA rule from a pair of birth and survive rules.
+    /// Create a CA rule with a Moore neighborhood from a pair of birth and
+    /// survive rules.
     pub fn new(birth: impl Into<RuleBitset2>, survive: impl Into<RuleBitset2>) -> Self {
         Self {
             birth: birth.into(),
             survive: survive.into(),
+            neighborhood: Neighborhood::Moore,
         }
     }
+
+    /// Change the neighborhood of the rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the birth or survive rules contain a neighbor count larger
+    /// than the maximum number of neighbors in the new neighborhood.
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.neighborhood = neighborhood;
+        assert!(
+            self.is_valid(),
+            "Rule neighbor count exceeds the maximum of {} neighbors.",
+            neighborhood.max_count2()
+        );
+        self
+    }
+
+    /// Check that the birth and survive rules only contain neighbor counts
+    /// possible in the [`neighborhood`] of the rule.
+    ///
+    /// Rules changed with [`with_neighborhood()`] are always valid, but the
+    /// check is bypassed when setting the fields directly.
+    ///
+    /// [`neighborhood`]: Self::neighborhood
+    /// [`with_neighborhood()`]: Self::with_neighborhood
+    pub fn is_valid(&self) -> bool {
+        (self.birth.to_bits() | self.survive.to_bits()) >> (self.neighborhood.max_count2() + 1) == 0
+    }
 }
 
 /// 2D cellular automaton grid.
@@ -201,17 +365,32 @@ impl Rule2 {
 pub struct Grid2 {
     /// Grid size, in number of cells.
     pub size: UVec2,
+    /// Boundary condition applied to the edges of the grid when applying a
+    /// rule.
+    pub boundary: Boundary,
     /// Bitblocks encoding the state of all cells in the grid.
     ///
     /// The bitblocks are laid out in X-major order, that is all X blocks for
     /// Y=0, then all X blocks for Y=1, etc. There are
     /// [`Self::get_bitblock_count`]`(`[`Self::size`]`)` blocks.
+    ///
+    /// Each bitblock encodes a 8x8 block of cells, with one row of 8 cells per
+    /// byte. The lowest bit is the cell at (0,0), the next one (1,0), etc. and
+    /// the highest bit is the cell at (7,7).
     pub data: Vec<u64>,
 }
 
 impl Grid2 {
+    /// Create a new empty grid of the given size.
+    ///
+    /// To save on allocations, the grid is not allocated until one of the
+    /// [`fill()`] or [`fill_rand()`] functions are called.
     pub fn new(size: UVec2) -> Self {
-        Self { size, data: vec![] }
+        Self {
+            size,
+            boundary: Boundary::Dead,
+            data: vec![],
+        }
     }
 
     /// Get the number of bit blocks to allocate for a given grid size.
@@ -223,6 +402,47 @@ impl Grid2 {
         size.x.div_ceil(8) as usize * size.y.div_ceil(8) as usize
     }
 
+    /// Get the number of bit blocks along each axis for a given grid size.
+    #[inline]
+    fn get_bitblock_dims(size: UVec2) -> UVec2 {
+        (size + 7) / 8
+    }
+
+    /// Get the mask of the bits of a bit block which correspond to cells inside
+    /// the grid.
+    ///
+    /// When the grid size is not a multiple of 8, the blocks on the X+ and Y+
+    /// edges of the grid are only partially used. The unused padding bits are
+    /// always kept dead, so they never contribute to neighbor counts.
+    fn get_bitblock_mask(size: UVec2, bpos: UVec2) -> u64 {
+        let n = (size - bpos * 8).min(UVec2::splat(8));
+        if n == UVec2::splat(8) {
+            return !0u64;
+        }
+        let row = (1u64 << n.x) - 1;
+        let mut mask = 0;
+        for y in 0..n.y {
+            mask |= row << (y * 8);
+        }
+        mask
+    }
+
+    /// Clear all padding bits of the bit blocks on the edges of the grid.
+    fn clear_padding(&mut self) {
+        let dims = Self::get_bitblock_dims(self.size);
+        if self.size == dims * 8 {
+            return;
+        }
+        let mut ib = 0;
+        for by in 0..dims.y {
+            for bx in 0..dims.x {
+                let bpos = UVec2::new(bx, by);
+                self.data[ib] &= Self::get_bitblock_mask(self.size, bpos);
+                ib += 1;
+            }
+        }
+    }
+
     /// Fill the grid with the given `value`.
     pub fn fill(&mut self, value: bool) {
         #[cfg(feature = "trace")]
@@ -230,7 +450,9 @@ impl Grid2 {
 
         let size = Self::get_bitblock_count(self.size);
         let value = if value { !0u64 } else { 0 };
+        self.data.clear();
         self.data.resize(size, value);
+        self.clear_padding();
     }
 
     /// Fill the grid with random values.
@@ -243,81 +465,374 @@ impl Grid2 {
 
         let capacity = Self::get_bitblock_count(self.size);
         self.data = fill_rand(capacity, fill_ratio, &mut prng);
+        self.clear_padding();
     }
 
-    #[inline]
-    pub fn cell(&self, pos: IVec2) -> Option<bool> {
+    /// Resolve the position of a cell in the grid to its array index and bit.
+    fn resolve(&self, pos: IVec2) -> Option<(usize, u8)> {
         if pos.x < 0 || pos.y < 0 || pos.x as u32 >= self.size.x || pos.y as u32 >= self.size.y {
             None
         } else {
-            let index = pos.y as u32 * self.size.x + pos.x as u32;
-            let i0 = index >> 6;
-            let i1 = 1u64 << (index & 0x3F);
-            Some(self.data[i0 as usize] & i1 != 0)
+            let dims = Self::get_bitblock_dims(self.size);
+            let index = (pos.y / 8) as u32 * dims.x + (pos.x / 8) as u32;
+            let bit = (pos.x as u8 & 0x7) | ((pos.y as u8 & 0x7) << 3);
+            Some((index as usize, bit))
         }
     }
 
+    /// Resolve the position of a cell in the grid to its array index and bit.
     #[inline]
-    pub fn set_cell(&mut self, pos: IVec2, value: bool) {
-        if pos.x < 0 || pos.y < 0 || pos.x as u32 >= self.size.x || pos.y as u32 >= self.size.y {
+    fn resolve_bit(&self, pos: IVec2) -> Option<(usize, u64)> {
+        self.resolve(pos).map(|(index, bit)| (index, 1u64 << bit))
+    }
+
+    #[inline]
+    pub fn cell(&self, pos: IVec2) -> Option<bool> {
+        if let Some((index, bit)) = self.resolve_bit(pos) {
+            Some(self.data[index] & bit != 0)
         } else {
-            let index = pos.y as u32 * self.size.x + pos.x as u32;
-            let i0 = index >> 6;
-            let i1 = (1u64 as u64) << (index & 0x3F);
+            None
+        }
+    }
+
+    #[inline]
+    pub fn set_cell(&mut self, pos: IVec2, value: bool) {
+        if let Some((index, bit)) = self.resolve_bit(pos) {
             if value {
-                self.data[i0 as usize] |= i1;
+                self.data[index] |= bit;
             } else {
-                self.data[i0 as usize] &= !i1;
+                self.data[index] &= !bit;
             }
         }
     }
 
+    /// Apply the given cellular automaton rule once to the entire grid.
+    ///
+    /// The rule must be [valid] for its neighborhood, which is only checked in
+    /// debug builds.
+    ///
+    /// [valid]: Rule2::is_valid
     pub fn apply_rule(&mut self, rule: &Rule2) {
+        let mut next = vec![];
+        self.step_into(rule, &mut next, &mut Scratch2::default());
+        self.data = next;
+    }
+
+    /// Compute the next state of the grid after applying the given cellular
+    /// automaton rule once, and write its bitblocks into `next`.
+    ///
+    /// The `scratch` buffers are used to store the neighbor counts. All buffers
+    /// are resized as needed, and only allocate if too small.
+    pub(crate) fn step_into(&self, rule: &Rule2, next: &mut Vec<u64>, scratch: &mut Scratch2) {
         #[cfg(feature = "trace")]
         let _span = info_span!("apply_rule2").entered();
+        debug_assert!(
+            rule.is_valid(),
+            "Rule neighbor count exceeds the neighborhood."
+        );
+
+        let Scratch2 { planes, row } = scratch;
+        self.count_neighbors_planes_into(rule.neighborhood, self.boundary, planes, row);
+        next.resize(self.data.len(), 0);
+        let dims = Self::get_bitblock_dims(self.size);
+        let birth = rule.birth.to_bits();
+        let survive = rule.survive.to_bits();
+        let mut ib = 0;
+        for by in 0..dims.y {
+            for bx in 0..dims.x {
+                let mask = Self::get_bitblock_mask(self.size, UVec2::new(bx, by));
+                let b = Self::match_counts(&planes[ib], birth);
+                let s = Self::match_counts(&planes[ib], survive);
+                let cells = self.data[ib];
+                next[ib] = ((cells & s) | (!cells & b)) & mask;
+                ib += 1;
+            }
+        }
+    }
+
+    /// Reference single-threaded implementation of [`apply_rule()`]. Very slow.
+    pub fn apply_rule_ref(&mut self, rule: &Rule2) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("apply_rule2_ref").entered();
 
         let imax = self.size.x - 1;
         let jmax = self.size.y - 1;
-        let default = false;
         let old_grid = self.clone();
         let survive = rule.survive.to_array();
         let birth = rule.birth.to_array();
         for j in 0..=jmax {
             for i in 0..=imax {
                 let pos = IVec2::new(i as i32, j as i32);
-                if default && (i == 0 || j == 0 || i == imax || j == jmax) {
+                let c = old_grid.count_neighbors_single(pos, rule.neighborhood, self.boundary);
+                if self.cell(pos).unwrap_or(false) {
+                    if !survive[c as usize] {
+                        self.set_cell(pos, false);
+                    }
+                } else if birth[c as usize] {
                     self.set_cell(pos, true);
-                } else {
-                    let c = old_grid.count_neighbors(pos, default);
-                    if self.cell(pos).unwrap_or(false) {
-                        if !survive[c as usize] {
-                            self.set_cell(pos, false);
-                        }
+                }
+            }
+        }
+    }
+
+    /// Get the mask of the cells whose neighbor count, encoded in bit planes,
+    /// is one of the counts of the given rule bitset.
+    #[inline]
+    fn match_counts(planes: &[u64; 4], bits: u16) -> u64 {
+        let mut mask = 0;
+        for n in 0..=8 {
+            if bits & (1u16 << n) != 0 {
+                let mut eq = !0u64;
+                for (k, plane) in planes.iter().enumerate() {
+                    eq &= if (n >> k) & 1 != 0 { *plane } else { !*plane };
+                }
+                mask |= eq;
+            }
+        }
+        mask
+    }
+
+    /// Add a single bit per cell to the neighbor counts encoded in bit planes.
+    #[inline]
+    fn add_bit(planes: &mut [u64; 4], bits: u64) {
+        let mut carry = bits;
+        for plane in planes.iter_mut() {
+            let c = *plane & carry;
+            *plane ^= carry;
+            carry = c;
+        }
+    }
+
+    /// Count the neighbors of all cells of the grid, in bit planes.
+    ///
+    /// The count of each cell is encoded as a 4-bit value, with one bit in each
+    /// of 4 bit planes, so that all 64 cells of a bit block are counted in
+    /// parallel with plain bitwise operations. Plane `k` holds the `k`-th bit
+    /// of the counts of all cells of the block, using the same bit layout as
+    /// the block itself.
+    ///
+    /// For cells on the edges of the grid, the state of the virtual neighbors
+    /// outside the grid is defined by the `boundary` condition.
+    fn count_neighbors_planes(
+        &self,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+    ) -> Vec<[u64; 4]> {
+        let mut planes = vec![];
+        self.count_neighbors_planes_into(neighborhood, boundary, &mut planes, &mut vec![]);
+        planes
+    }
+
+    /// Variant of [`Self::count_neighbors_planes()`] writing into an existing
+    /// buffer, which is resized as needed. The `row` buffer is used as scratch
+    /// storage for the virtual cells outside the grid.
+    fn count_neighbors_planes_into(
+        &self,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+        planes: &mut Vec<[u64; 4]>,
+        row: &mut Vec<u8>,
+    ) {
+        let dims = Self::get_bitblock_dims(self.size).as_ivec2();
+        planes.resize(self.data.len(), [0u64; 4]);
+
+        // Get a block, or an empty one if outside the grid
+        let block = |bpos: IVec2| {
+            if bpos.cmpge(IVec2::ZERO).all() && bpos.cmplt(dims).all() {
+                self.data[(bpos.y * dims.x + bpos.x) as usize]
+            } else {
+                0u64
+            }
+        };
+
+        for by in 0..dims.y {
+            for bx in 0..dims.x {
+                // Shift rows of the 3 columns of blocks vertically. For each column,
+                // the "up" word holds the cells at Y-1 and the "down" word the cells
+                // at Y+1, taking the missing row from the adjacent block.
+                let mut rows = [[0u64; 3]; 3];
+                for (col, dx) in (-1..=1).enumerate() {
+                    let b = block(IVec2::new(bx + dx, by));
+                    let bm = block(IVec2::new(bx + dx, by - 1));
+                    let bp = block(IVec2::new(bx + dx, by + 1));
+                    rows[0][col] = (b << 8) | (bm >> 56);
+                    rows[1][col] = b;
+                    rows[2][col] = (b >> 8) | (bp << 56);
+                }
+
+                // Shift each row horizontally. The "left" word holds the cells at X-1
+                // and the "right" word the cells at X+1, taking the missing column
+                // from the adjacent block.
+                let mut acc = [0u64; 4];
+                for (dy, row) in rows.iter().enumerate() {
+                    let left = ((row[1] << 1) & 0xFEFE_FEFE_FEFE_FEFEu64)
+                        | ((row[0] >> 7) & 0x0101_0101_0101_0101u64);
+                    let right = ((row[1] >> 1) & 0x7F7F_7F7F_7F7F_7F7Fu64)
+                        | ((row[2] << 7) & 0x8080_8080_8080_8080u64);
+                    if dy == 1 {
+                        // Center row: self is not a neighbor
+                        Self::add_bit(&mut acc, left);
+                        Self::add_bit(&mut acc, right);
                     } else {
-                        if birth[c as usize] {
-                            self.set_cell(pos, true);
+                        Self::add_bit(&mut acc, row[1]);
+                        if neighborhood == Neighborhood::Moore {
+                            Self::add_bit(&mut acc, left);
+                            Self::add_bit(&mut acc, right);
                         }
+                    }
+                }
+                planes[(by * dims.x + bx) as usize] = acc;
+            }
+        }
+
+        self.count_boundary_neighbors(planes, neighborhood, boundary, row);
+    }
+
+    /// Add the virtual neighbors outside the grid to the counts of the cells on
+    /// the edges of the grid.
+    ///
+    /// The bit-parallel counting only counts neighbors inside the grid, which
+    /// is equivalent to a [`Boundary::Dead`] condition. This fixes up its
+    /// result for the other boundary conditions, one cell at a time. Each
+    /// virtual neighbor is counted by the edge of the grid along the first
+    /// axis it's outside of, from a row of virtual cells extending one cell
+    /// beyond the grid along Y for the X edges, so each virtual cell is read
+    /// once. The row is stored in the `row` buffer, which is resized as needed.
+    fn count_boundary_neighbors(
+        &self,
+        planes: &mut [[u64; 4]],
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+        row: &mut Vec<u8>,
+    ) {
+        if boundary == Boundary::Dead {
+            return;
+        }
+
+        let size = self.size.as_ivec2();
+        // Virtual cells are at most one cell outside the grid, which avoids the
+        // divisions of Boundary::wrap()
+        let wrap = |c: i32, size: i32| match boundary {
+            _ if (0..size).contains(&c) => c,
+            Boundary::Periodic if c < 0 => size - 1,
+            Boundary::Periodic => 0,
+            _ => c.clamp(0, size - 1),
+        };
+        let virtual_cell = |pos: IVec2| match boundary {
+            Boundary::Alive => 1,
+            _ => {
+                let pos = IVec2::new(wrap(pos.x, size.x), wrap(pos.y, size.y));
+                self.cell(pos).unwrap() as u8
+            }
+        };
+
+        for axis in 0..2 {
+            let other = 1 - axis;
+            let extend = (other > axis) as i32;
+            for side in [-1, size[axis]] {
+                // Row of virtual cells, with a margin of one cell on each side
+                row.clear();
+                row.resize(size[other] as usize + 2, 0u8);
+                let mut pos = IVec2::ZERO;
+                pos[axis] = side;
+                for q in -extend..size[other] + extend {
+                    pos[other] = q;
+                    row[(q + 1) as usize] = virtual_cell(pos);
+                }
+
+                pos[axis] = side.clamp(0, size[axis] - 1);
+                for p in 0..size[other] {
+                    pos[other] = p;
+                    let center = (p + 1) as usize;
+                    let count = match neighborhood {
+                        Neighborhood::Moore => row[center - 1] + row[center] + row[center + 1],
+                        Neighborhood::VonNeumann => row[center],
                     };
+                    if count == 0 {
+                        continue;
+                    }
+
+                    // Add to the count of the single cell
+                    let (index, bit) = self.resolve_bit(pos).unwrap();
+                    let planes = &mut planes[index];
+                    let mut c = 0;
+                    for (k, plane) in planes.iter().enumerate() {
+                        c |= ((*plane & bit != 0) as u8) << k;
+                    }
+                    c += count;
+                    for (k, plane) in planes.iter_mut().enumerate() {
+                        if (c >> k) & 1 != 0 {
+                            *plane |= bit;
+                        } else {
+                            *plane &= !bit;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count the number of alive neighbor cells of all cells of the grid.
+    ///
+    /// For cells on the edges of the grid, the state of the virtual neighbors
+    /// outside the grid is defined by the `boundary` condition.
+    ///
+    /// The counts are returned in the same layout as the bitblocks, that is
+    /// the count for the cell at bit `b` of block `i` is at index `i * 64 +
+    /// b`.
+    fn count_neighbors(&self, neighborhood: Neighborhood, boundary: Boundary) -> Vec<u8> {
+        let planes = self.count_neighbors_planes(neighborhood, boundary);
+        let mut counts = vec![0u8; planes.len() * 64];
+        for (ib, p) in planes.iter().enumerate() {
+            for bit in 0..64 {
+                let mut c = 0;
+                for (k, plane) in p.iter().enumerate() {
+                    c |= (((*plane >> bit) & 1) as u8) << k;
                 }
+                counts[ib * 64 + bit] = c;
             }
         }
+        counts
+    }
+
+    /// Get the state of a cell, which may be outside the grid.
+    ///
+    /// If the position is outside the grid, the state of the virtual cell is
+    /// defined by the `boundary` condition.
+    #[inline]
+    fn cell_or_boundary(&self, pos: IVec2, boundary: Boundary) -> bool {
+        if let Some(value) = self.cell(pos) {
+            return value;
+        }
+        match (
+            boundary.wrap(pos.x, self.size.x),
+            boundary.wrap(pos.y, self.size.y),
+        ) {
+            (Some(x), Some(y)) => self.cell(IVec2::new(x, y)).unwrap(),
+            _ => boundary == Boundary::Alive,
+        }
     }
 
     /// Count the number of alive neighbor cells at the given position.
     ///
-    /// If the position is on the edges of the grid, assume some neighbor exists
-    /// outside the grid with a value of `default`. For `default = false`, this
-    /// counts the actual neighbors without any change. For `default = true`,
-    /// this adds a virtual boundary condition around the grid. This is useful
-    /// to ensure the resulting geometry is closed.
-    fn count_neighbors(&self, pos: IVec2, default: bool) -> u8 {
+    /// If the position is on the edges of the grid, the state of the virtual
+    /// neighbors outside the grid is defined by the `boundary` condition.
+    fn count_neighbors_single(
+        &self,
+        pos: IVec2,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+    ) -> u8 {
         let mut count = 0;
         let mut xy = pos;
         for j in (pos.y - 1)..=(pos.y + 1) {
             xy.y = j;
             for i in (pos.x - 1)..=(pos.x + 1) {
                 xy.x = i;
-                if xy != pos && self.cell(xy).unwrap_or(default) {
+                if neighborhood == Neighborhood::VonNeumann && i != pos.x && j != pos.y {
+                    continue;
+                }
+                if xy != pos && self.cell_or_boundary(xy, boundary) {
                     count += 1;
                 }
             }
@@ -326,6 +841,64 @@ impl Grid2 {
     }
 }
 
+/// Scratch buffers for the neighbor counts of a [`Grid2`], reused across
+/// steps to avoid allocating.
+#[derive(Default, Clone)]
+pub(crate) struct Scratch2 {
+    /// Counts in bit planes.
+    planes: Vec<[u64; 4]>,
+    /// Virtual cells outside the grid, for the boundary conditions.
+    row: Vec<u8>,
+}
+
+/// Largest number of bit blocks of a [`Grid3`] stepped with the AVX2 backend.
+///
+/// Past about 200x200x200 cells, the 64 byte counts of each block no longer
+/// fit in the caches, and the bit-sliced backend, which moves 8 times less
+/// memory, becomes faster.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const AVX2_MAX_BLOCKS: usize = 1 << 17;
+
+/// Scratch buffers for the neighbor counts of a [`Grid3`], reused across
+/// steps to avoid allocating.
+#[derive(Default, Clone)]
+pub(crate) struct Scratch3 {
+    /// Byte counts, for the AVX2 backend.
+    counts: Vec<u8>,
+    /// Intermediate byte counts, for the AVX2 backend.
+    counts2: Vec<u8>,
+    /// Intermediate X and Y sums, for the bit-sliced backend.
+    planes_xy: Vec<[u64; 4]>,
+    /// Bit-sliced counts, for the bit-sliced backend.
+    planes: Vec<bitslice::Planes>,
+    /// Virtual cells outside the grid, for the boundary conditions.
+    layer: Vec<u8>,
+}
+
+/// Run a pass over a buffer of per-block values, split into Z slabs of
+/// `slab_len` values each.
+///
+/// The pass is called with a slice of the buffer and the Z block coordinate of
+/// the slab its first value belongs to. With the `parallel` feature, each slab
+/// is processed on its own task. Otherwise the pass is called once for the
+/// entire buffer.
+fn for_each_slab<T: Send>(data: &mut [T], slab_len: usize, pass: impl Fn(&mut [T], usize) + Sync) {
+    #[cfg(feature = "parallel")]
+    {
+        if slab_len > 0 {
+            data.par_chunks_mut(slab_len)
+                .enumerate()
+                .for_each(|(z, slab)| pass(slab, z));
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        let _ = slab_len;
+        pass(data, 0);
+    }
+}
+
 /// Bitset encoding a rule for a 3D cellular automaton.
 ///
 /// Each bit represents whether the associated rule applies to a cell with the
@@ -335,27 +908,31 @@ impl Grid2 {
 ///
 /// # Construction
 ///
-/// A new [`RuleBiset3`] can be constructed from:
+/// A new [`RuleBitset3`] can be constructed from:
 /// - Another instance (`Copy`).
 /// - A `u32` bit representation.
 ///
 ///   ```
+///   # use cytogon::RuleBitset3;
 ///   let r = RuleBitset3::from(0xF07u32);
 ///   ```
 /// - An array of exactly 27 `bool`.
 ///
 ///   ```
+///   # use cytogon::RuleBitset3;
 ///   let r = RuleBitset3::from([true; 27]);
 ///   ```
 /// - A slice of at most 27 `bool` (all missing elements are assumed `false`).
 ///
 ///   ```
-///   let r = RuleBitset3::from(&[true; 14]);
+///   # use cytogon::RuleBitset3;
+///   let r = RuleBitset3::from(&[true; 14][..]);
 ///   ```
 /// - A `Range<u8>` or `RangeInclusive<u8>` of length up to 27, describing the
 ///   `true` values.
 ///
 ///   ```
+///   # use cytogon::RuleBitset3;
 ///   let r = RuleBitset3::from(3u8..17u8);
 ///   let r = RuleBitset3::from(13u8..=16u8);
 ///   ```
@@ -363,6 +940,7 @@ impl Grid2 {
 /// - By combining 2 existing rules via the bitwise OR `|` operator.
 ///
 ///   ```
+///   # use cytogon::RuleBitset3;
 ///   let r1 = RuleBitset3::from(3u8..8u8);
 ///   let r2 = RuleBitset3::from(13u8..=16u8);
 ///   let r = r1 | r2;
@@ -505,12 +1083,26 @@ impl std::ops::BitOr for RuleBitset3 {
 }
 
 /// 3D cellular automaton rule.
+///
+/// A rule can be parsed from a rule string like `13-26/13-14,17-19/2/M` or
+/// `B13-14,17-19/S13-26`, and formatted back with
+/// [`Display`](std::fmt::Display). See [`ParseRuleError`] for the errors
+/// reported on invalid strings.
+///
+/// ```
+/// # use cytogon::Rule3;
+/// let rule: Rule3 = "13-26/13-14,17-19/2/M".parse().unwrap();
+/// assert_eq!(rule, Rule3::SMOOTH);
+/// assert_eq!(rule.to_string(), "S13-26/B13-14,17-19/2/M");
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rule3 {
     /// Birth rule, applied to dead cells to determine if they become alive.
     pub birth: RuleBitset3,
     /// Survive rule, applied to alive cells to determine if they remain alive.
     pub survive: RuleBitset3,
+    /// Neighborhood of each cell, used to count its neighbors.
+    pub neighborhood: Neighborhood,
 }
 
 impl Rule3 {
@@ -518,15 +1110,46 @@ impl Rule3 {
     pub const SMOOTH: Rule3 = Rule3 {
         birth: RuleBitset3::from_bits(0xE_6000u32), // 13..=14, 17..=19
         survive: RuleBitset3::from_bits(0x7FF_E000u32), // 13..=26
+        neighborhood: Neighborhood::Moore,
     };
 
-    /// Create a CA rule from a pair of birth and survive rules.
+    /// Create a CA rule with a Moore neighborhood from a pair of birth and
+    /// survive rules.
     pub fn new(birth: impl Into<RuleBitset3>, survive: impl Into<RuleBitset3>) -> Self {
         Self {
             birth: birth.into(),
             survive: survive.into(),
+            neighborhood: Neighborhood::Moore,
         }
     }
+
+    /// Change the neighborhood of the rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the birth or survive rules contain a neighbor count larger
+    /// than the maximum number of neighbors in the new neighborhood.
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.neighborhood = neighborhood;
+        assert!(
+            self.is_valid(),
+            "Rule neighbor count exceeds the maximum of {} neighbors.",
+            neighborhood.max_count3()
+        );
+        self
+    }
+
+    /// Check that the birth and survive rules only contain neighbor counts
+    /// possible in the [`neighborhood`] of the rule.
+    ///
+    /// Rules changed with [`with_neighborhood()`] are always valid, but the
+    /// check is bypassed when setting the fields directly.
+    ///
+    /// [`neighborhood`]: Self::neighborhood
+    /// [`with_neighborhood()`]: Self::with_neighborhood
+    pub fn is_valid(&self) -> bool {
+        (self.birth.to_bits() | self.survive.to_bits()) >> (self.neighborhood.max_count3() + 1) == 0
+    }
 }
 
 /// 3D cellular automaton grid.
@@ -537,6 +1160,9 @@ impl Rule3 {
 pub struct Grid3 {
     /// Grid size, in number of cells.
     pub size: UVec3,
+    /// Boundary condition applied to the edges of the grid when applying a
+    /// rule.
+    pub boundary: Boundary,
     /// Bitblocks encoding the state of all cells in the grid.
     ///
     /// The bitblocks are laid out in X-major and Z-minor order, that is all X
@@ -551,7 +1177,11 @@ impl Grid3 {
     /// To save on allocations, the grid is not allocated until one of the
     /// [`fill()`] or [`fill_rand()`] functions are called.
     pub fn new(size: UVec3) -> Self {
-        Self { size, data: vec![] }
+        Self {
+            size,
+            boundary: Boundary::Dead,
+            data: vec![],
+        }
     }
 
     /// Get the number of bit blocks to allocate for a given grid size.
@@ -563,6 +1193,56 @@ impl Grid3 {
         size.x.div_ceil(4) as usize * size.y.div_ceil(4) as usize * size.z.div_ceil(4) as usize
     }
 
+    /// Get the number of bit blocks along each axis for a given grid size.
+    #[inline]
+    fn get_bitblock_dims(size: UVec3) -> UVec3 {
+        (size + 3) / 4
+    }
+
+    /// Get the mask of the bits of a bit block which correspond to cells inside
+    /// the grid.
+    ///
+    /// When the grid size is not a multiple of 4, the blocks on the X+, Y+, and
+    /// Z+ edges of the grid are only partially used. The unused padding bits
+    /// are always kept dead, so they never contribute to neighbor counts.
+    fn get_bitblock_mask(size: UVec3, bpos: UVec3) -> u64 {
+        let n = (size - bpos * 4).min(UVec3::splat(4));
+        if n == UVec3::splat(4) {
+            return !0u64;
+        }
+        let row = (1u64 << n.x) - 1;
+        let mut mask = 0;
+        for z in 0..n.z {
+            for y in 0..n.y {
+                mask |= row << (y * 4 + z * 16);
+            }
+        }
+        mask
+    }
+
+    /// Clear all padding bits of the bit blocks on the edges of the grid.
+    fn clear_padding(&mut self) {
+        Self::clear_padding_blocks(self.size, &mut self.data[..]);
+    }
+
+    /// Clear all padding bits of the bit blocks of a grid of the given size.
+    fn clear_padding_blocks(size: UVec3, data: &mut [u64]) {
+        let dims = Self::get_bitblock_dims(size);
+        if size == dims * 4 {
+            return;
+        }
+        let mut ib = 0;
+        for bz in 0..dims.z {
+            for by in 0..dims.y {
+                for bx in 0..dims.x {
+                    let bpos = UVec3::new(bx, by, bz);
+                    data[ib] &= Self::get_bitblock_mask(size, bpos);
+                    ib += 1;
+                }
+            }
+        }
+    }
+
     /// Fill the grid with the given `value`.
     pub fn fill(&mut self, value: bool) {
         #[cfg(feature = "trace")]
@@ -570,7 +1250,9 @@ impl Grid3 {
 
         let size = Self::get_bitblock_count(self.size);
         let value = if value { !0u64 } else { 0 };
+        self.data.clear();
         self.data.resize(size, value);
+        self.clear_padding();
     }
 
     /// Fill the grid with random values.
@@ -583,6 +1265,7 @@ impl Grid3 {
 
         let capacity = Self::get_bitblock_count(self.size);
         self.data = fill_rand(capacity, fill_ratio, &mut prng);
+        self.clear_padding();
     }
 
     /// Resolve the position of a cell in the grid to its array index and bit.
@@ -596,8 +1279,9 @@ impl Grid3 {
         {
             None
         } else {
-            let index = (pos.z / 4) as u32 * (self.size.y / 4) * (self.size.x / 4)
-                + (pos.y / 4) as u32 * (self.size.x / 4)
+            let dims = Self::get_bitblock_dims(self.size);
+            let index = (pos.z / 4) as u32 * dims.y * dims.x
+                + (pos.y / 4) as u32 * dims.x
                 + (pos.x / 4) as u32;
             let bit = (pos.x as u8 & 0x3) | ((pos.y as u8 & 0x3) << 2) | ((pos.z as u8 & 0x3) << 4);
             Some((index as usize, bit))
@@ -631,71 +1315,282 @@ impl Grid3 {
     }
 
     /// Apply the given cellular automaton rule once to the entire grid.
+    ///
+    /// On x86 CPUs supporting AVX2, a vectorized implementation is selected at
+    /// runtime for the Moore neighborhood on grids of up to about 200x200x200
+    /// cells. Otherwise, a portable bit-sliced implementation processes the 64
+    /// cells of each bit block at once, and is faster on larger grids as it
+    /// moves less memory. With the `parallel` feature, the
+    /// neighbor counting and the rule application are split over all
+    /// available threads. In all cases the result is identical to the one of
+    /// [`apply_rule_ref()`].
+    ///
+    /// The rule must be [valid] for its neighborhood, which is only checked in
+    /// debug builds.
+    ///
+    /// [valid]: Rule3::is_valid
     pub fn apply_rule(&mut self, rule: &Rule3) {
-        // #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        // {
-        //     if is_x86_feature_detected!("avx2") {
-        //         return unsafe { avx2::apply_rule(self.size, &self.data[..], default)
-        // };     }
-        // }
+        let mut next = vec![];
+        self.step_into(rule, &mut next, &mut Scratch3::default());
+        self.data = next;
+    }
+
+    /// Compute the next state of the grid after applying the given cellular
+    /// automaton rule once, and write its bitblocks into `next`.
+    ///
+    /// The `scratch` buffers are used to store the neighbor counts. All buffers
+    /// are resized as needed, and only allocate if too small.
+    pub(crate) fn step_into(&self, rule: &Rule3, next: &mut Vec<u64>, scratch: &mut Scratch3) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("apply_rule3").entered();
+        debug_assert!(
+            rule.is_valid(),
+            "Rule neighbor count exceeds the neighborhood."
+        );
+
+        let block_count = Self::get_bitblock_dims(self.size);
+        let dz = block_count.x as usize * block_count.y as usize;
+        let birth = rule.birth.to_bits();
+        let survive = rule.survive.to_bits();
+        next.resize(self.data.len(), 0);
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if rule.neighborhood == Neighborhood::Moore
+            && self.data.len() <= AVX2_MAX_BLOCKS
+            && is_x86_feature_detected!("avx2")
+        {
+            let Scratch3 {
+                counts,
+                counts2,
+                layer,
+                ..
+            } = scratch;
+            unsafe { self.count_neighbors_avx2_m_into(self.boundary, counts, counts2, layer) };
+            for_each_slab(&mut next[..], dz, |slab, z| unsafe {
+                avx2::apply_rule(
+                    &self.data[z * dz..],
+                    slab,
+                    &counts[z * dz * 64..],
+                    birth,
+                    survive,
+                )
+            });
+            Self::clear_padding_blocks(self.size, next);
+            return;
+        }
 
-        self.apply_rule_ref(rule)
+        let Scratch3 {
+            planes_xy,
+            planes,
+            layer,
+            ..
+        } = scratch;
+        self.count_neighbors_bitsliced_into(
+            rule.neighborhood,
+            self.boundary,
+            planes_xy,
+            planes,
+            layer,
+        );
+        for_each_slab(&mut next[..], dz, |slab, z| {
+            bitslice::apply_rule(
+                &self.data[z * dz..],
+                slab,
+                &planes[z * dz..],
+                birth,
+                survive,
+            )
+        });
+        Self::clear_padding_blocks(self.size, next);
     }
 
     /// Reference single-threaded implementation of [`apply_rule()`]. Very slow.
     pub fn apply_rule_ref(&mut self, rule: &Rule3) {
         #[cfg(feature = "trace")]
-        let _span = info_span!("apply_rule3").entered();
+        let _span = info_span!("apply_rule3_ref").entered();
 
         let imax = self.size.x - 1;
         let jmax = self.size.y - 1;
         let kmax = self.size.z - 1;
-        let default = false;
-        let old_grid = self.clone();
-        let counts = old_grid.count_neighbors(default);
+        let counts = self.count_neighbors(rule.neighborhood, self.boundary);
         for k in 0..=kmax {
             for j in 0..=jmax {
                 for i in 0..=imax {
                     let pos = IVec3::new(i as i32, j as i32, k as i32);
-                    if default
-                        && (i == 0 || j == 0 || k == 0 || i == imax || j == jmax || k == kmax)
-                    {
-                        self.set_cell(pos, true);
+                    // 13-26/13-14,17-19/2/M
+                    let cell = self.cell(pos).unwrap();
+                    let (index, offset) = self.resolve(pos).unwrap();
+                    let c = counts[index * 64 + offset as usize];
+                    let b = 1u32 << c;
+                    if cell {
+                        self.set_cell(pos, rule.survive.to_bits() & b != 0);
                     } else {
-                        // 13-26/13-14,17-19/2/M
-                        let cell = self.cell(pos).unwrap();
-                        let (index, offset) = self.resolve(pos).unwrap();
-                        let c = counts[index * 64 + offset as usize];
-                        let b = 1u32 << c;
-                        if cell {
-                            self.set_cell(pos, rule.survive.to_bits() & b != 0);
-                        } else {
-                            self.set_cell(pos, rule.birth.to_bits() & b != 0);
-                        }
+                        self.set_cell(pos, rule.birth.to_bits() & b != 0);
                     }
                 }
             }
         }
     }
 
-    /// Count the number of alive neighbor cells at the given position.
+    /// Count the number of alive neighbor cells of all cells of the grid.
+    ///
+    /// For cells on the edges of the grid, the state of the virtual neighbors
+    /// outside the grid is defined by the `boundary` condition.
+    ///
+    /// The counts are returned in the same layout as the bitblocks, that is
+    /// the count for the cell at bit `b` of block `i` is at index `i * 64 +
+    /// b`.
+    fn count_neighbors(&self, neighborhood: Neighborhood, boundary: Boundary) -> Vec<u8> {
+        match neighborhood {
+            Neighborhood::Moore => {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if is_x86_feature_detected!("avx2") {
+                    return unsafe { self.count_neighbors_avx2_m(boundary) };
+                }
+
+                self.count_neighbors_separable_m(boundary)
+            }
+            Neighborhood::VonNeumann => self.count_neighbors_separable_vn(boundary),
+        }
+    }
+
+    /// Get the state of a cell, which may be outside the grid.
+    ///
+    /// If the position is outside the grid, the state of the virtual cell is
+    /// defined by the `boundary` condition.
+    #[inline]
+    fn cell_or_boundary(&self, pos: IVec3, boundary: Boundary) -> bool {
+        if let Some(value) = self.cell(pos) {
+            return value;
+        }
+        match (
+            boundary.wrap(pos.x, self.size.x),
+            boundary.wrap(pos.y, self.size.y),
+            boundary.wrap(pos.z, self.size.z),
+        ) {
+            (Some(x), Some(y), Some(z)) => self.cell(IVec3::new(x, y, z)).unwrap(),
+            _ => boundary == Boundary::Alive,
+        }
+    }
+
+    /// Add the virtual neighbors outside the grid to the counts of the cells on
+    /// the edges of the grid.
+    ///
+    /// The counting backends only count neighbors inside the grid, which is
+    /// equivalent to a [`Boundary::Dead`] condition. This fixes up their
+    /// result for the other boundary conditions. The `layer` buffer is used as
+    /// scratch storage for the virtual cells.
+    fn count_boundary_neighbors(
+        &self,
+        counts: &mut [u8],
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+        layer: &mut Vec<u8>,
+    ) {
+        self.for_each_boundary_count(neighborhood, boundary, layer, |index, offset, count| {
+            counts[index * 64 + offset as usize] += count;
+        });
+    }
+
+    /// Bit-sliced variant of [`Self::count_boundary_neighbors()`].
+    fn count_boundary_neighbors_planes(
+        &self,
+        planes: &mut [bitslice::Planes],
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+        layer: &mut Vec<u8>,
+    ) {
+        self.for_each_boundary_count(neighborhood, boundary, layer, |index, offset, count| {
+            bitslice::add_to_cell(&mut planes[index], offset, count);
+        });
+    }
+
+    /// Count the virtual neighbors outside the grid of each cell on the faces
+    /// of the grid, and call `add` with its block index, its bit offset in that
+    /// block, and the count. Cells on the edges and corners of the grid may be
+    /// reported several times.
     ///
-    /// If the position is on the edges of the grid, assume some neighbor exists
-    /// outside the grid with a value of `default`. For `default = false`, this
-    /// counts the actual neighbors without any change. For `default = true`,
-    /// this adds a virtual boundary condition around the grid. This is useful
-    /// to ensure the resulting geometry is closed.
-    fn count_neighbors(&self, default: bool) -> Vec<u8> {
-        // #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        // {
-        //     if is_x86_feature_detected!("avx2") {
-        //         return unsafe { avx2::count_neighbors(self.size, &self.data[..],
-        // default) };     }
-        // }
+    /// Each virtual neighbor is counted by the face of the grid along the first
+    /// axis it's outside of, from a layer of virtual cells extending one cell
+    /// beyond the grid along the next axes only. The layer is stored in the
+    /// `layer` buffer, which is resized as needed.
+    fn for_each_boundary_count(
+        &self,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+        layer: &mut Vec<u8>,
+        mut add: impl FnMut(usize, u8, u8),
+    ) {
+        if boundary == Boundary::Dead {
+            return;
+        }
+
+        let size = self.size.as_ivec3();
+        // Virtual cells are at most one cell outside the grid, which avoids the
+        // divisions of Boundary::wrap()
+        let wrap = |c: i32, size: i32| match boundary {
+            _ if (0..size).contains(&c) => c,
+            Boundary::Periodic if c < 0 => size - 1,
+            Boundary::Periodic => 0,
+            _ => c.clamp(0, size - 1),
+        };
+        let virtual_cell = |pos: IVec3| match boundary {
+            Boundary::Alive => 1,
+            _ => {
+                let pos = IVec3::new(
+                    wrap(pos.x, size.x),
+                    wrap(pos.y, size.y),
+                    wrap(pos.z, size.z),
+                );
+                self.cell(pos).unwrap() as u8
+            }
+        };
 
-        //self.count_neighbors_ref(default)
+        // This reads each virtual cell once, so the cost is proportional to the
+        // surface of the grid, but it's a scalar loop shared by all counting
+        // backends. On grids up to a few hundred cells wide, it can take as
+        // long as the bit-parallel counting itself.
+        for axis in 0..3 {
+            let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (eb, ec) = ((b > axis) as i32, (c > axis) as i32);
+            // Layer of virtual cells, with a margin of one cell on each side
+            let w = size[b] + 2;
+            for side in [-1, size[axis]] {
+                layer.clear();
+                layer.resize((w * (size[c] + 2)) as usize, 0u8);
+                let mut pos = IVec3::ZERO;
+                pos[axis] = side;
+                for qc in -ec..size[c] + ec {
+                    pos[c] = qc;
+                    for qb in -eb..size[b] + eb {
+                        pos[b] = qb;
+                        layer[((qc + 1) * w + qb + 1) as usize] = virtual_cell(pos);
+                    }
+                }
 
-        self.count_neighbors_separable_m(default)
+                pos[axis] = side.clamp(0, size[axis] - 1);
+                for pc in 0..size[c] {
+                    pos[c] = pc;
+                    for pb in 0..size[b] {
+                        pos[b] = pb;
+                        let center = ((pc + 1) * w + pb + 1) as usize;
+                        let count = match neighborhood {
+                            Neighborhood::Moore => {
+                                [center - w as usize, center, center + w as usize]
+                                    .iter()
+                                    .map(|i| layer[i - 1] + layer[*i] + layer[i + 1])
+                                    .sum()
+                            }
+                            Neighborhood::VonNeumann => layer[center],
+                        };
+                        if count > 0 {
+                            let (index, offset) = self.resolve(pos).unwrap();
+                            add(index, offset, count);
+                        }
+                    }
+                }
+            }
+        }
     }
 
     /// Convert the 8 lowest bits of the input to 8 bytes (lowest bit).
@@ -743,127 +1638,70 @@ impl Grid3 {
     }
 
     /// Count Moore 8-neighbors (or, 26 in 3D) with POPCNT.
-    pub(crate) fn count_neighbors_popcnt_m(&self, default: bool) -> Vec<u8> {
-        let block_count = self.size.as_ivec3() / 4;
+    pub(crate) fn count_neighbors_popcnt_m(&self, boundary: Boundary) -> Vec<u8> {
+        let block_count = Self::get_bitblock_dims(self.size).as_ivec3();
 
         // Over-allocate entire blocks to avoid having to bound-check the writes
         let capacity =
             block_count.x as usize * block_count.y as usize * block_count.z as usize * 64;
-        let mut counts = Vec::with_capacity(capacity);
-        counts.resize(capacity, 0);
+        let mut counts = vec![0; capacity];
 
-        // Loop over 2x2x2 chunks.
         let mut bpos = IVec3::ZERO;
         let dy = block_count.x as usize;
         let dz = (block_count.x * block_count.y) as usize;
         let mut ic = 0;
-        for (ib, b) in self.data.iter().enumerate() {
-            // If
+        for ib in 0..self.data.len() {
+            // Gather the 3x3x3 blocks around the current one, including itself. Blocks
+            // outside the grid are empty.
+            let mut blocks = [0u64; 27];
+            for z in -1..=1 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        let nb = bpos + IVec3::new(x, y, z);
+                        if nb.cmpge(IVec3::ZERO).all() && nb.cmplt(block_count).all() {
+                            let index = ib as isize
+                                + x as isize
+                                + y as isize * dy as isize
+                                + z as isize * dz as isize;
+                            blocks[((z + 1) * 9 + (y + 1) * 3 + (x + 1)) as usize] =
+                                self.data[index as usize];
+                        }
+                    }
+                }
+            }
 
-            let xm = if bpos.x > 0 { self.data[ib - 1] } else { 0u64 };
-            let xp = if bpos.x + 1 < block_count.x {
-                self.data[ib + 1]
-            } else {
-                0u64
-            };
-            let ym = if bpos.y > 0 { self.data[ib - dy] } else { 0u64 };
-            let yp = if bpos.y + 1 < block_count.y {
-                self.data[ib + dy]
-            } else {
-                0u64
-            };
-            let zm = if bpos.z > 0 { self.data[ib - dz] } else { 0u64 };
-            let zp = if bpos.z + 1 < block_count.z {
-                self.data[ib + dz]
-            } else {
-                0u64
-            };
-
-            // Accumulate within the block
-            let acc = [
-                (*b & 0x0000_0000_0033_0033u64).count_ones() as u8,
-                (*b & 0x0000_0000_0077_0077u64).count_ones() as u8,
-                (*b & 0x0000_0000_00EE_00EEu64).count_ones() as u8,
-                (*b & 0x0000_0000_00CC_00CCu64).count_ones() as u8,
-                //
-                (*b & 0x0000_0000_0333_0333u64).count_ones() as u8,
-                (*b & 0x0000_0000_0777_0777u64).count_ones() as u8,
-                (*b & 0x0000_0000_0EEE_0EEEu64).count_ones() as u8,
-                (*b & 0x0000_0000_0CCC_0CCCu64).count_ones() as u8,
-                //
-                (*b & 0x0000_0000_3330_3330u64).count_ones() as u8,
-                (*b & 0x0000_0000_7770_7770u64).count_ones() as u8,
-                (*b & 0x0000_0000_EEE0_EEE0u64).count_ones() as u8,
-                (*b & 0x0000_0000_CCC0_CCC0u64).count_ones() as u8,
-                //
-                (*b & 0x0000_0000_3300_3300u64).count_ones() as u8,
-                (*b & 0x0000_0000_7700_7700u64).count_ones() as u8,
-                (*b & 0x0000_0000_EE00_EE00u64).count_ones() as u8,
-                (*b & 0x0000_0000_CC00_CC00u64).count_ones() as u8,
-                // ----
-                (*b & 0x0000_0033_0033_0033u64).count_ones() as u8,
-                (*b & 0x0000_0077_0077_0077u64).count_ones() as u8,
-                (*b & 0x0000_00EE_00EE_00EEu64).count_ones() as u8,
-                (*b & 0x0000_00CC_00CC_00CCu64).count_ones() as u8,
-                //
-                (*b & 0x0000_0333_0333_0333u64).count_ones() as u8,
-                (*b & 0x0000_0777_0777_0777u64).count_ones() as u8,
-                (*b & 0x0000_0EEE_0EEE_0EEEu64).count_ones() as u8,
-                (*b & 0x0000_0CCC_0CCC_0CCCu64).count_ones() as u8,
-                //
-                (*b & 0x0000_3330_3330_3330u64).count_ones() as u8,
-                (*b & 0x0000_7770_7770_7770u64).count_ones() as u8,
-                (*b & 0x0000_EEE0_EEE0_EEE0u64).count_ones() as u8,
-                (*b & 0x0000_CCC0_CCC0_CCC0u64).count_ones() as u8,
-                //
-                (*b & 0x0000_3300_3300_3300u64).count_ones() as u8,
-                (*b & 0x0000_7700_7700_7700u64).count_ones() as u8,
-                (*b & 0x0000_EE00_EE00_EE00u64).count_ones() as u8,
-                (*b & 0x0000_CC00_CC00_CC00u64).count_ones() as u8,
-                // ----
-                (*b & 0x0033_0033_0033_0000u64).count_ones() as u8,
-                (*b & 0x0077_0077_0077_0000u64).count_ones() as u8,
-                (*b & 0x00EE_00EE_00EE_0000u64).count_ones() as u8,
-                (*b & 0x00CC_00CC_00CC_0000u64).count_ones() as u8,
-                //
-                (*b & 0x0333_0333_0333_0000u64).count_ones() as u8,
-                (*b & 0x0777_0777_0777_0000u64).count_ones() as u8,
-                (*b & 0x0EEE_0EEE_0EEE_0000u64).count_ones() as u8,
-                (*b & 0x0CCC_0CCC_0CCC_0000u64).count_ones() as u8,
-                //
-                (*b & 0x3330_3330_3330_0000u64).count_ones() as u8,
-                (*b & 0x7770_7770_7770_0000u64).count_ones() as u8,
-                (*b & 0xEEE0_EEE0_EEE0_0000u64).count_ones() as u8,
-                (*b & 0xCCC0_CCC0_CCC0_0000u64).count_ones() as u8,
-                //
-                (*b & 0x3300_3300_3300_0000u64).count_ones() as u8,
-                (*b & 0x7700_7700_7700_0000u64).count_ones() as u8,
-                (*b & 0xEE00_EE00_EE00_0000u64).count_ones() as u8,
-                (*b & 0xCC00_CC00_CC00_0000u64).count_ones() as u8,
-                // ----
-                (*b & 0x0033_0033_0000_0000u64).count_ones() as u8,
-                (*b & 0x0077_0077_0000_0000u64).count_ones() as u8,
-                (*b & 0x00EE_00EE_0000_0000u64).count_ones() as u8,
-                (*b & 0x00CC_00CC_0000_0000u64).count_ones() as u8,
-                //
-                (*b & 0x0333_0333_0000_0000u64).count_ones() as u8,
-                (*b & 0x0777_0777_0000_0000u64).count_ones() as u8,
-                (*b & 0x0EEE_0EEE_0000_0000u64).count_ones() as u8,
-                (*b & 0x0CCC_0CCC_0000_0000u64).count_ones() as u8,
-                //
-                (*b & 0x3330_3330_0000_0000u64).count_ones() as u8,
-                (*b & 0x7770_7770_0000_0000u64).count_ones() as u8,
-                (*b & 0xEEE0_EEE0_0000_0000u64).count_ones() as u8,
-                (*b & 0xCCC0_CCC0_0000_0000u64).count_ones() as u8,
-                //
-                (*b & 0x3300_3300_0000_0000u64).count_ones() as u8,
-                (*b & 0x7700_7700_0000_0000u64).count_ones() as u8,
-                (*b & 0xEE00_EE00_0000_0000u64).count_ones() as u8,
-                (*b & 0xCC00_CC00_0000_0000u64).count_ones() as u8,
-            ];
+            // Build 6 layers of 6x6 cells along Z, covering the current block and a
+            // 1-cell border around it. Each layer row packs 6 bits along X.
+            let mut layers = [0u64; 6];
+            for (lz, layer) in layers.iter_mut().enumerate() {
+                let (bz, z) = Self::split_cell(lz as i32 - 1);
+                for ly in 0..6 {
+                    let (by, y) = Self::split_cell(ly - 1);
+                    let shift = y * 4 + z * 16;
+                    let base = (bz * 9 + by * 3) as usize;
+                    let xm = (blocks[base] >> (shift + 3)) & 0x1;
+                    let x0 = (blocks[base + 1] >> shift) & 0xF;
+                    let xp = (blocks[base + 2] >> shift) & 0x1;
+                    let row = xm | (x0 << 1) | (xp << 5);
+                    *layer |= row << (ly * 6);
+                }
+            }
+
+            // Count the 3x3x3 cells around each cell with 3 POPCNT of a 3x3 mask, and
+            // remove self because we count only neighbors.
+            let mut acc = [0u8; 64];
+            for (bit, count) in acc.iter_mut().enumerate() {
+                let (x, y, z) = (bit & 0x3, (bit >> 2) & 0x3, bit >> 4);
+                let mask = 0x71C7u64 << (y * 6 + x);
+                let this = (layers[z + 1] >> ((y + 1) * 6 + x + 1)) & 0x1;
+                *count = ((layers[z] & mask).count_ones()
+                    + (layers[z + 1] & mask).count_ones()
+                    + (layers[z + 2] & mask).count_ones()
+                    - this as u32) as u8;
+            }
 
             // Copy counts into output array
-            counts[ic..ic + 64].copy_from_slice(bytemuck::cast_slice(&acc[..]));
+            counts[ic..ic + 64].copy_from_slice(&acc[..]);
             ic += 64;
 
             // Update block position
@@ -878,17 +1716,37 @@ impl Grid3 {
             }
         }
 
+        self.count_boundary_neighbors(&mut counts, Neighborhood::Moore, boundary, &mut vec![]);
+
         counts
     }
 
-    fn acc_x(src: &[u64], counts: &mut [u8], block_count: IVec3) {
-        debug_assert!(src.len() * 64 <= counts.len());
-        let mut bpos = IVec3::ZERO;
-        let mut ic = 0;
-        for (ib, b) in src.iter().enumerate() {
+    /// Split a cell coordinate relative to a block, in `-1..=4`, into the index
+    /// of the block containing it in `0..=2` (with the current block at index
+    /// 1) and the coordinate of the cell inside that block.
+    #[inline]
+    fn split_cell(c: i32) -> (i32, i32) {
+        if c < 0 {
+            (0, c + 4)
+        } else if c >= 4 {
+            (2, c - 4)
+        } else {
+            (1, c)
+        }
+    }
+
+    /// Accumulate the X neighbors of the blocks of `src` into `counts`.
+    ///
+    /// The `counts` slice covers all the blocks starting at the first block of
+    /// the Z slab `z`.
+    fn acc_x(src: &[u64], counts: &mut [u8], block_count: IVec3, z: usize) {
+        debug_assert!(counts.len() % 64 == 0);
+        let dz = block_count.x as usize * block_count.y as usize;
+        let mut bpos = IVec3::new(0, 0, z as i32);
+        for (ib, dst) in (z * dz..).zip(counts.chunks_exact_mut(64)) {
             // Decompress the current block into 8 x 8 bytes. Each byte has its lowest bit
             // set or not.
-            let b = Self::decompress_block(*b);
+            let b = Self::decompress_block(src[ib]);
 
             // X
             let mut acc = b;
@@ -925,8 +1783,7 @@ impl Grid3 {
             }
 
             // Copy counts into output array
-            counts[ic..ic + 64].copy_from_slice(bytemuck::cast_slice(&acc[..]));
-            ic += 64;
+            dst.copy_from_slice(bytemuck::cast_slice(&acc[..]));
 
             // Update block position
             bpos.x += 1;
@@ -941,12 +1798,17 @@ impl Grid3 {
         }
     }
 
-    fn acc_y(src: &[u8], dst: &mut [u8], block_count: IVec3) {
+    /// Accumulate the Y neighbors of the blocks of `src` into `dst`.
+    ///
+    /// The `dst` slice covers all the blocks starting at the first block of the
+    /// Z slab `z`.
+    fn acc_y(src: &[u8], dst: &mut [u8], block_count: IVec3, z: usize) {
         let dy = block_count.x;
-        let mut bpos = IVec3::ZERO;
-        let mut ic = 0;
-        for ib in 0..(src.len() / 64) {
-            let prev: &[[u64; 8]] = bytemuck::cast_slice(&src[..]);
+        let prev: &[[u64; 8]] = bytemuck::cast_slice(src);
+        let mut bpos = IVec3::new(0, 0, z as i32);
+        for (ib, dst) in
+            (z * block_count.x as usize * block_count.y as usize..).zip(dst.chunks_exact_mut(64))
+        {
             let b = prev[ib];
 
             // Y
@@ -994,8 +1856,7 @@ impl Grid3 {
             }
 
             // Copy counts into output array
-            dst[ic..ic + 64].copy_from_slice(bytemuck::cast_slice(&acc[..]));
-            ic += 64;
+            dst.copy_from_slice(bytemuck::cast_slice(&acc[..]));
 
             // Update block position
             bpos.x += 1;
@@ -1010,12 +1871,15 @@ impl Grid3 {
         }
     }
 
-    fn acc_z(src: &[u8], dst: &mut [u8], block_count: IVec3) {
+    /// Accumulate the Z neighbors of the blocks of `src` into `dst`.
+    ///
+    /// The `dst` slice covers all the blocks starting at the first block of the
+    /// Z slab `z`.
+    fn acc_z(src: &[u8], dst: &mut [u8], block_count: IVec3, z: usize) {
         let dz = block_count.x as usize * block_count.y as usize;
-        let mut bpos = IVec3::ZERO;
-        let mut ic = 0;
-        for ib in 0..(src.len() / 64) {
-            let prev: &[[u64; 8]] = bytemuck::cast_slice(&src[..]);
+        let prev: &[[u64; 8]] = bytemuck::cast_slice(src);
+        let mut bpos = IVec3::new(0, 0, z as i32);
+        for (ib, dst) in (z * dz..).zip(dst.chunks_exact_mut(64)) {
             let b = prev[ib];
 
             // Z
@@ -1052,8 +1916,7 @@ impl Grid3 {
             }
 
             // Copy counts into output array
-            dst[ic..ic + 64].copy_from_slice(bytemuck::cast_slice(&acc[..]));
-            ic += 64;
+            dst.copy_from_slice(bytemuck::cast_slice(&acc[..]));
 
             // Update block position
             bpos.x += 1;
@@ -1069,8 +1932,8 @@ impl Grid3 {
     }
 
     /// Count Moore 8-neighbors (or, 26 in 3D) with a separable sum.
-    pub(crate) fn count_neighbors_separable_m(&self, default: bool) -> Vec<u8> {
-        let block_count = (self.size.as_ivec3() + 3) / 4;
+    pub(crate) fn count_neighbors_separable_m(&self, boundary: Boundary) -> Vec<u8> {
+        let block_count = Self::get_bitblock_dims(self.size).as_ivec3();
 
         // Over-allocate entire blocks to avoid having to bound-check the writes
         let capacity =
@@ -1079,27 +1942,154 @@ impl Grid3 {
         counts.resize(capacity, 0);
         let mut counts2 = counts.clone();
 
-        // Separable sum over X then Y then Z
-        Self::acc_x(&self.data[..], &mut counts[..], block_count);
-        Self::acc_y(&counts, &mut counts2, block_count);
-        Self::acc_z(&counts2, &mut counts, block_count);
+        // Separable sum over X then Y then Z. Each pass only reads from the
+        // output of the previous one, so can be split over Z slabs.
+        let slab_len = block_count.x as usize * block_count.y as usize * 64;
+        for_each_slab(&mut counts[..], slab_len, |dst, z| {
+            Self::acc_x(&self.data[..], dst, block_count, z)
+        });
+        for_each_slab(&mut counts2[..], slab_len, |dst, z| {
+            Self::acc_y(&counts, dst, block_count, z)
+        });
+        for_each_slab(&mut counts[..], slab_len, |dst, z| {
+            Self::acc_z(&counts2, dst, block_count, z)
+        });
 
         // Remove self, because we count only neighbors
-        let mut ic = 0;
-        for b in self.data.iter() {
-            let b: [u8; 64] = bytemuck::cast(Self::decompress_block(*b));
-            for i in 0..64 {
-                counts[ic + i] -= b[i];
+        for_each_slab(&mut counts[..], slab_len, |dst, z| {
+            let blocks = &self.data[z * slab_len / 64..];
+            for (dst, b) in dst.chunks_exact_mut(64).zip(blocks) {
+                let b: [u8; 64] = bytemuck::cast(Self::decompress_block(*b));
+                for i in 0..64 {
+                    dst[i] -= b[i];
+                }
+            }
+        });
+
+        self.count_boundary_neighbors(&mut counts, Neighborhood::Moore, boundary, &mut vec![]);
+
+        counts
+    }
+
+    /// Count the neighbors of all cells of the grid, as bit-sliced counts.
+    ///
+    /// Each block of cells gets [`bitslice::PLANE_COUNT`] bit-planes, where
+    /// plane `k` holds the `k`-th bit of the count of each cell of the block,
+    /// in the same bit layout as the block itself. Moore neighbors are counted
+    /// with a separable sum, von Neumann neighbors with a direct sum.
+    #[cfg(test)]
+    fn count_neighbors_bitsliced(
+        &self,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+    ) -> Vec<bitslice::Planes> {
+        let mut planes = vec![];
+        self.count_neighbors_bitsliced_into(
+            neighborhood,
+            boundary,
+            &mut vec![],
+            &mut planes,
+            &mut vec![],
+        );
+        planes
+    }
+
+    /// Variant of [`Self::count_neighbors_bitsliced()`] writing into existing
+    /// buffers, which are resized as needed. The `planes_xy` buffer is used as
+    /// scratch storage for the intermediate sums, and the `layer` buffer for
+    /// the virtual cells outside the grid.
+    fn count_neighbors_bitsliced_into(
+        &self,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+        planes_xy: &mut Vec<[u64; 4]>,
+        planes: &mut Vec<bitslice::Planes>,
+        layer: &mut Vec<u8>,
+    ) {
+        let block_count = Self::get_bitblock_dims(self.size).as_ivec3();
+        let dz = block_count.x as usize * block_count.y as usize;
+        planes.resize(self.data.len(), [0u64; bitslice::PLANE_COUNT]);
+
+        match neighborhood {
+            Neighborhood::Moore => {
+                // Separable sum over X and Y, then Z
+                planes_xy.resize(self.data.len(), [0u64; 4]);
+                for_each_slab(&mut planes_xy[..], dz, |dst, z| {
+                    bitslice::acc_xy(&self.data[..], dst, block_count, z)
+                });
+                for_each_slab(&mut planes[..], dz, |dst, z| {
+                    bitslice::acc_z(&self.data[..], &planes_xy[..], dst, block_count, z)
+                });
+            }
+            Neighborhood::VonNeumann => {
+                for_each_slab(&mut planes[..], dz, |dst, z| {
+                    bitslice::count_vn(&self.data[..], dst, block_count, z)
+                });
             }
-            ic += 64;
         }
 
+        self.count_boundary_neighbors_planes(planes, neighborhood, boundary, layer);
+    }
+
+    /// Count Moore 8-neighbors (or, 26 in 3D) with a separable sum, using AVX2.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support the `avx2` target feature.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe fn count_neighbors_avx2_m(&self, boundary: Boundary) -> Vec<u8> {
+        let mut counts = vec![];
+        self.count_neighbors_avx2_m_into(boundary, &mut counts, &mut vec![], &mut vec![]);
         counts
     }
 
+    /// Variant of [`Self::count_neighbors_avx2_m()`] writing into an existing
+    /// buffer, which is resized as needed. The `counts2` buffer is used as
+    /// scratch storage for the intermediate sums, and the `layer` buffer for
+    /// the virtual cells outside the grid.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support the `avx2` target feature.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe fn count_neighbors_avx2_m_into(
+        &self,
+        boundary: Boundary,
+        counts: &mut Vec<u8>,
+        counts2: &mut Vec<u8>,
+        layer: &mut Vec<u8>,
+    ) {
+        let block_count = Self::get_bitblock_dims(self.size).as_ivec3();
+
+        // Over-allocate entire blocks to avoid having to bound-check the writes
+        let capacity =
+            block_count.x as usize * block_count.y as usize * block_count.z as usize * 64;
+        counts.resize(capacity, 0);
+        counts2.resize(capacity, 0);
+
+        // Separable sum over X then Y then Z
+        let slab_len = block_count.x as usize * block_count.y as usize * 64;
+        for_each_slab(&mut counts[..], slab_len, |dst, z| unsafe {
+            avx2::acc_x(&self.data[..], dst, block_count, z)
+        });
+        for_each_slab(&mut counts2[..], slab_len, |dst, z| unsafe {
+            avx2::acc_y(counts, dst, block_count, z)
+        });
+        for_each_slab(&mut counts[..], slab_len, |dst, z| unsafe {
+            avx2::acc_z(counts2, dst, block_count, z)
+        });
+
+        // Remove self, because we count only neighbors
+        for_each_slab(&mut counts[..], slab_len, |dst, z| unsafe {
+            avx2::remove_self(&self.data[z * slab_len / 64..], dst)
+        });
+
+        self.count_boundary_neighbors(counts, Neighborhood::Moore, boundary, layer);
+    }
+
     /// Count von Neumann 4-neighbors (or, 6 in 3D) with a separable sum.
-    pub(crate) fn count_neighbors_separable_vn(&self, default: bool) -> Vec<u8> {
-        let block_count = self.size.as_ivec3() / 4;
+    pub(crate) fn count_neighbors_separable_vn(&self, boundary: Boundary) -> Vec<u8> {
+        let block_count = Self::get_bitblock_dims(self.size).as_ivec3();
 
         // Over-allocate entire blocks to avoid having to bound-check the writes
         let capacity =
@@ -1108,108 +2098,103 @@ impl Grid3 {
         counts.resize(capacity, 0);
 
         // Separable sum over X then Y then Z
-        let mut bpos = IVec3::ZERO;
         let dy = block_count.x;
         let dz = block_count.x * block_count.y;
-        let mut ic = 0;
-        for (ib, b) in self.data.iter().enumerate() {
-            // Shifted X
-            let mut bxm = (b >> 1) & 0x7777_7777_7777_7777u64;
-            let mut bxp = (b << 1) & 0xEEEE_EEEE_EEEE_EEEEu64;
-            if bpos.x + 1 < block_count.x {
-                // Move upper bit from next block
-                let bp = (self.data[ib + 1] & 0x1111_1111_1111_1111u64) << 3;
-                bxm |= bp;
-            }
-            if bpos.x > 0 {
-                // Move lower bit from previous block
-                let bm = (self.data[ib - 1] & 0x8888_8888_8888_8888u64) >> 3;
-                bxp |= bm;
-            }
+        for_each_slab(&mut counts[..], dz as usize * 64, |counts, z| {
+            let mut bpos = IVec3::new(0, 0, z as i32);
+            for (ib, dst) in (z * dz as usize..).zip(counts.chunks_exact_mut(64)) {
+                let b = &self.data[ib];
+                // Shifted X
+                let mut bxm = (b >> 1) & 0x7777_7777_7777_7777u64;
+                let mut bxp = (b << 1) & 0xEEEE_EEEE_EEEE_EEEEu64;
+                if bpos.x + 1 < block_count.x {
+                    // Move upper bit from next block
+                    let bp = (self.data[ib + 1] & 0x1111_1111_1111_1111u64) << 3;
+                    bxm |= bp;
+                }
+                if bpos.x > 0 {
+                    // Move lower bit from previous block
+                    let bm = (self.data[ib - 1] & 0x8888_8888_8888_8888u64) >> 3;
+                    bxp |= bm;
+                }
 
-            // Shifted Y
-            let mut bym = (b >> 4) & 0x0FFF_0FFF_0FFF_0FFFu64;
-            let mut byp = (b << 4) & 0xFFF0_FFF0_FFF0_FFF0u64;
-            if bpos.y + 1 < block_count.y {
-                // Move upper bit from next block
-                let bp = (self.data[ib + dy as usize] & 0x000F_000F_000F_000Fu64) << 12;
-                bym |= bp;
-            }
-            if bpos.y > 0 {
-                // Move lower bit from previous block
-                let bm = (self.data[ib - dy as usize] & 0xF000_F000_F000_F000u64) >> 12;
-                byp |= bm;
-            }
+                // Shifted Y
+                let mut bym = (b >> 4) & 0x0FFF_0FFF_0FFF_0FFFu64;
+                let mut byp = (b << 4) & 0xFFF0_FFF0_FFF0_FFF0u64;
+                if bpos.y + 1 < block_count.y {
+                    // Move upper bit from next block
+                    let bp = (self.data[ib + dy as usize] & 0x000F_000F_000F_000Fu64) << 12;
+                    bym |= bp;
+                }
+                if bpos.y > 0 {
+                    // Move lower bit from previous block
+                    let bm = (self.data[ib - dy as usize] & 0xF000_F000_F000_F000u64) >> 12;
+                    byp |= bm;
+                }
 
-            // Shifted Z
-            let mut bzm = (b >> 16) & 0x0FFF_0FFF_0FFF_0FFFu64;
-            let mut bzp = (b << 16) & 0xFFF0_FFF0_FFF0_FFF0u64;
-            if bpos.z + 1 < block_count.z {
-                // Move upper bit from next block
-                let bp = (self.data[ib + dz as usize] & 0x0000_0000_0000_FFFFu64) << 48;
-                bzm |= bp;
-            }
-            if bpos.z > 0 {
-                // Move lower bit from previous block
-                let bm = (self.data[ib - dz as usize] & 0xFFFF_0000_0000_0000u64) >> 48;
-                bzp |= bm;
-            }
+                // Shifted Z
+                let mut bzm = b >> 16;
+                let mut bzp = b << 16;
+                if bpos.z + 1 < block_count.z {
+                    // Move upper bit from next block
+                    let bp = (self.data[ib + dz as usize] & 0x0000_0000_0000_FFFFu64) << 48;
+                    bzm |= bp;
+                }
+                if bpos.z > 0 {
+                    // Move lower bit from previous block
+                    let bm = (self.data[ib - dz as usize] & 0xFFFF_0000_0000_0000u64) >> 48;
+                    bzp |= bm;
+                }
 
-            // Accumulate
-            let mut acc = [0u64; 8];
-            for i in 0..8 {
-                let shift = i as u64 * 8;
-                let mask = 0xFFu64 << shift;
-                acc[i] += Self::bit_to_byte((bxm & mask) >> shift);
-                acc[i] += Self::bit_to_byte((bxp & mask) >> shift);
-                acc[i] += Self::bit_to_byte((bym & mask) >> shift);
-                acc[i] += Self::bit_to_byte((byp & mask) >> shift);
-                acc[i] += Self::bit_to_byte((bzm & mask) >> shift);
-                acc[i] += Self::bit_to_byte((bzp & mask) >> shift);
-
-                for j in 0..8 {
-                    counts[ic + 0] = (acc[i] & 0xFFu64) as u8;
-                    counts[ic + 1] = ((acc[i] & 0xFF00u64) >> 8) as u8;
-                    counts[ic + 2] = ((acc[i] & 0xFF_0000u64) >> 16) as u8;
-                    counts[ic + 3] = ((acc[i] & 0xFF00_0000u64) >> 24) as u8;
-                    counts[ic + 4] = ((acc[i] & 0xFF_0000_0000u64) >> 32) as u8;
-                    counts[ic + 5] = ((acc[i] & 0xFF00_0000_0000u64) >> 40) as u8;
-                    counts[ic + 6] = ((acc[i] & 0xFF_0000_0000_0000u64) >> 48) as u8;
-                    counts[ic + 7] = ((acc[i] & 0xFF00_0000_0000_0000u64) >> 56) as u8;
+                // Accumulate
+                let mut acc = [0u64; 8];
+                for (i, acc) in acc.iter_mut().enumerate() {
+                    let shift = i as u64 * 8;
+                    let mask = 0xFFu64 << shift;
+                    *acc += Self::bit_to_byte((bxm & mask) >> shift);
+                    *acc += Self::bit_to_byte((bxp & mask) >> shift);
+                    *acc += Self::bit_to_byte((bym & mask) >> shift);
+                    *acc += Self::bit_to_byte((byp & mask) >> shift);
+                    *acc += Self::bit_to_byte((bzm & mask) >> shift);
+                    *acc += Self::bit_to_byte((bzp & mask) >> shift);
                 }
-                ic += 8;
-            }
 
-            bpos.x += 1;
-            if bpos.x >= block_count.x {
-                bpos.x = 0;
-                bpos.y += 1;
-                if bpos.y >= block_count.y {
-                    bpos.y = 0;
-                    bpos.z += 1;
+                // Copy counts into output array
+                dst.copy_from_slice(bytemuck::cast_slice(&acc[..]));
+
+                bpos.x += 1;
+                if bpos.x >= block_count.x {
+                    bpos.x = 0;
+                    bpos.y += 1;
+                    if bpos.y >= block_count.y {
+                        bpos.y = 0;
+                        bpos.z += 1;
+                    }
                 }
             }
-        }
+        });
+
+        self.count_boundary_neighbors(&mut counts, Neighborhood::VonNeumann, boundary, &mut vec![]);
 
         counts
     }
 
-    fn count_neighbors_ref(&self, default: bool) -> Vec<u8> {
-        let capacity = self.size.x as usize * self.size.y as usize * self.size.z as usize;
-        let mut counts = Vec::with_capacity(capacity);
-        counts.resize(capacity, 0);
+    /// Reference single-threaded implementation of [`Self::count_neighbors()`].
+    /// Very slow.
+    fn count_neighbors_ref(&self, neighborhood: Neighborhood, boundary: Boundary) -> Vec<u8> {
+        let capacity = Self::get_bitblock_count(self.size) * 64;
+        let mut counts = vec![0; capacity];
 
         let mut pos = IVec3::ZERO;
-        for k in [0, self.size.z as i32 - 1] {
+        for k in 0..self.size.z as i32 {
             pos.z = k;
-            let index = k * self.size.y as i32 * self.size.x as i32;
             for j in 0..self.size.y as i32 {
-                pos.y = j as i32;
-                let index = index + j * self.size.x as i32;
+                pos.y = j;
                 for i in 0..self.size.x as i32 {
-                    pos.x = i as i32;
-                    let index = index + i;
-                    counts[index as usize] = self.count_neighbors_single(pos, default);
+                    pos.x = i;
+                    let (index, offset) = self.resolve(pos).unwrap();
+                    counts[index * 64 + offset as usize] =
+                        self.count_neighbors_single(pos, neighborhood, boundary);
                 }
             }
         }
@@ -1217,9 +2202,13 @@ impl Grid3 {
         counts
     }
 
-    /// Reference single-threaded implementation of [`count_neighbors()`]. Very
-    /// slow.
-    fn count_neighbors_single(&self, pos: IVec3, default: bool) -> u8 {
+    /// Count the number of alive neighbor cells of a single cell.
+    fn count_neighbors_single(
+        &self,
+        pos: IVec3,
+        neighborhood: Neighborhood,
+        boundary: Boundary,
+    ) -> u8 {
         let mut count = 0;
         let mut xyz = pos;
         for k in (pos.z - 1)..=(pos.z + 1) {
@@ -1228,7 +2217,13 @@ impl Grid3 {
                 xyz.y = j;
                 for i in (pos.x - 1)..=(pos.x + 1) {
                     xyz.x = i;
-                    if xyz != pos && self.cell(xyz).unwrap_or(default) {
+                    let offset = (xyz - pos).abs();
+                    if neighborhood == Neighborhood::VonNeumann
+                        && offset.x + offset.y + offset.z > 1
+                    {
+                        continue;
+                    }
+                    if xyz != pos && self.cell_or_boundary(xyz, boundary) {
                         count += 1;
                     }
                 }
@@ -1252,8 +2247,38 @@ fn fill_rand(capacity: usize, fill_ratio: f32, mut prng: impl RngCore) -> Vec<u6
     data
 }
 
+/// Temporary directory for tests writing files, removed with its content when
+/// dropped, even if the test panics.
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// Create an empty directory whose name is unique to `name` and the
+    /// current process.
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("cytogon-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Get the path of a file inside the directory.
+    pub(crate) fn join(&self, file: &str) -> std::path::PathBuf {
+        self.0.join(file)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
     use super::*;
 
     #[test]
@@ -1302,6 +2327,7 @@ mod tests {
         let rule = Rule2 {
             birth: (5u8..=8u8).into(),
             survive: (4u8..=8u8).into(),
+            neighborhood: Neighborhood::Moore,
         };
         assert_eq!(Rule2::SMOOTH, rule);
     }
@@ -1353,13 +2379,14 @@ mod tests {
         let rule = Rule3 {
             birth: RuleBitset3::from(13u8..=14u8) | (17u8..=19u8).into(),
             survive: (13u8..=26u8).into(),
+            neighborhood: Neighborhood::Moore,
         };
         assert_eq!(Rule3::SMOOTH, rule);
     }
 
-    fn index(pos: IVec3, size: UVec3) -> usize {
-        let index = pos.z as u32 * size.y * size.x + pos.y as u32 * size.x + pos.x as u32;
-        index as usize
+    fn index(pos: IVec3, grid: &Grid3) -> usize {
+        let (index, offset) = grid.resolve(pos).unwrap();
+        index * 64 + offset as usize
     }
 
     #[test]
@@ -1382,6 +2409,125 @@ mod tests {
         assert_eq!(grid.resolve_bit(IVec3::ONE * 7), Some((7, 1u64 << 63)));
     }
 
+    #[test]
+    fn resolve_partial() {
+        // 7x9x5 grid, that is 2x3x2 blocks
+        let size = UVec3::new(7, 9, 5);
+        let grid = Grid3::new(size);
+        assert_eq!(Grid3::get_bitblock_count(size), 12);
+        assert_eq!(grid.resolve_bit(IVec3::X * 4), Some((1, 1u64 << 0)));
+        assert_eq!(grid.resolve_bit(IVec3::Y * 4), Some((2, 1u64 << 0)));
+        assert_eq!(grid.resolve_bit(IVec3::Z * 4), Some((6, 1u64 << 0)));
+        assert_eq!(grid.resolve_bit(IVec3::new(6, 8, 4)), Some((11, 1u64 << 2)));
+        assert_eq!(grid.resolve_bit(IVec3::new(7, 8, 4)), None);
+    }
+
+    #[test]
+    fn fill_partial() {
+        let size = UVec3::new(7, 9, 5);
+        let mut grid = Grid3::new(size);
+        grid.fill(true);
+        let alive: u32 = grid.data.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(alive, 7 * 9 * 5);
+        assert_eq!(grid.data[0], !0u64);
+        assert_eq!(grid.data[11], 0x0000_0000_0000_0007u64);
+
+        grid.fill_rand(1.0, StdRng::seed_from_u64(42));
+        let alive: u32 = grid.data.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(alive, 7 * 9 * 5);
+
+        grid.fill(false);
+        assert!(grid.data.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn count_neighbors_partial() {
+        for size in [
+            UVec3::new(7, 9, 5),
+            UVec3::new(1, 2, 3),
+            UVec3::new(13, 4, 6),
+        ] {
+            let mut grid = Grid3::new(size);
+            grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+
+            for boundary in [
+                Boundary::Dead,
+                Boundary::Alive,
+                Boundary::Periodic,
+                Boundary::Mirror,
+            ] {
+                let ref_m = grid.count_neighbors_ref(Neighborhood::Moore, boundary);
+                let ref_vn = grid.count_neighbors_ref(Neighborhood::VonNeumann, boundary);
+                let m = grid.count_neighbors_separable_m(boundary);
+                let p = grid.count_neighbors_popcnt_m(boundary);
+                let vn = grid.count_neighbors_separable_vn(boundary);
+                // Compare only cells inside the grid; the padding counts are unused
+                let dims = Grid3::get_bitblock_dims(size);
+                for ib in 0..grid.data.len() {
+                    let bpos = UVec3::new(
+                        ib as u32 % dims.x,
+                        (ib as u32 / dims.x) % dims.y,
+                        ib as u32 / (dims.x * dims.y),
+                    );
+                    let mask = Grid3::get_bitblock_mask(size, bpos);
+                    for bit in 0..64 {
+                        if mask & (1u64 << bit) != 0 {
+                            let i = ib * 64 + bit;
+                            assert_eq!(m[i], ref_m[i]);
+                            assert_eq!(p[i], ref_m[i]);
+                            assert_eq!(vn[i], ref_vn[i]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn smooth3_partial() {
+        // Rule application never makes padding cells alive, even with birth on 0
+        let size = UVec3::new(5, 6, 7);
+        let mut grid = Grid3::new(size);
+        grid.fill(false);
+        grid.apply_rule(&Rule3::new(0u8..=0u8, 0u8..=26u8));
+        let alive: u32 = grid.data.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(alive, 5 * 6 * 7);
+
+        // Full grid shaves off edges, exactly like with a size multiple of 4
+        grid.apply_rule(&Rule3::SMOOTH);
+        for k in 0..7 {
+            for j in 0..6 {
+                for i in 0..5 {
+                    let value = grid.cell(IVec3::new(i, j, k)).unwrap();
+                    // Edges are on the border along at least 2 axes
+                    let borders = [i == 0 || i == 4, j == 0 || j == 5, k == 0 || k == 6];
+                    let edge = borders.iter().filter(|b| **b).count() >= 2;
+                    assert_eq!(value, !edge);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn apply_rule3_backends() {
+        // Several Z slabs of blocks, so the parallel path splits the work
+        let mut prng = StdRng::seed_from_u64(42);
+        let vn: Rule3 = "S1-6/B1,3/N".parse().unwrap();
+        for boundary in [Boundary::Dead, Boundary::Alive, Boundary::Periodic] {
+            for rule in [Rule3::SMOOTH, vn] {
+                let mut grid = Grid3::new(UVec3::new(13, 8, 23));
+                grid.boundary = boundary;
+                grid.fill_rand(0.5, &mut prng);
+                let mut grid_ref = grid.clone();
+                for _ in 0..3 {
+                    grid.apply_rule(&rule);
+                    grid_ref.apply_rule_ref(&rule);
+                    assert_eq!(grid.data, grid_ref.data);
+                }
+            }
+        }
+    }
+
     #[test]
     fn count_neighbors_separable() {
         // 8x8x8 grid (2x2x2 blocks)
@@ -1397,7 +2543,7 @@ mod tests {
             grid.set_cell(IVec3::ZERO, true);
 
             // Check counts
-            let counts = grid.count_neighbors_separable_m(false);
+            let counts = grid.count_neighbors_separable_m(Boundary::Dead);
             // All 26 Moore neighbors around (0,0,0)
             for i in [
                 // ------------------------------------------------------------- Z = 0
@@ -1426,7 +2572,7 @@ mod tests {
             grid.set_cell(IVec3::ONE, true);
 
             // Check counts
-            let counts = grid.count_neighbors_separable_m(false);
+            let counts = grid.count_neighbors_separable_m(Boundary::Dead);
             // All 26 Moore neighbors around (1,1,1)
             for i in [
                 // ------------------------------------- Z = 0
@@ -1458,7 +2604,7 @@ mod tests {
             grid.set_cell(IVec3::ONE * 3, true);
 
             // Check counts
-            let counts = grid.count_neighbors_separable_m(false);
+            let counts = grid.count_neighbors_separable_m(Boundary::Dead);
             // All 26 Moore neighbors around (3,3,3)
             let cells = [
                 // ------------------------------------- Z = 2
@@ -1495,6 +2641,349 @@ mod tests {
         }
     }
 
+    #[test]
+    fn count_neighbors_backends() {
+        // 8x8x8 grid (2x2x2 blocks) with random cells
+        let size = UVec3::ONE * 8;
+        let mut grid = Grid3::new(size);
+        grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+
+        for boundary in [
+            Boundary::Dead,
+            Boundary::Alive,
+            Boundary::Periodic,
+            Boundary::Mirror,
+        ] {
+            let ref_m = grid.count_neighbors_ref(Neighborhood::Moore, boundary);
+            let ref_vn = grid.count_neighbors_ref(Neighborhood::VonNeumann, boundary);
+            assert_eq!(grid.count_neighbors_separable_m(boundary), ref_m);
+            assert_eq!(grid.count_neighbors_popcnt_m(boundary), ref_m);
+            assert_eq!(grid.count_neighbors_separable_vn(boundary), ref_vn);
+        }
+    }
+
+    #[test]
+    fn apply_rule_bitsliced() {
+        let mut prng = StdRng::seed_from_u64(42);
+        for boundary in [Boundary::Dead, Boundary::Mirror] {
+            for rule in [
+                Rule3::SMOOTH,
+                Rule3::new(0u8..=26u8, 0u8..=0u8),
+                "S4,9,16,26/B0,15,17-18".parse().unwrap(),
+                "S0-2,5/B1,3/N".parse().unwrap(),
+            ] {
+                let mut grid = Grid3::new(UVec3::new(7, 9, 5));
+                grid.boundary = boundary;
+                grid.fill_rand(0.5, &mut prng);
+                let mut grid_ref = grid.clone();
+
+                let planes = grid.count_neighbors_bitsliced(rule.neighborhood, boundary);
+                let birth = rule.birth.to_bits();
+                let survive = rule.survive.to_bits();
+                let mut next = grid.data.clone();
+                bitslice::apply_rule(&grid.data, &mut next, &planes, birth, survive);
+                grid.data = next;
+                grid.clear_padding();
+
+                grid_ref.apply_rule_ref(&rule);
+                assert_eq!(grid.data, grid_ref.data);
+            }
+        }
+    }
+
+    #[test]
+    fn count_neighbors_bitsliced() {
+        let mut prng = StdRng::seed_from_u64(42);
+        for size in [
+            UVec3::ONE * 4,
+            UVec3::ONE * 8,
+            UVec3::new(7, 9, 5),
+            UVec3::new(13, 8, 23),
+        ] {
+            let mut grid = Grid3::new(size);
+            grid.fill_rand(0.5, &mut prng);
+            for boundary in [
+                Boundary::Dead,
+                Boundary::Alive,
+                Boundary::Periodic,
+                Boundary::Mirror,
+            ] {
+                for neighborhood in [Neighborhood::Moore, Neighborhood::VonNeumann] {
+                    let ref_counts = grid.count_neighbors_ref(neighborhood, boundary);
+                    let planes = grid.count_neighbors_bitsliced(neighborhood, boundary);
+                    for k in 0..size.z as i32 {
+                        for j in 0..size.y as i32 {
+                            for i in 0..size.x as i32 {
+                                let (index, bit) = grid.resolve(IVec3::new(i, j, k)).unwrap();
+                                assert_eq!(
+                                    bitslice::get_cell(&planes[index], bit),
+                                    ref_counts[index * 64 + bit as usize]
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Saturated counts
+        let mut grid = Grid3::new(UVec3::ONE * 8);
+        grid.fill(true);
+        let planes = grid.count_neighbors_bitsliced(Neighborhood::Moore, Boundary::Alive);
+        assert!(planes.iter().all(|p| *p == [0, !0, 0, !0, !0]));
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn count_neighbors_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let mut prng = StdRng::seed_from_u64(42);
+        for size in [
+            UVec3::ONE * 4,
+            UVec3::ONE * 8,
+            UVec3::new(7, 9, 5),
+            UVec3::new(13, 8, 23),
+        ] {
+            let mut grid = Grid3::new(size);
+            grid.fill_rand(0.5, &mut prng);
+            for boundary in [
+                Boundary::Dead,
+                Boundary::Alive,
+                Boundary::Periodic,
+                Boundary::Mirror,
+            ] {
+                let ref_m = grid.count_neighbors_ref(Neighborhood::Moore, boundary);
+                let m = unsafe { grid.count_neighbors_avx2_m(boundary) };
+                // Compare only cells inside the grid; the padding counts are unused
+                for k in 0..size.z as i32 {
+                    for j in 0..size.y as i32 {
+                        for i in 0..size.x as i32 {
+                            let index = index(IVec3::new(i, j, k), &grid);
+                            assert_eq!(m[index], ref_m[index]);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Saturated counts
+        let mut grid = Grid3::new(UVec3::ONE * 8);
+        grid.fill(true);
+        let ref_m = grid.count_neighbors_ref(Neighborhood::Moore, Boundary::Alive);
+        assert_eq!(
+            unsafe { grid.count_neighbors_avx2_m(Boundary::Alive) },
+            ref_m
+        );
+        assert_eq!(ref_m.iter().filter(|&&c| c == 26).count(), 512);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn apply_rule_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let mut prng = StdRng::seed_from_u64(42);
+        let mut grid = Grid3::new(UVec3::new(12, 8, 16));
+        for rule in [
+            Rule3::SMOOTH,
+            Rule3::new(0u8..=26u8, 0u8..=0u8),
+            "S4,9,16,26/B0,15,17-18".parse().unwrap(),
+        ] {
+            grid.fill_rand(0.5, &mut prng);
+            let counts = grid.count_neighbors_ref(Neighborhood::Moore, Boundary::Alive);
+            let planes = grid.count_neighbors_bitsliced(Neighborhood::Moore, Boundary::Alive);
+            let birth = rule.birth.to_bits();
+            let survive = rule.survive.to_bits();
+            let mut expected = grid.data.clone();
+            bitslice::apply_rule(&grid.data, &mut expected, &planes, birth, survive);
+            let mut next = grid.data.clone();
+            unsafe { avx2::apply_rule(&grid.data, &mut next, &counts, birth, survive) };
+            assert_eq!(next, expected);
+        }
+    }
+
+    #[test]
+    fn boundary_wrap() {
+        assert_eq!(Boundary::Dead.wrap(-1, 4), None);
+        assert_eq!(Boundary::Alive.wrap(4, 4), None);
+        assert_eq!(Boundary::Periodic.wrap(-1, 4), Some(3));
+        assert_eq!(Boundary::Periodic.wrap(4, 4), Some(0));
+        assert_eq!(Boundary::Mirror.wrap(-1, 4), Some(0));
+        assert_eq!(Boundary::Mirror.wrap(-2, 4), Some(1));
+        assert_eq!(Boundary::Mirror.wrap(4, 4), Some(3));
+        assert_eq!(Boundary::Mirror.wrap(5, 4), Some(2));
+    }
+
+    #[test]
+    fn boundary_single() {
+        // 4x4x4 grid with a single alive cell in the corner
+        let mut grid = Grid3::new(UVec3::ONE * 4);
+        grid.fill(false);
+        grid.set_cell(IVec3::ZERO, true);
+
+        let count =
+            |pos: IVec3, boundary| grid.count_neighbors_single(pos, Neighborhood::Moore, boundary);
+        // Periodic: the opposite corner wraps around to touch the alive cell
+        assert_eq!(count(IVec3::ONE * 3, Boundary::Dead), 0);
+        assert_eq!(count(IVec3::ONE * 3, Boundary::Periodic), 1);
+        // Mirror: the alive cell is reflected 7 times around the corner
+        assert_eq!(count(IVec3::ZERO, Boundary::Dead), 0);
+        assert_eq!(count(IVec3::ZERO, Boundary::Mirror), 7);
+        assert_eq!(count(IVec3::X, Boundary::Mirror), 4);
+        // Alive: all 19 cells outside the grid are alive
+        assert_eq!(count(IVec3::ZERO, Boundary::Alive), 19);
+    }
+
+    #[test]
+    fn periodic_blinker2() {
+        // Life blinker crossing the X edge of the grid
+        let mut grid = Grid2::new(UVec2::new(8, 8));
+        grid.boundary = Boundary::Periodic;
+        grid.fill(false);
+        for x in [7, 0, 1] {
+            grid.set_cell(IVec2::new(x, 4), true);
+        }
+
+        let life: Rule2 = "B3/S23".parse().unwrap();
+        grid.apply_rule(&life);
+        for j in 0..8 {
+            for i in 0..8 {
+                let expected = i == 0 && (3..=5).contains(&j);
+                assert_eq!(grid.cell(IVec2::new(i, j)), Some(expected));
+            }
+        }
+        grid.apply_rule(&life);
+        for j in 0..8 {
+            for i in 0..8 {
+                let expected = j == 4 && (i == 7 || i <= 1);
+                assert_eq!(grid.cell(IVec2::new(i, j)), Some(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn resolve2() {
+        let grid = Grid2::new(UVec2::new(19, 10));
+        assert_eq!(Grid2::get_bitblock_count(grid.size), 6);
+        assert_eq!(grid.resolve(IVec2::ZERO), Some((0, 0)));
+        assert_eq!(grid.resolve(IVec2::new(7, 0)), Some((0, 7)));
+        assert_eq!(grid.resolve(IVec2::new(0, 1)), Some((0, 8)));
+        assert_eq!(grid.resolve(IVec2::new(7, 7)), Some((0, 63)));
+        assert_eq!(grid.resolve(IVec2::new(8, 0)), Some((1, 0)));
+        assert_eq!(grid.resolve(IVec2::new(18, 9)), Some((5, 2 | 1 << 3)));
+        assert_eq!(grid.resolve(IVec2::new(19, 0)), None);
+        assert_eq!(grid.resolve(IVec2::new(0, 10)), None);
+        assert_eq!(grid.resolve(IVec2::new(-1, 0)), None);
+    }
+
+    #[test]
+    fn fill2_partial() {
+        let mut grid = Grid2::new(UVec2::new(19, 10));
+        grid.fill(true);
+        assert_eq!(grid.data.len(), 6);
+        assert_eq!(grid.data[0], !0u64);
+        assert_eq!(grid.data[2], 0x0707_0707_0707_0707);
+        assert_eq!(grid.data[3], 0xFFFF);
+        assert_eq!(grid.data[5], 0x0707);
+        for j in 0..10 {
+            for i in 0..19 {
+                assert_eq!(grid.cell(IVec2::new(i, j)), Some(true));
+            }
+        }
+
+        let mut prng = StdRng::seed_from_u64(42);
+        grid.fill_rand(1.0, &mut prng);
+        assert_eq!(grid.data[5], 0x0707);
+    }
+
+    #[test]
+    fn apply_rule2_backends() {
+        let mut prng = StdRng::seed_from_u64(42);
+        let life: Rule2 = "B3/S23".parse().unwrap();
+        let vn: Rule2 = "B1,3/S0-2/N".parse().unwrap();
+        for size in [UVec2::new(8, 8), UVec2::new(19, 10), UVec2::new(1, 13)] {
+            for boundary in [
+                Boundary::Dead,
+                Boundary::Alive,
+                Boundary::Periodic,
+                Boundary::Mirror,
+            ] {
+                for rule in [Rule2::SMOOTH, life, vn] {
+                    let mut grid = Grid2::new(size);
+                    grid.boundary = boundary;
+                    grid.fill_rand(0.4, &mut prng);
+                    let mut grid_ref = grid.clone();
+                    for _ in 0..3 {
+                        grid.apply_rule(&rule);
+                        grid_ref.apply_rule_ref(&rule);
+                        assert_eq!(grid.data, grid_ref.data);
+                    }
+
+                    // Counts are returned in block layout
+                    let counts = grid.count_neighbors(rule.neighborhood, boundary);
+                    for j in 0..size.y as i32 {
+                        for i in 0..size.x as i32 {
+                            let pos = IVec2::new(i, j);
+                            let (index, bit) = grid.resolve(pos).unwrap();
+                            assert_eq!(
+                                counts[index * 64 + bit as usize],
+                                grid.count_neighbors_single(pos, rule.neighborhood, boundary)
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rule_with_neighborhood() {
+        let rule = Rule3::new(1u8..=2u8, 3u8..=6u8).with_neighborhood(Neighborhood::VonNeumann);
+        assert_eq!(rule.neighborhood, Neighborhood::VonNeumann);
+        assert!(rule.is_valid());
+
+        // Single cell grows into a 3D cross
+        let mut grid = Grid3::new(UVec3::ONE * 8);
+        grid.fill(false);
+        grid.set_cell(IVec3::ONE * 3, true);
+        grid.apply_rule(&rule);
+        for k in 0..8 {
+            for j in 0..8 {
+                for i in 0..8 {
+                    let pos = IVec3::new(i, j, k);
+                    let d = (pos - IVec3::ONE * 3).abs();
+                    let expected = d.x + d.y + d.z == 1;
+                    assert_eq!(grid.cell(pos), Some(expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rule_with_neighborhood_invalid() {
+        let _r = Rule3::SMOOTH.with_neighborhood(Neighborhood::VonNeumann);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn apply_rule_invalid() {
+        let rule = Rule3 {
+            neighborhood: Neighborhood::VonNeumann,
+            ..Rule3::SMOOTH
+        };
+        assert!(!rule.is_valid());
+        let mut grid = Grid3::new(UVec3::ONE * 4);
+        grid.fill(false);
+        grid.apply_rule(&rule);
+    }
+
     #[test]
     fn neighbors3() {
         // 3x3x3 grid with alive cell in center
@@ -1503,8 +2992,8 @@ mod tests {
         grid.fill(false);
         grid.set_cell(IVec3::ONE, true);
 
-        let counts_false = grid.count_neighbors(false);
-        let counts_true = grid.count_neighbors(true);
+        let counts_false = grid.count_neighbors(Neighborhood::Moore, Boundary::Dead);
+        let counts_true = grid.count_neighbors(Neighborhood::Moore, Boundary::Alive);
 
         for k in -1..=1 {
             for j in -1..=1 {
@@ -1512,22 +3001,22 @@ mod tests {
                     let pos = IVec3::new(i + 1, j + 1, k + 1);
                     if pos == IVec3::ONE {
                         // center: no neighbor
-                        assert_eq!(counts_false[index(pos, size)], 0);
+                        assert_eq!(counts_false[index(pos, &grid)], 0);
                     } else if i * j * k != 0 {
                         // corner: neighbor is center, and optionally out-of-bound values if
-                        // default=true
-                        assert_eq!(counts_false[index(pos, size)], 1);
-                        assert_eq!(counts_true[index(pos, size)], 20);
+                        // boundary is alive
+                        assert_eq!(counts_false[index(pos, &grid)], 1);
+                        assert_eq!(counts_true[index(pos, &grid)], 20);
                     } else if i * j != 0 || i * k != 0 || j * k != 0 {
                         // edge center: neighbor is center, and optionally out-of-bound values if
-                        // default=true
-                        assert_eq!(counts_false[index(pos, size)], 1);
-                        assert_eq!(counts_true[index(pos, size)], 16);
+                        // boundary is alive
+                        assert_eq!(counts_false[index(pos, &grid)], 1);
+                        assert_eq!(counts_true[index(pos, &grid)], 16);
                     } else {
                         // face center: neighbor is center, and optionally out-of-bound values if
-                        // default=true
-                        assert_eq!(counts_false[index(pos, size)], 1);
-                        assert_eq!(counts_true[index(pos, size)], 10);
+                        // boundary is alive
+                        assert_eq!(counts_false[index(pos, &grid)], 1);
+                        assert_eq!(counts_true[index(pos, &grid)], 10);
                     }
                 }
             }
@@ -0,0 +1,426 @@
+//! Watertight and manifold meshes of the faces of the alive cells of
+//! [`Grid3`], and validation of the topology of [`Mesh`]es.
+//!
+//! The faces between alive and dead cells form a 2-manifold surface only if
+//! the grid has no critical configuration: two alive (or dead) cells sharing
+//! only an edge while the two other cells around that edge are in the other
+//! state, or two alive (or dead) cells sharing only a corner while the 6
+//! other cells around that corner are in the other state. Such grids are
+//! called well-composed.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use glam::{IVec3, UVec3};
+
+use crate::marching_cubes::corner;
+use crate::{Grid3, Mesh, MeshOptions};
+
+/// Get the corner of a 2x2x2 window of cells to make alive for each set of
+/// alive corners, if that configuration is critical.
+fn fix_table() -> &'static [Option<u8>; 256] {
+    static TABLE: OnceLock<[Option<u8>; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|config| {
+            let alive = |c: u8| (config >> c) & 1 != 0;
+            // Diagonal cells of one of the 6 layers of 4 cells around an edge
+            for axis in 0..3 {
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                for side in 0..2 {
+                    let c0 = side << axis;
+                    let [c1, c2, c3] = [c0 | 1 << u, c0 | 1 << u | 1 << v, c0 | 1 << v];
+                    if alive(c0) == alive(c2) && alive(c1) == alive(c3) && alive(c0) != alive(c1) {
+                        return Some(if alive(c0) { c1 } else { c0 });
+                    }
+                }
+            }
+            // Opposite corners of the window, the 6 others in the other state
+            for c in 0..4u8 {
+                let pair = 1 << c | 1 << (7 - c);
+                if config == pair {
+                    // This leaves an edge configuration, fixed afterward
+                    return Some(c ^ 1);
+                }
+                if config == 0xFF & !pair {
+                    return Some(c);
+                }
+            }
+            None
+        })
+    })
+}
+
+impl Grid3 {
+    /// Make alive the dead cells needed for the faces between alive and dead
+    /// cells to form a manifold surface.
+    ///
+    /// Alive cells touching only along an edge or at a corner are connected by
+    /// making alive some of the dead cells between them, and similarly dead
+    /// cells touching only along an edge or at a corner are separated. This is
+    /// repeated until no such configuration remains, so cells are only ever
+    /// made alive. Cells outside the grid are considered dead. Returns the
+    /// number of cells made alive.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, IVec3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(false);
+    /// // Two cells sharing only an edge
+    /// grid.set_cell(IVec3::new(1, 1, 1), true);
+    /// grid.set_cell(IVec3::new(2, 2, 1), true);
+    /// assert!(!grid.cube_mesh(&MeshOptions::default()).validate().is_valid());
+    ///
+    /// assert_eq!(grid.resolve_non_manifold(), 1);
+    /// assert!(grid.cube_mesh(&MeshOptions::default()).validate().is_valid());
+    /// ```
+    pub fn resolve_non_manifold(&mut self) -> usize {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("resolve_non_manifold").entered();
+
+        if self.data.is_empty() {
+            return 0;
+        }
+        let table = fix_table();
+        let size = self.size.as_ivec3();
+        let mut count = 0;
+        loop {
+            let mut changed = false;
+            // Windows partially outside the grid are critical only for cells
+            // inside it, so every fix is inside the grid.
+            for z in -1..size.z {
+                for y in -1..size.y {
+                    for x in -1..size.x {
+                        let min = IVec3::new(x, y, z);
+                        let config = (0..8).fold(0u8, |config, c| {
+                            let alive = self.cell(min + corner(c)).unwrap_or(false);
+                            config | (alive as u8) << c
+                        });
+                        if let Some(c) = table[config as usize] {
+                            debug_assert!(self.cell(min + corner(c)).is_some());
+                            self.set_cell(min + corner(c), true);
+                            count += 1;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                return count;
+            }
+        }
+    }
+
+    /// Extract a mesh of the faces between alive and dead cells, with one quad
+    /// per face.
+    ///
+    /// Unlike with [`greedy_mesh()`], the quads share their corners, so the
+    /// mesh has no T-junction. Cells outside the grid are considered dead, so
+    /// the mesh is closed, and it's also manifold if the grid has no alive or
+    /// dead cells touching only along an edge or at a corner, which
+    /// [`resolve_non_manifold()`] ensures. The vertex normals are averaged
+    /// from the faces around each corner.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::new(2, 2, 2));
+    /// grid.fill(true);
+    ///
+    /// let mesh = grid.cube_mesh(&MeshOptions::default());
+    /// assert_eq!(mesh.vertex_count(), 26);
+    /// assert_eq!(mesh.triangle_count(), 6 * 4 * 2);
+    /// ```
+    ///
+    /// [`greedy_mesh()`]: Self::greedy_mesh
+    /// [`resolve_non_manifold()`]: Self::resolve_non_manifold
+    pub fn cube_mesh(&self, options: &MeshOptions) -> Mesh {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("cube_mesh").entered();
+
+        let mut mesh = Mesh::default();
+        if self.data.is_empty() {
+            return mesh;
+        }
+        let faces = self.face_blocks();
+        let dims = Self::get_bitblock_dims(self.size);
+        let mut vertices = HashMap::new();
+        let mut ib = 0;
+        for bz in 0..dims.z {
+            for by in 0..dims.y {
+                for bx in 0..dims.x {
+                    let bpos = UVec3::new(bx, by, bz);
+                    for (f, mut bits) in faces[ib].into_iter().enumerate() {
+                        while bits != 0 {
+                            let bit = bits.trailing_zeros();
+                            bits &= bits - 1;
+                            let pos = bpos * 4 + UVec3::new(bit & 3, (bit >> 2) & 3, bit >> 4);
+                            push_face(&mut mesh, &mut vertices, options, pos, f / 2, f % 2);
+                        }
+                    }
+                    ib += 1;
+                }
+            }
+        }
+        mesh.compute_normals();
+        mesh
+    }
+}
+
+/// Push the quad of the face of the cell `pos` along `axis`, in direction
+/// `dir` (0 for -axis, 1 for +axis), sharing the corners already pushed.
+fn push_face(
+    mesh: &mut Mesh,
+    vertices: &mut HashMap<IVec3, u32>,
+    options: &MeshOptions,
+    pos: UVec3,
+    axis: usize,
+    dir: usize,
+) {
+    let u = (axis + 1) % 3;
+    let v = (axis + 2) % 3;
+    let corner = |du: i32, dv: i32| {
+        let mut p = pos.as_ivec3();
+        p[axis] += dir as i32;
+        p[u] += du;
+        p[v] += dv;
+        p
+    };
+    let mut quad = [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)];
+    if dir == 0 {
+        quad.reverse();
+    }
+    let [a, b, c, d] = quad.map(|p| {
+        *vertices.entry(p).or_insert_with(|| {
+            mesh.positions.push(options.to_mesh(p.as_vec3()));
+            mesh.positions.len() as u32 - 1
+        })
+    });
+    mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+}
+
+/// Topological defects of a mesh, as found by [`Mesh::validate()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MeshValidity {
+    /// Number of edges used by a single triangle, on the border of a hole.
+    pub boundary_edges: usize,
+    /// Number of edges used by more than two triangles.
+    pub non_manifold_edges: usize,
+    /// Number of edges used by two triangles in the same direction, whose
+    /// orientations are inconsistent.
+    pub flipped_edges: usize,
+    /// Number of vertices where several separate fans of triangles touch.
+    pub non_manifold_vertices: usize,
+    /// Number of triangles with several vertices at the same position.
+    pub degenerate_triangles: usize,
+}
+
+impl MeshValidity {
+    /// Check if the mesh has no defect, that is it's a closed and consistently
+    /// oriented 2-manifold surface.
+    pub fn is_valid(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Mesh {
+    /// Check that the mesh is a closed and consistently oriented 2-manifold
+    /// surface, as expected by 3D printing slicers for example.
+    ///
+    /// Vertices at the exact same position are considered the same vertex,
+    /// since formats like STL don't store the vertices of the triangles
+    /// separately.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::new(4, 4, 4));
+    /// grid.fill(true);
+    /// assert!(grid.marching_cubes(&MeshOptions::default()).validate().is_valid());
+    /// ```
+    pub fn validate(&self) -> MeshValidity {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("validate").entered();
+
+        let mut validity = MeshValidity::default();
+
+        // Weld the vertices at the same position
+        let mut welded = HashMap::new();
+        let vertex: Vec<u32> = self
+            .positions
+            .iter()
+            .map(|p| {
+                let count = welded.len() as u32;
+                *welded
+                    .entry(p.to_array().map(f32::to_bits))
+                    .or_insert(count)
+            })
+            .collect();
+
+        let mut edges = HashMap::new();
+        let mut corners = vec![];
+        for tri in self.triangles() {
+            let [a, b, c] = tri.map(|i| vertex[i as usize]);
+            if a == b || b == c || c == a {
+                validity.degenerate_triangles += 1;
+                continue;
+            }
+            for (u, v, w) in [(a, b, c), (b, c, a), (c, a, b)] {
+                *edges.entry((u, v)).or_insert(0usize) += 1;
+                corners.push((u, v, w));
+            }
+        }
+
+        for (&(u, v), &n) in &edges {
+            let m = edges.get(&(v, u)).copied().unwrap_or(0);
+            // Count each edge once, from its first vertex
+            if m > 0 && u > v {
+                continue;
+            }
+            match n + m {
+                1 => validity.boundary_edges += 1,
+                2 if n == 2 || m == 2 => validity.flipped_edges += 1,
+                2 => {}
+                _ => validity.non_manifold_edges += 1,
+            }
+        }
+
+        // Around each vertex, triangles sharing an edge belong to the same fan
+        corners.sort_unstable();
+        for group in corners.chunk_by(|a, b| a.0 == b.0) {
+            let mut neighbors: Vec<u32> = group.iter().flat_map(|c| [c.1, c.2]).collect();
+            neighbors.sort_unstable();
+            neighbors.dedup();
+            let index = |v: u32| neighbors.binary_search(&v).unwrap();
+            let mut parent: Vec<usize> = (0..neighbors.len()).collect();
+            fn find(parent: &mut [usize], mut i: usize) -> usize {
+                while parent[i] != i {
+                    parent[i] = parent[parent[i]];
+                    i = parent[i];
+                }
+                i
+            }
+            for &(_, v, w) in group {
+                let a = find(&mut parent, index(v));
+                let b = find(&mut parent, index(w));
+                parent[a] = b;
+            }
+            let fans = (0..parent.len()).filter(|&i| parent[i] == i).count();
+            if fans > 1 {
+                validity.non_manifold_vertices += 1;
+            }
+        }
+
+        validity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::mesh;
+
+    #[test]
+    fn edge() {
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(false);
+        grid.set_cell(IVec3::new(1, 1, 1), true);
+        grid.set_cell(IVec3::new(2, 2, 1), true);
+        let validity = grid.cube_mesh(&MeshOptions::default()).validate();
+        assert_eq!(validity.non_manifold_edges, 1);
+        assert!(!validity.is_valid());
+
+        assert_eq!(grid.resolve_non_manifold(), 1);
+        let mesh = grid.cube_mesh(&MeshOptions::default());
+        assert!(mesh.validate().is_valid());
+        assert!(mesh.is_closed());
+        assert!((mesh.volume() - 3.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn corner() {
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(false);
+        grid.set_cell(IVec3::new(1, 1, 1), true);
+        grid.set_cell(IVec3::new(2, 2, 2), true);
+        let validity = grid.cube_mesh(&MeshOptions::default()).validate();
+        assert_eq!(validity.non_manifold_vertices, 1);
+        assert_eq!(validity.non_manifold_edges, 0);
+
+        assert_eq!(grid.resolve_non_manifold(), 2);
+        let mesh = grid.cube_mesh(&MeshOptions::default());
+        assert!(mesh.validate().is_valid());
+        assert!((mesh.volume() - 4.).abs() < 1e-4);
+
+        // Two dead cells touching at a corner inside alive cells
+        grid.fill(true);
+        grid.set_cell(IVec3::new(1, 1, 1), false);
+        grid.set_cell(IVec3::new(2, 2, 2), false);
+        let validity = grid.cube_mesh(&MeshOptions::default()).validate();
+        assert_eq!(validity.non_manifold_vertices, 1);
+        assert_eq!(grid.resolve_non_manifold(), 1);
+        assert!(grid
+            .cube_mesh(&MeshOptions::default())
+            .validate()
+            .is_valid());
+    }
+
+    #[test]
+    fn random() {
+        for mut grid in mesh::random_grids() {
+            let options = MeshOptions {
+                voxel_size: Vec3::new(0.5, 1., 2.),
+                origin: Vec3::new(3., -2., 1.),
+            };
+            assert!(!grid.cube_mesh(&options).validate().is_valid());
+
+            let before = grid.data.iter().map(|b| b.count_ones()).sum::<u32>();
+            let count = grid.resolve_non_manifold();
+            let alive = grid.data.iter().map(|b| b.count_ones()).sum::<u32>();
+            assert_eq!(alive, before + count as u32);
+            assert_eq!(grid.resolve_non_manifold(), 0);
+
+            let mesh = grid.cube_mesh(&options);
+            assert_eq!(mesh.validate(), MeshValidity::default());
+            assert!(mesh.is_closed());
+            assert!((mesh.volume() - alive as f32).abs() < 1e-2);
+            assert_eq!(mesh.normals.len(), mesh.vertex_count());
+
+            let mesh = grid.marching_cubes(&options);
+            assert!(mesh.validate().is_valid());
+        }
+    }
+
+    #[test]
+    fn defects() {
+        // Greedy meshes have T-junctions
+        let mut grid = Grid3::new(UVec3::splat(8));
+        grid.fill(false);
+        for z in 0..4 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    grid.set_cell(IVec3::new(x, y, z), true);
+                }
+            }
+        }
+        grid.set_cell(IVec3::new(4, 0, 0), true);
+        let mut mesh = grid.greedy_mesh(&MeshOptions::default());
+        assert!(mesh.validate().boundary_edges > 0);
+
+        mesh = grid.cube_mesh(&MeshOptions::default());
+        assert!(mesh.validate().is_valid());
+        mesh.indices.swap(0, 1);
+        let validity = mesh.validate();
+        assert_eq!(validity.flipped_edges, 3);
+        assert_eq!(validity.boundary_edges, 0);
+        mesh.indices[1] = mesh.indices[0];
+        assert_eq!(mesh.validate().degenerate_triangles, 1);
+
+        assert!(grid
+            .cube_mesh(&MeshOptions::default())
+            .validate()
+            .is_valid());
+        assert!(Grid3::new(UVec3::ONE)
+            .cube_mesh(&MeshOptions::default())
+            .is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+//! Marching cubes surface extraction for [`Grid3`].
+//!
+//! The grid is sampled at the center of each cell, with a value of 1 for alive
+//! cells and 0 for dead ones, and the isosurface at 0.5 is extracted. Each cube
+//! of the sampling lattice is made of 8 adjacent cell centers, and is
+//! triangulated according to which of its corners are alive. Since the samples
+//! are binary, vertices are always at the middle of the cube edges.
+
+use std::sync::OnceLock;
+
+use glam::IVec3;
+
+use crate::{Grid3, Mesh, MeshOptions};
+
+/// Corner and axis of each cube edge.
+///
+/// Corners are numbered with bit 0 for X, bit 1 for Y, and bit 2 for Z. Each
+/// edge goes from its corner along its axis. Edges `4*a..4*a+4` are along axis
+/// `a`.
+pub(crate) const EDGES: [(u8, u8); 12] = [
+    (0, 0),
+    (2, 0),
+    (4, 0),
+    (6, 0),
+    (0, 1),
+    (4, 1),
+    (1, 1),
+    (5, 1),
+    (0, 2),
+    (1, 2),
+    (2, 2),
+    (3, 2),
+];
+
+/// Get the cube edge joining two corners which differ along a single axis.
+fn edge_between(p: u8, q: u8) -> u8 {
+    let axis = (p ^ q).trailing_zeros() as u8;
+    let corner = p.min(q);
+    EDGES.iter().position(|e| *e == (corner, axis)).unwrap() as u8
+}
+
+/// Check if two cube edges lie on a common face of the cube.
+fn share_face(a: u8, b: u8) -> bool {
+    let faces = |e: u8| {
+        let (c, axis) = EDGES[e as usize];
+        [1, 2].map(|d| {
+            let other = (axis + d) % 3;
+            (other, (c >> other) & 1)
+        })
+    };
+    let fa = faces(a);
+    faces(b).iter().any(|f| fa.contains(f))
+}
+
+/// Triangulate the isosurface of a cube for the given set of alive corners.
+///
+/// On each face of the cube, the isosurface crosses the edges whose corners
+/// differ. Those crossings are joined in pairs by segments around the alive
+/// corners, oriented consistently so that the segments form closed loops
+/// around the cube, which are then triangulated as fans.
+///
+/// On faces with two diagonally opposite alive corners, the segments separate
+/// the alive corners. Since this only depends on the corners of the face, the
+/// two cubes sharing a face always agree, and the surface has no hole.
+fn triangulate(config: u8) -> Vec<[u8; 3]> {
+    let alive = |c: u8| (config >> c) & 1 != 0;
+
+    // Segment starting at each crossed edge, if any
+    let mut next = [None; 12];
+    for axis in 0..3 {
+        let u = (axis + 1) % 3;
+        let v = (axis + 2) % 3;
+        for side in 0..2 {
+            // Corners of the face, counter-clockwise seen from outside
+            let mut face = [(0, 0), (1, 0), (1, 1), (0, 1)]
+                .map(|(bu, bv)| (side << axis) | (bu << u) | (bv << v));
+            if side == 0 {
+                face.reverse();
+            }
+            for i in 0..4 {
+                // Entering the alive region, go to the next edge leaving it
+                if alive(face[i]) || !alive(face[(i + 1) % 4]) {
+                    continue;
+                }
+                let j = (1..4)
+                    .map(|d| (i + d) % 4)
+                    .find(|j| alive(face[*j]) && !alive(face[(*j + 1) % 4]))
+                    .unwrap();
+                let from = edge_between(face[i], face[(i + 1) % 4]);
+                let to = edge_between(face[j], face[(j + 1) % 4]);
+                next[from as usize] = Some(to);
+            }
+        }
+    }
+
+    let mut triangles = vec![];
+    let mut visited = [false; 12];
+    for start in 0..12 {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut cycle = vec![];
+        let mut e = start as u8;
+        while !visited[e as usize] {
+            visited[e as usize] = true;
+            cycle.push(e);
+            e = next[e as usize].unwrap();
+        }
+        // Start the fan from a vertex whose diagonals never lie on a face of
+        // the cube, otherwise the two cubes sharing that face would both have
+        // a flat triangle on it.
+        let len = cycle.len();
+        let origin = (0..len)
+            .find(|&i| (2..len - 1).all(|k| !share_face(cycle[i], cycle[(i + k) % len])))
+            .unwrap();
+        cycle.rotate_left(origin);
+        for k in 1..cycle.len() - 1 {
+            triangles.push([cycle[0], cycle[k], cycle[k + 1]]);
+        }
+    }
+    triangles
+}
+
+/// Get the triangles of the isosurface of a cube for each set of alive
+/// corners, as triplets of cube edges.
+fn triangle_table() -> &'static [Vec<[u8; 3]>] {
+    static TABLE: OnceLock<Vec<Vec<[u8; 3]>>> = OnceLock::new();
+    TABLE.get_or_init(|| (0..=255).map(triangulate).collect())
+}
+
+impl Grid3 {
+    /// Extract a smooth mesh of the surface between alive and dead cells with
+    /// the marching cubes algorithm.
+    ///
+    /// Cells outside the grid are considered dead, so the mesh is always
+    /// closed. Its normals point from the alive cells toward the dead ones.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, IVec3, MeshOptions, UVec3, Vec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(false);
+    /// grid.set_cell(IVec3::ONE, true);
+    ///
+    /// let options = MeshOptions {
+    ///     voxel_size: Vec3::splat(0.5),
+    ///     ..Default::default()
+    /// };
+    /// let mesh = grid.marching_cubes(&options);
+    /// // A single cell gives an octahedron around its center
+    /// assert_eq!(mesh.vertex_count(), 6);
+    /// assert_eq!(mesh.triangle_count(), 8);
+    /// ```
+    pub fn marching_cubes(&self, options: &MeshOptions) -> Mesh {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("marching_cubes").entered();
+
+        let mut mesh = Mesh::default();
+        if self.data.is_empty() {
+            return mesh;
+        }
+        let table = triangle_table();
+
+        // The lattice has one point per cell center, plus one layer of dead
+        // cells around the grid. Each Z plane of points is sampled once, and
+        // shared by the two layers of cubes it belongs to.
+        let n = self.size.as_ivec3() + 2;
+        let plane_len = (n.x * n.y) as usize;
+        let index = |p: IVec3| (p.y * n.x + p.x) as usize;
+        let mut planes = [vec![false; plane_len], vec![false; plane_len]];
+        // Vertex on each X and Y edge of the two current planes, and each Z
+        // edge between them.
+        let mut xy_edges = [vec![u32::MAX; plane_len * 2], vec![u32::MAX; plane_len * 2]];
+        let mut z_edges = vec![u32::MAX; plane_len];
+
+        let sample = |z: i32, plane: &mut [bool]| {
+            for y in 0..n.y {
+                for x in 0..n.x {
+                    let pos = IVec3::new(x, y, z) - 1;
+                    plane[index(pos + 1)] = self.cell(pos).unwrap_or(false);
+                }
+            }
+        };
+
+        sample(0, &mut planes[0]);
+        for z in 0..n.z - 1 {
+            let hi = ((z + 1) & 1) as usize;
+            sample(z + 1, &mut planes[hi]);
+            xy_edges[hi].fill(u32::MAX);
+            z_edges.fill(u32::MAX);
+
+            for y in 0..n.y - 1 {
+                for x in 0..n.x - 1 {
+                    let base = IVec3::new(x, y, z);
+                    let mut config = 0u8;
+                    for c in 0..8 {
+                        let p = base + corner(c);
+                        if planes[(p.z & 1) as usize][index(p)] {
+                            config |= 1 << c;
+                        }
+                    }
+
+                    for tri in &table[config as usize] {
+                        let tri = tri.map(|e| {
+                            let (c, axis) = EDGES[e as usize];
+                            let p = base + corner(c);
+                            let slot = if axis == 2 {
+                                &mut z_edges[index(p)]
+                            } else {
+                                let plane = (p.z & 1) as usize;
+                                &mut xy_edges[plane][axis as usize * plane_len + index(p)]
+                            };
+                            if *slot == u32::MAX {
+                                *slot = mesh.positions.len() as u32;
+                                // Lattice point p is the center of cell p-1
+                                let mut pos = p.as_vec3() - 0.5;
+                                pos[axis as usize] += 0.5;
+                                mesh.positions.push(options.to_mesh(pos));
+                            }
+                            *slot
+                        });
+                        mesh.indices.extend_from_slice(&tri);
+                    }
+                }
+            }
+        }
+
+        mesh.compute_normals();
+        mesh
+    }
+}
+
+/// Offset of a cube corner from the minimum corner of the cube.
+#[inline]
+pub(crate) fn corner(c: u8) -> IVec3 {
+    IVec3::new((c & 1) as i32, ((c >> 1) & 1) as i32, ((c >> 2) & 1) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec3, Vec3};
+
+    use super::*;
+    use crate::{mesh, Boundary};
+
+    #[test]
+    fn table() {
+        let table = triangle_table();
+        assert!(table[0].is_empty());
+        assert!(table[255].is_empty());
+        for config in 1..255 {
+            assert!(!table[config].is_empty());
+            // Complementary configurations cut the same edges
+            let mut edges: Vec<u8> = table[config].iter().flatten().copied().collect();
+            let mut other: Vec<u8> = table[255 - config].iter().flatten().copied().collect();
+            edges.sort();
+            edges.dedup();
+            other.sort();
+            other.dedup();
+            assert_eq!(edges, other);
+        }
+
+        // Single alive corner: the triangle faces away from it
+        let [a, b, c] = table[1][0].map(|e| {
+            let (c, axis) = EDGES[e as usize];
+            let mut p = corner(c).as_vec3();
+            p[axis as usize] += 0.5;
+            p
+        });
+        assert!((b - a).cross(c - a).dot(Vec3::ONE) > 0.);
+    }
+
+    #[test]
+    fn closed() {
+        for mut grid in mesh::random_grids() {
+            let mesh = grid.marching_cubes(&MeshOptions::default());
+            assert!(!mesh.is_empty());
+            assert!(mesh.is_closed());
+            assert!(mesh.volume() > 0.);
+            assert_eq!(mesh.normals.len(), mesh.positions.len());
+
+            // The boundary condition doesn't affect meshing
+            grid.boundary = Boundary::Alive;
+            assert_eq!(grid.marching_cubes(&MeshOptions::default()), mesh);
+        }
+    }
+
+    #[test]
+    fn full_box() {
+        let mut grid = Grid3::new(UVec3::new(3, 4, 5));
+        grid.fill(true);
+        let options = MeshOptions {
+            voxel_size: Vec3::new(1., 2., 0.5),
+            origin: Vec3::new(-1., 0., 10.),
+        };
+        let mesh = grid.marching_cubes(&options);
+        assert!(mesh.is_closed());
+
+        // The surface passes through the faces of the boundary cells, with
+        // cut corners, so it's inside the box of the cells
+        let min = options.origin;
+        let max = options.to_mesh(grid.size.as_vec3());
+        for p in &mesh.positions {
+            assert!(p.cmpge(min).all() && p.cmple(max).all());
+        }
+        let extent = mesh
+            .positions
+            .iter()
+            .fold(Vec3::splat(f32::MIN), |m, p| m.max(*p));
+        assert_eq!(extent, max);
+
+        // Normals point outward
+        let center = (min + max) / 2.;
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!((*p - center).dot(*n) > 0.);
+        }
+    }
+
+    #[test]
+    fn configs() {
+        // Each configuration alone, surrounded by dead cells
+        for config in 0..256u32 {
+            let mut grid = Grid3::new(UVec3::splat(2));
+            grid.fill(false);
+            for c in 0..8 {
+                if (config >> c) & 1 != 0 {
+                    grid.set_cell(corner(c as u8), true);
+                }
+            }
+            let mesh = grid.marching_cubes(&MeshOptions::default());
+            assert!(mesh.is_closed(), "config {config:08b}");
+        }
+    }
+
+    #[test]
+    fn empty() {
+        mesh::assert_empty(Grid3::marching_cubes);
+    }
+}
@@ -0,0 +1,277 @@
+//! Engine-agnostic indexed triangle meshes generated from grids.
+
+use glam::{Vec2, Vec3, Vec4};
+
+/// Indexed triangle mesh.
+///
+/// Each group of 3 consecutive indices forms a triangle, whose vertices are
+/// in counter-clockwise order when seen from the side its normal points to.
+/// For meshes generated from a grid, normals point from the alive cells toward
+/// the dead ones.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Mesh {
+    /// Position of each vertex.
+    pub positions: Vec<Vec3>,
+    /// Unit normal of each vertex.
+    pub normals: Vec<Vec3>,
+    /// Texture coordinates of each vertex, or empty if the mesh has none.
+    pub uvs: Vec<Vec2>,
+    /// Linear RGBA color of each vertex, or empty if the mesh has none.
+    pub colors: Vec<Vec4>,
+    /// Vertex indices of the triangles.
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Get the number of vertices.
+    #[inline]
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Get the number of triangles.
+    #[inline]
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Check if the mesh has no triangle.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Iterate over the vertex indices of all triangles.
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    /// Recompute the vertex normals from the triangles.
+    ///
+    /// The normal of each vertex is the average of the normals of the
+    /// triangles using it, weighted by their area.
+    pub fn compute_normals(&mut self) {
+        self.normals.clear();
+        self.normals.resize(self.positions.len(), Vec3::ZERO);
+        for tri in self.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| i as usize);
+            let n = (self.positions[b] - self.positions[a])
+                .cross(self.positions[c] - self.positions[a]);
+            self.normals[a] += n;
+            self.normals[b] += n;
+            self.normals[c] += n;
+        }
+        for n in &mut self.normals {
+            *n = n.normalize_or_zero();
+        }
+    }
+
+    /// Replace the optional vertex attributes with the ones of the vertices
+    /// `source`, in order, before the vertices themselves are replaced.
+    pub(crate) fn copy_attributes(&mut self, source: &[u32]) {
+        let n = self.positions.len();
+        self.uvs = if self.uvs.len() == n {
+            source.iter().map(|i| self.uvs[*i as usize]).collect()
+        } else {
+            vec![]
+        };
+        self.colors = if self.colors.len() == n {
+            source.iter().map(|i| self.colors[*i as usize]).collect()
+        } else {
+            vec![]
+        };
+    }
+
+    /// Compute texture coordinates by box projection.
+    ///
+    /// Each vertex is projected along the axis its normal is the most aligned
+    /// with, so that textures repeat every `1 / scale` mesh units on the
+    /// surfaces facing each axis. The normals must be computed.
+    pub fn compute_box_uvs(&mut self, scale: f32) {
+        self.uvs = self
+            .positions
+            .iter()
+            .zip(&self.normals)
+            .map(|(p, n)| {
+                let a = n.abs();
+                let uv = if a.x >= a.y && a.x >= a.z {
+                    Vec2::new(p.y, p.z)
+                } else if a.y >= a.z {
+                    Vec2::new(p.z, p.x)
+                } else {
+                    Vec2::new(p.x, p.y)
+                };
+                uv * scale
+            })
+            .collect();
+    }
+}
+
+/// Indexed 2D triangle mesh with texture coordinates.
+///
+/// Each group of 3 consecutive indices forms a triangle, whose vertices are
+/// in counter-clockwise order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Mesh2 {
+    /// Position of each vertex.
+    pub positions: Vec<Vec2>,
+    /// Texture coordinates of each vertex.
+    pub uvs: Vec<Vec2>,
+    /// Vertex indices of the triangles.
+    pub indices: Vec<u32>,
+}
+
+impl Mesh2 {
+    /// Get the number of vertices.
+    #[inline]
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Get the number of triangles.
+    #[inline]
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Check if the mesh has no triangle.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Iterate over the vertex indices of all triangles.
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    /// Compute the total area of the triangles.
+    pub fn area(&self) -> f32 {
+        self.triangles()
+            .map(|[a, b, c]| {
+                let [a, b, c] = [a, b, c].map(|i| self.positions[i as usize]);
+                (b - a).perp_dot(c - a) / 2.
+            })
+            .sum()
+    }
+}
+
+/// Options controlling the placement of the meshes generated from a grid.
+///
+/// The cell at position `(i, j, k)` in the grid covers the box from `origin +
+/// (i, j, k) * voxel_size` to `origin + (i + 1, j + 1, k + 1) * voxel_size`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshOptions {
+    /// Size of a single cell, in mesh units.
+    pub voxel_size: Vec3,
+    /// Position of the minimum corner of the cell (0,0,0), in mesh units.
+    pub origin: Vec3,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            voxel_size: Vec3::ONE,
+            origin: Vec3::ZERO,
+        }
+    }
+}
+
+impl MeshOptions {
+    /// Get the position of a point given in cell units.
+    #[inline]
+    pub fn to_mesh(&self, p: Vec3) -> Vec3 {
+        self.origin + p * self.voxel_size
+    }
+}
+
+#[cfg(test)]
+impl Mesh {
+    /// Check that each edge is shared by exactly two triangles, which use it
+    /// in opposite directions.
+    pub(crate) fn is_closed(&self) -> bool {
+        let mut edges = std::collections::HashMap::new();
+        for [a, b, c] in self.triangles() {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((u, v)).or_insert(0) += 1;
+            }
+        }
+        edges
+            .iter()
+            .all(|(&(u, v), &n)| n == 1 && edges.get(&(v, u)) == Some(&1))
+    }
+
+    /// Check that each edge is used as many times in each direction, so that
+    /// the mesh has no hole, but may have edges shared by more than two
+    /// triangles.
+    pub(crate) fn is_watertight(&self) -> bool {
+        let mut edges = std::collections::HashMap::new();
+        for [a, b, c] in self.triangles() {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((u, v)).or_insert(0) += 1;
+            }
+        }
+        edges
+            .iter()
+            .all(|(&(u, v), &n)| edges.get(&(v, u)) == Some(&n))
+    }
+
+    /// Compute the signed volume enclosed by the mesh, which is positive if
+    /// the triangles face outward.
+    pub(crate) fn volume(&self) -> f32 {
+        self.triangles()
+            .map(|[a, b, c]| {
+                let pa = self.positions[a as usize];
+                let pb = self.positions[b as usize];
+                let pc = self.positions[c as usize];
+                pa.dot(pb.cross(pc)) / 6.
+            })
+            .sum()
+    }
+}
+
+/// Grids filled at random with various ratios, on which all meshers are
+/// tested.
+#[cfg(test)]
+pub(crate) fn random_grids() -> impl Iterator<Item = crate::Grid3> {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    [0.3, 0.7, 0.5].into_iter().map(|fill_ratio| {
+        let mut grid = crate::Grid3::new(glam::UVec3::new(13, 8, 21));
+        grid.fill_rand(fill_ratio, StdRng::seed_from_u64(42));
+        grid
+    })
+}
+
+/// Check that a mesher generates an empty mesh for a new grid and for a grid
+/// of dead cells.
+#[cfg(test)]
+pub(crate) fn assert_empty(mesher: impl Fn(&crate::Grid3, &MeshOptions) -> Mesh) {
+    let mut grid = crate::Grid3::new(glam::UVec3::ONE * 8);
+    assert!(mesher(&grid, &MeshOptions::default()).is_empty());
+    grid.fill(false);
+    assert!(mesher(&grid, &MeshOptions::default()).is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_normals() {
+        let mut mesh = Mesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z],
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+        };
+        assert!(mesh.is_closed());
+        assert!((mesh.volume() - 1. / 6.).abs() < 1e-6);
+
+        mesh.compute_normals();
+        assert_eq!(mesh.normals.len(), 4);
+        assert!((mesh.normals[0] - Vec3::NEG_ONE.normalize()).length() < 1e-6);
+        assert!(mesh.normals[1].x > 0.);
+    }
+}
@@ -0,0 +1,540 @@
+//! Parsing and formatting of rule strings.
+//!
+//! Rules are written as up to 4 fields separated by slashes. Each field can be
+//! prefixed by a letter identifying it, in which case fields can appear in any
+//! order:
+//! - `B` : birth counts, like `B13-14,17-19`.
+//! - `S` : survive counts, like `S13-26`.
+//! - `C` : number of cell states, like `C2`. Only [`GenerationsRule2`] and
+//!   [`GenerationsRule3`] support more than 2 states.
+//!
+//! The neighborhood is a single letter field, `M` for Moore or `N` (also `V`)
+//! for von Neumann. Neighbor counts are validated against the maximum count of
+//! the neighborhood, for example 6 for a 3D von Neumann rule.
+//!
+//! Unprefixed fields are interpreted by position as survive / birth / states /
+//! neighborhood, as in `13-26/13-14,17-19/2/M`. The Golly-style `B3/S23` is
+//! also accepted. For 2D rules, a run of digits like `23` denotes the counts 2
+//! and 3, because counts never exceed 8. For 3D rules, numbers are separated
+//! by commas, and `23` denotes the single count 23.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    GenerationsRule2, GenerationsRule3, Neighborhood, Rule2, Rule3, RuleBitset2, RuleBitset3,
+};
+
+/// Error returned when parsing a rule string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseRuleError {
+    /// The rule string is empty.
+    Empty,
+    /// A token could not be parsed. Contains the offending token.
+    InvalidToken(String),
+    /// A neighbor count exceeds the maximum number of neighbors.
+    CountOutOfRange {
+        /// The invalid count.
+        count: u32,
+        /// The maximum count allowed for the rule.
+        max: u8,
+    },
+    /// The same field appears more than once. Contains the field letter.
+    DuplicateField(char),
+    /// A mandatory field is missing. Contains the field letter.
+    MissingField(char),
+    /// The rule string has more than 4 fields.
+    TooManyFields,
+    /// The number of states is not supported by the rule type.
+    UnsupportedStates(u32),
+    /// The neighborhood is not supported by the rule type.
+    UnsupportedNeighborhood(String),
+}
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty rule string"),
+            Self::InvalidToken(token) => write!(f, "invalid token '{}'", token),
+            Self::CountOutOfRange { count, max } => write!(
+                f,
+                "neighbor count {} out of range, maximum is {}",
+                count, max
+            ),
+            Self::DuplicateField(c) => write!(f, "duplicate field '{}'", c),
+            Self::MissingField(c) => write!(f, "missing field '{}'", c),
+            Self::TooManyFields => write!(f, "too many fields, expected at most 4"),
+            Self::UnsupportedStates(states) => {
+                write!(f, "unsupported number of states {}", states)
+            }
+            Self::UnsupportedNeighborhood(n) => write!(f, "unsupported neighborhood '{}'", n),
+        }
+    }
+}
+
+impl std::error::Error for ParseRuleError {}
+
+/// Parse a list of neighbor counts into a bit representation.
+///
+/// If `digits` is `true`, each digit of a number is a separate count.
+fn parse_counts(s: &str, max: u8, digits: bool) -> Result<u32, ParseRuleError> {
+    let mut bits = 0u32;
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(0);
+    }
+    let check = |count: u32| {
+        if count > max as u32 {
+            Err(ParseRuleError::CountOutOfRange { count, max })
+        } else {
+            Ok(count)
+        }
+    };
+    let number = |token: &str| {
+        let token = token.trim();
+        if token.is_empty() || !token.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(ParseRuleError::InvalidToken(token.to_string()));
+        }
+        token
+            .parse::<u32>()
+            .map_err(|_| ParseRuleError::InvalidToken(token.to_string()))
+    };
+    for item in s.split(',') {
+        let item = item.trim();
+        if let Some((start, end)) = item.split_once('-') {
+            let start = check(number(start)?)?;
+            let end = check(number(end)?)?;
+            if start > end {
+                return Err(ParseRuleError::InvalidToken(item.to_string()));
+            }
+            for b in start..=end {
+                bits |= 1u32 << b;
+            }
+        } else if digits {
+            number(item)?;
+            for c in item.bytes() {
+                bits |= 1u32 << check((c - b'0') as u32)?;
+            }
+        } else {
+            bits |= 1u32 << check(number(item)?)?;
+        }
+    }
+    Ok(bits)
+}
+
+/// Format a bit representation as a list of neighbor counts and ranges.
+fn fmt_counts(bits: u32, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut first = true;
+    let mut b = 0;
+    while b < 32 {
+        if bits & (1u32 << b) == 0 {
+            b += 1;
+            continue;
+        }
+        let start = b;
+        while b < 31 && bits & (1u32 << (b + 1)) != 0 {
+            b += 1;
+        }
+        if !first {
+            write!(f, ",")?;
+        }
+        first = false;
+        if start == b {
+            write!(f, "{}", start)?;
+        } else {
+            write!(f, "{}-{}", start, b)?;
+        }
+        b += 1;
+    }
+    Ok(())
+}
+
+/// Raw fields of a rule string, before validation against a rule type.
+#[derive(Debug, Default)]
+struct RuleFields<'a> {
+    birth: Option<&'a str>,
+    survive: Option<&'a str>,
+    states: Option<u32>,
+    neighborhood: Option<&'a str>,
+}
+
+impl<'a> RuleFields<'a> {
+    fn parse(s: &'a str) -> Result<Self, ParseRuleError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseRuleError::Empty);
+        }
+
+        fn set<T>(slot: &mut Option<T>, value: T, c: char) -> Result<(), ParseRuleError> {
+            if slot.is_some() {
+                return Err(ParseRuleError::DuplicateField(c));
+            }
+            *slot = Some(value);
+            Ok(())
+        }
+
+        let mut fields = Self::default();
+        for (index, field) in s.split('/').enumerate() {
+            if index >= 4 {
+                return Err(ParseRuleError::TooManyFields);
+            }
+            let field = field.trim();
+            let mut chars = field.chars();
+            let prefix = chars.next().map(|c| c.to_ascii_uppercase());
+            let rest = chars.as_str();
+            match prefix {
+                Some('B') => set(&mut fields.birth, rest, 'B')?,
+                Some('S') => set(&mut fields.survive, rest, 'S')?,
+                Some('C') | Some('G') => {
+                    let states = rest
+                        .trim()
+                        .parse()
+                        .map_err(|_| ParseRuleError::InvalidToken(field.to_string()))?;
+                    set(&mut fields.states, states, 'C')?
+                }
+                // Neighborhoods are single letters, other words are invalid
+                Some(c) if c.is_ascii_alphabetic() && rest.is_empty() => {
+                    set(&mut fields.neighborhood, field, 'M')?
+                }
+                _ => match index {
+                    0 => set(&mut fields.survive, field, 'S')?,
+                    1 => set(&mut fields.birth, field, 'B')?,
+                    2 => {
+                        let states = field
+                            .parse()
+                            .map_err(|_| ParseRuleError::InvalidToken(field.to_string()))?;
+                        set(&mut fields.states, states, 'C')?
+                    }
+                    _ => return Err(ParseRuleError::InvalidToken(field.to_string())),
+                },
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Get the neighborhood, which defaults to Moore.
+    fn neighborhood(&self) -> Result<Neighborhood, ParseRuleError> {
+        match self.neighborhood {
+            None => Ok(Neighborhood::Moore),
+            Some(n) if n.eq_ignore_ascii_case("M") => Ok(Neighborhood::Moore),
+            Some(n) if n.eq_ignore_ascii_case("N") || n.eq_ignore_ascii_case("V") => {
+                Ok(Neighborhood::VonNeumann)
+            }
+            Some(n) => Err(ParseRuleError::UnsupportedNeighborhood(n.to_string())),
+        }
+    }
+
+    /// Get the number of states, which defaults to 2.
+    fn states(&self) -> Result<u8, ParseRuleError> {
+        match self.states {
+            None => Ok(2),
+            Some(states) if (2..=255).contains(&states) => Ok(states as u8),
+            Some(states) => Err(ParseRuleError::UnsupportedStates(states)),
+        }
+    }
+
+    /// Validate the fields which only 2-state rules support.
+    fn check_life_like(&self) -> Result<(), ParseRuleError> {
+        match self.states()? {
+            2 => Ok(()),
+            states => Err(ParseRuleError::UnsupportedStates(states as u32)),
+        }
+    }
+
+    /// Parse the birth and survive fields of a 2D rule.
+    fn bitsets2(&self, n: Neighborhood) -> Result<(RuleBitset2, RuleBitset2), ParseRuleError> {
+        let max = n.max_count2();
+        let birth = self.birth.ok_or(ParseRuleError::MissingField('B'))?;
+        let survive = self.survive.ok_or(ParseRuleError::MissingField('S'))?;
+        Ok((
+            RuleBitset2::from(parse_counts(birth, max, true)? as u16),
+            RuleBitset2::from(parse_counts(survive, max, true)? as u16),
+        ))
+    }
+
+    /// Parse the birth and survive fields of a 3D rule.
+    fn bitsets3(&self, n: Neighborhood) -> Result<(RuleBitset3, RuleBitset3), ParseRuleError> {
+        let max = n.max_count3();
+        let birth = self.birth.ok_or(ParseRuleError::MissingField('B'))?;
+        let survive = self.survive.ok_or(ParseRuleError::MissingField('S'))?;
+        Ok((
+            RuleBitset3::from(parse_counts(birth, max, false)?),
+            RuleBitset3::from(parse_counts(survive, max, false)?),
+        ))
+    }
+}
+
+impl fmt::Display for Neighborhood {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Moore => write!(f, "M"),
+            Self::VonNeumann => write!(f, "N"),
+        }
+    }
+}
+
+impl FromStr for RuleBitset2 {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(parse_counts(s, 8, true)? as u16))
+    }
+}
+
+impl fmt::Display for RuleBitset2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_counts(self.to_bits() as u32, f)
+    }
+}
+
+impl FromStr for RuleBitset3 {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(parse_counts(s, 26, false)?))
+    }
+}
+
+impl fmt::Display for RuleBitset3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_counts(self.to_bits(), f)
+    }
+}
+
+impl FromStr for Rule2 {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = RuleFields::parse(s)?;
+        fields.check_life_like()?;
+        let neighborhood = fields.neighborhood()?;
+        let (birth, survive) = fields.bitsets2(neighborhood)?;
+        Ok(Self {
+            birth,
+            survive,
+            neighborhood,
+        })
+    }
+}
+
+impl fmt::Display for Rule2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "S{}/B{}/2/{}",
+            self.survive, self.birth, self.neighborhood
+        )
+    }
+}
+
+impl FromStr for Rule3 {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = RuleFields::parse(s)?;
+        fields.check_life_like()?;
+        let neighborhood = fields.neighborhood()?;
+        let (birth, survive) = fields.bitsets3(neighborhood)?;
+        Ok(Self {
+            birth,
+            survive,
+            neighborhood,
+        })
+    }
+}
+
+impl fmt::Display for Rule3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "S{}/B{}/2/{}",
+            self.survive, self.birth, self.neighborhood
+        )
+    }
+}
+
+impl FromStr for GenerationsRule2 {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = RuleFields::parse(s)?;
+        let neighborhood = fields.neighborhood()?;
+        let (birth, survive) = fields.bitsets2(neighborhood)?;
+        Ok(Self {
+            birth,
+            survive,
+            states: fields.states()?,
+            neighborhood,
+        })
+    }
+}
+
+impl fmt::Display for GenerationsRule2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "S{}/B{}/{}/{}",
+            self.survive, self.birth, self.states, self.neighborhood
+        )
+    }
+}
+
+impl FromStr for GenerationsRule3 {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = RuleFields::parse(s)?;
+        let neighborhood = fields.neighborhood()?;
+        let (birth, survive) = fields.bitsets3(neighborhood)?;
+        Ok(Self {
+            birth,
+            survive,
+            states: fields.states()?,
+            neighborhood,
+        })
+    }
+}
+
+impl fmt::Display for GenerationsRule3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "S{}/B{}/{}/{}",
+            self.survive, self.birth, self.states, self.neighborhood
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bitset2() {
+        assert_eq!("".parse(), Ok(RuleBitset2::from(0u16)));
+        assert_eq!("23".parse(), Ok(RuleBitset2::from(2u8..=3u8)));
+        assert_eq!("4-8".parse(), Ok(RuleBitset2::from(4u8..=8u8)));
+        assert_eq!(
+            "1,3-4".parse(),
+            Ok(RuleBitset2::from(1u8..2u8) | (3u8..=4u8).into())
+        );
+        assert_eq!(
+            "9".parse::<RuleBitset2>(),
+            Err(ParseRuleError::CountOutOfRange { count: 9, max: 8 })
+        );
+        assert_eq!(
+            "3-x".parse::<RuleBitset2>(),
+            Err(ParseRuleError::InvalidToken("x".to_string()))
+        );
+        assert_eq!(
+            "5-3".parse::<RuleBitset2>(),
+            Err(ParseRuleError::InvalidToken("5-3".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_bitset3() {
+        assert_eq!("23".parse(), Ok(RuleBitset3::from(23u8..24u8)));
+        assert_eq!(
+            "13-14,17-19".parse(),
+            Ok(RuleBitset3::from(13u8..=14u8) | (17u8..=19u8).into())
+        );
+        assert_eq!(
+            "13-27".parse::<RuleBitset3>(),
+            Err(ParseRuleError::CountOutOfRange { count: 27, max: 26 })
+        );
+        assert_eq!(
+            "1,,2".parse::<RuleBitset3>(),
+            Err(ParseRuleError::InvalidToken("".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_rule2() {
+        let life = Rule2::new(3u8..=3u8, 2u8..=3u8);
+        assert_eq!("B3/S23".parse(), Ok(life));
+        assert_eq!("S23/B3".parse(), Ok(life));
+        assert_eq!("b3/s23".parse(), Ok(life));
+        assert_eq!("23/3".parse(), Ok(life));
+        assert_eq!("2-3/3/2/M".parse(), Ok(life));
+        assert_eq!("S4-8/B5-8/2/M".parse(), Ok(Rule2::SMOOTH));
+        assert_eq!(
+            "B3/S23/3".parse::<Rule2>(),
+            Err(ParseRuleError::UnsupportedStates(3))
+        );
+        assert_eq!(
+            "B3".parse::<Rule2>(),
+            Err(ParseRuleError::MissingField('S'))
+        );
+        assert_eq!(
+            "B3/B4/S2".parse::<Rule2>(),
+            Err(ParseRuleError::DuplicateField('B'))
+        );
+        assert_eq!(
+            "S2/B3/2/M/M".parse::<Rule2>(),
+            Err(ParseRuleError::TooManyFields)
+        );
+        assert_eq!(" ".parse::<Rule2>(), Err(ParseRuleError::Empty));
+    }
+
+    #[test]
+    fn parse_rule3() {
+        assert_eq!("13-26/13-14,17-19/2/M".parse(), Ok(Rule3::SMOOTH));
+        assert_eq!("S13-26/B13-14,17-19/2/M".parse(), Ok(Rule3::SMOOTH));
+        assert_eq!("B13-14,17-19/S13-26".parse(), Ok(Rule3::SMOOTH));
+        assert_eq!(
+            "S13-26/B13-30".parse::<Rule3>(),
+            Err(ParseRuleError::CountOutOfRange { count: 30, max: 26 })
+        );
+        assert_eq!(
+            "S2-6/B1/2/N".parse(),
+            Ok(Rule3::new(1u8..=1u8, 2u8..=6u8).with_neighborhood(Neighborhood::VonNeumann))
+        );
+        assert_eq!(
+            "S2-7/B1/2/N".parse::<Rule3>(),
+            Err(ParseRuleError::CountOutOfRange { count: 7, max: 6 })
+        );
+        assert_eq!(
+            "B1/S5/V".parse::<Rule2>(),
+            Err(ParseRuleError::CountOutOfRange { count: 5, max: 4 })
+        );
+        assert_eq!(
+            "S13-26/B13/2/X".parse::<Rule3>(),
+            Err(ParseRuleError::UnsupportedNeighborhood("X".to_string()))
+        );
+        assert_eq!(
+            "S13-26/B13/two".parse::<Rule3>(),
+            Err(ParseRuleError::InvalidToken("two".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_generations() {
+        assert_eq!("/2/3".parse(), Ok(GenerationsRule2::BRIANS_BRAIN));
+        assert_eq!("B2/S/C3".parse(), Ok(GenerationsRule2::BRIANS_BRAIN));
+        assert_eq!("4-7/6-8/10/M".parse(), Ok(GenerationsRule3::PYROCLASTIC));
+        assert_eq!("13-26/13-14,17-19".parse(), Ok(GenerationsRule3::CLOUDS));
+        assert_eq!(
+            "4-7/6-8/1".parse::<GenerationsRule3>(),
+            Err(ParseRuleError::UnsupportedStates(1))
+        );
+        assert_eq!(
+            "4-7/6-8/256".parse::<GenerationsRule3>(),
+            Err(ParseRuleError::UnsupportedStates(256))
+        );
+        assert_eq!(GenerationsRule2::BRIANS_BRAIN.to_string(), "S/B2/3/M");
+        assert_eq!(GenerationsRule3::PYROCLASTIC.to_string(), "S4-7/B6-8/10/M");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(RuleBitset3::from(0u32).to_string(), "");
+        assert_eq!(Rule2::SMOOTH.to_string(), "S4-8/B5-8/2/M");
+        assert_eq!(Rule3::SMOOTH.to_string(), "S13-26/B13-14,17-19/2/M");
+        let rule = Rule3::new(0u8..1u8, RuleBitset3::from(2u8..3u8) | (4u8..=26u8).into());
+        assert_eq!(rule.to_string(), "S2,4-26/B0/2/M");
+        assert_eq!(rule.to_string().parse(), Ok(rule));
+        let rule = Rule2::new(1u8..=1u8, 2u8..=4u8).with_neighborhood(Neighborhood::VonNeumann);
+        assert_eq!(rule.to_string(), "S2-4/B1/2/N");
+        assert_eq!(rule.to_string().parse(), Ok(rule));
+    }
+}
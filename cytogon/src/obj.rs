@@ -0,0 +1,360 @@
+//! Export of [`Mesh`]es to the Wavefront OBJ format, with an optional MTL
+//! material library.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use glam::Vec3;
+
+use crate::Mesh;
+
+/// Material written to an MTL material library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjMaterial {
+    /// File name of the material library, relative to the OBJ file.
+    pub library: String,
+    /// Name of the material.
+    pub name: String,
+    /// Diffuse color, in linear RGB.
+    pub diffuse: Vec3,
+}
+
+impl Default for ObjMaterial {
+    fn default() -> Self {
+        Self {
+            library: "mesh.mtl".to_string(),
+            name: "default".to_string(),
+            diffuse: Vec3::splat(0.8),
+        }
+    }
+}
+
+impl ObjMaterial {
+    /// Write the material library.
+    pub fn write_mtl<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let Vec3 { x, y, z } = self.diffuse;
+        writeln!(writer, "newmtl {}", self.name)?;
+        writeln!(writer, "Ka 0 0 0")?;
+        writeln!(writer, "Kd {x} {y} {z}")?;
+        writeln!(writer, "Ks 0 0 0")?;
+        writeln!(writer, "d 1")?;
+        writeln!(writer, "illum 1")
+    }
+}
+
+/// Options controlling the export of a [`Mesh`] to OBJ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjOptions {
+    /// Name of the object.
+    pub name: String,
+    /// Put the triangles of each connected part of the mesh into their own
+    /// group, named after the object and the index of the part.
+    pub groups: bool,
+    /// Write the texture coordinates of the mesh, if any.
+    pub uvs: bool,
+    /// Material of the mesh, referenced from its material library.
+    pub material: Option<ObjMaterial>,
+}
+
+impl Default for ObjOptions {
+    fn default() -> Self {
+        Self {
+            name: "mesh".to_string(),
+            groups: false,
+            uvs: true,
+            material: None,
+        }
+    }
+}
+
+/// Assign a unique index to each distinct value, compared bitwise.
+fn dedup<const N: usize>(values: impl Iterator<Item = [f32; N]>) -> (Vec<[f32; N]>, Vec<u32>) {
+    let mut unique = vec![];
+    let mut map = HashMap::new();
+    let indices = values
+        .map(|v| {
+            *map.entry(v.map(f32::to_bits)).or_insert_with(|| {
+                unique.push(v);
+                unique.len() as u32 - 1
+            })
+        })
+        .collect();
+    (unique, indices)
+}
+
+impl Mesh {
+    /// Write the mesh in the Wavefront OBJ format.
+    ///
+    /// Since OBJ indexes positions, normals and texture coordinates
+    /// separately, each distinct value is written only once, so that vertices
+    /// at the same position are welded even if their normals differ.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, ObjOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    /// let mesh = grid.greedy_mesh(&MeshOptions::default());
+    ///
+    /// let mut obj = vec![];
+    /// mesh.write_obj(&mut obj, &ObjOptions::default()).unwrap();
+    /// let obj = String::from_utf8(obj).unwrap();
+    /// // A box has 8 corners and 6 normals
+    /// assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+    /// assert_eq!(obj.lines().filter(|l| l.starts_with("vn ")).count(), 6);
+    /// ```
+    pub fn write_obj<W: Write>(&self, writer: W, options: &ObjOptions) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write_obj").entered();
+
+        let mut w = BufWriter::new(writer);
+        writeln!(w, "# Generated by cytogon")?;
+        if let Some(material) = &options.material {
+            writeln!(w, "mtllib {}", material.library)?;
+        }
+        writeln!(w, "o {}", options.name)?;
+
+        let (positions, vertex) = dedup(self.positions.iter().map(|p| p.to_array()));
+        for [x, y, z] in &positions {
+            writeln!(w, "v {x} {y} {z}")?;
+        }
+        let has_uvs = options.uvs && self.uvs.len() == self.positions.len();
+        let uvs = if has_uvs {
+            let (uvs, indices) = dedup(self.uvs.iter().map(|uv| uv.to_array()));
+            for [u, v] in &uvs {
+                writeln!(w, "vt {u} {v}")?;
+            }
+            Some(indices)
+        } else {
+            None
+        };
+        let normals = if self.normals.len() == self.positions.len() {
+            let (normals, indices) = dedup(self.normals.iter().map(|n| n.to_array()));
+            for [x, y, z] in &normals {
+                writeln!(w, "vn {x} {y} {z}")?;
+            }
+            Some(indices)
+        } else {
+            None
+        };
+
+        if let Some(material) = &options.material {
+            writeln!(w, "usemtl {}", material.name)?;
+        }
+        let triangles: Vec<[u32; 3]> = self.triangles().collect();
+        let mut parts = vec![(0..triangles.len()).collect::<Vec<_>>()];
+        if options.groups {
+            parts = connected_parts(&triangles, &vertex, positions.len());
+        }
+        for (index, part) in parts.iter().enumerate() {
+            if options.groups {
+                writeln!(w, "g {}_{index}", options.name)?;
+            }
+            for t in part {
+                write!(w, "f")?;
+                for i in triangles[*t] {
+                    let i = i as usize;
+                    // OBJ indices start at 1
+                    write!(w, " {}", vertex[i] + 1)?;
+                    match (&uvs, &normals) {
+                        (Some(uvs), Some(normals)) => {
+                            write!(w, "/{}/{}", uvs[i] + 1, normals[i] + 1)?
+                        }
+                        (Some(uvs), None) => write!(w, "/{}", uvs[i] + 1)?,
+                        (None, Some(normals)) => write!(w, "//{}", normals[i] + 1)?,
+                        (None, None) => {}
+                    }
+                }
+                writeln!(w)?;
+            }
+        }
+        w.flush()
+    }
+
+    /// Save the mesh to an OBJ file, and its material library next to it if
+    /// it has a material.
+    ///
+    /// See [`write_obj()`] for details.
+    ///
+    /// [`write_obj()`]: Self::write_obj
+    pub fn save_obj(&self, path: impl AsRef<Path>, options: &ObjOptions) -> io::Result<()> {
+        let path = path.as_ref();
+        self.write_obj(File::create(path)?, options)?;
+        if let Some(material) = &options.material {
+            let file = File::create(path.with_file_name(&material.library))?;
+            let mut w = BufWriter::new(file);
+            material.write_mtl(&mut w)?;
+            w.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Split triangles into parts connected by their welded vertices, in order of
+/// their first triangle.
+fn connected_parts(triangles: &[[u32; 3]], vertex: &[u32], count: usize) -> Vec<Vec<usize>> {
+    // Union-find of the welded vertices
+    let mut parent: Vec<u32> = (0..count as u32).collect();
+    fn find(parent: &mut [u32], mut i: u32) -> u32 {
+        while parent[i as usize] != i {
+            parent[i as usize] = parent[parent[i as usize] as usize];
+            i = parent[i as usize];
+        }
+        i
+    }
+    for tri in triangles {
+        let [a, b, c] = tri.map(|i| find(&mut parent, vertex[i as usize]));
+        parent[b as usize] = a;
+        let c = find(&mut parent, c);
+        parent[c as usize] = find(&mut parent, a);
+    }
+
+    let mut part_of_root = HashMap::new();
+    let mut parts: Vec<Vec<usize>> = vec![];
+    for (t, tri) in triangles.iter().enumerate() {
+        let root = find(&mut parent, vertex[tri[0] as usize]);
+        let part = *part_of_root.entry(root).or_insert_with(|| {
+            parts.push(vec![]);
+            parts.len() - 1
+        });
+        parts[part].push(t);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use super::*;
+    use crate::{Grid3, MeshOptions, TempDir};
+
+    /// Parse the positions of the triangles of an OBJ file.
+    fn parse(obj: &str) -> Vec<[Vec3; 3]> {
+        let mut positions = vec![];
+        let mut triangles = vec![];
+        for line in obj.lines() {
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => {
+                    let v: Vec<f32> = tokens.map(|t| t.parse().unwrap()).collect();
+                    positions.push(Vec3::new(v[0], v[1], v[2]));
+                }
+                Some("f") => {
+                    let tri: Vec<Vec3> = tokens
+                        .map(|t| {
+                            let i: usize = t.split('/').next().unwrap().parse().unwrap();
+                            positions[i - 1]
+                        })
+                        .collect();
+                    triangles.push([tri[0], tri[1], tri[2]]);
+                }
+                _ => {}
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut grid = Grid3::new(UVec3::splat(12));
+        grid.fill(false);
+        // Two separate boxes
+        for z in 1..4 {
+            for y in 1..5 {
+                for x in 1..3 {
+                    grid.set_cell(IVec3::new(x, y, z), true);
+                    grid.set_cell(IVec3::new(x + 6, y + 6, z + 6), true);
+                }
+            }
+        }
+        let options = MeshOptions {
+            voxel_size: Vec3::new(0.5, 0.25, 2.),
+            origin: Vec3::new(-1.5, 3., 0.125),
+        };
+        let mut mesh = grid.greedy_mesh(&options);
+        mesh.compute_box_uvs(0.5);
+
+        let options = ObjOptions {
+            name: "cave".to_string(),
+            groups: true,
+            uvs: true,
+            material: Some(ObjMaterial {
+                library: "cave.mtl".to_string(),
+                name: "rock".to_string(),
+                diffuse: Vec3::new(0.5, 0.4, 0.3),
+            }),
+        };
+        let mut obj = vec![];
+        mesh.write_obj(&mut obj, &options).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("v "), 2 * 8);
+        assert_eq!(count("vn "), 6);
+        assert!(count("vt ") > 0);
+        assert_eq!(count("f "), mesh.triangle_count());
+        assert!(obj.lines().any(|l| l == "mtllib cave.mtl"));
+        assert!(obj.lines().any(|l| l == "usemtl rock"));
+        let groups: Vec<&str> = obj.lines().filter(|l| l.starts_with("g ")).collect();
+        assert_eq!(groups, ["g cave_0", "g cave_1"]);
+        for face in obj.lines().filter(|l| l.starts_with("f ")) {
+            assert!(face
+                .split_whitespace()
+                .skip(1)
+                .all(|v| v.split('/').count() == 3));
+        }
+
+        // The triangles are written exactly
+        let mut expected: Vec<[Vec3; 3]> = mesh
+            .triangles()
+            .map(|t| t.map(|i| mesh.positions[i as usize]))
+            .collect();
+        let mut triangles = parse(&obj);
+        let key = |t: &[Vec3; 3]| t.map(|p| p.to_array().map(f32::to_bits));
+        expected.sort_by_key(key);
+        triangles.sort_by_key(key);
+        assert_eq!(triangles, expected);
+
+        let mut mtl = vec![];
+        options.material.unwrap().write_mtl(&mut mtl).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert!(mtl.starts_with("newmtl rock\n"));
+        assert!(mtl.contains("Kd 0.5 0.4 0.3\n"));
+    }
+
+    #[test]
+    fn attributes() {
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mut mesh = grid.marching_cubes(&MeshOptions::default());
+        mesh.normals.clear();
+        let mut obj = vec![];
+        mesh.write_obj(&mut obj, &ObjOptions::default()).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(!obj.contains("vn ") && !obj.contains("vt ") && !obj.contains("g "));
+        assert!(obj
+            .lines()
+            .filter(|l| l.starts_with("f "))
+            .all(|l| !l.contains('/')));
+        assert_eq!(parse(&obj).len(), mesh.triangle_count());
+    }
+
+    #[test]
+    fn save() {
+        let dir = TempDir::new("obj");
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mesh = grid.greedy_mesh(&MeshOptions::default());
+        let options = ObjOptions {
+            material: Some(ObjMaterial::default()),
+            ..Default::default()
+        };
+        mesh.save_obj(dir.join("box.obj"), &options).unwrap();
+        let obj = std::fs::read_to_string(dir.join("box.obj")).unwrap();
+        assert_eq!(parse(&obj).len(), 12);
+        let mtl = std::fs::read_to_string(dir.join("mesh.mtl")).unwrap();
+        assert!(mtl.starts_with("newmtl default\n"));
+    }
+}
@@ -0,0 +1,417 @@
+//! Export of [`Mesh`]es and [`PointCloud`]s to the PLY format, in its ASCII
+//! or binary little-endian variant.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::{Mesh, PointCloud, PointValues};
+
+/// Options controlling the export to PLY.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlyOptions {
+    /// Write the human-readable ASCII variant of the format instead of the
+    /// more compact binary little-endian one.
+    pub ascii: bool,
+}
+
+/// Writer of the values of the elements of a PLY file, one row per element.
+struct RowWriter<W: Write> {
+    w: W,
+    ascii: bool,
+    first: bool,
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(w: W, options: &PlyOptions) -> Self {
+        Self {
+            w,
+            ascii: options.ascii,
+            first: true,
+        }
+    }
+
+    fn header(&mut self, elements: &[(&str, usize, Vec<String>)]) -> io::Result<()> {
+        let format = if self.ascii {
+            "ascii"
+        } else {
+            "binary_little_endian"
+        };
+        writeln!(self.w, "ply")?;
+        writeln!(self.w, "format {format} 1.0")?;
+        writeln!(self.w, "comment Generated by cytogon")?;
+        for (name, count, properties) in elements {
+            writeln!(self.w, "element {name} {count}")?;
+            for property in properties {
+                writeln!(self.w, "property {property}")?;
+            }
+        }
+        writeln!(self.w, "end_header")
+    }
+
+    fn separate(&mut self) -> io::Result<()> {
+        if self.ascii && !self.first {
+            self.w.write_all(b" ")?;
+        }
+        self.first = false;
+        Ok(())
+    }
+
+    fn f32(&mut self, value: f32) -> io::Result<()> {
+        self.separate()?;
+        if self.ascii {
+            write!(self.w, "{value}")
+        } else {
+            self.w.write_all(&value.to_le_bytes())
+        }
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.separate()?;
+        if self.ascii {
+            write!(self.w, "{value}")
+        } else {
+            self.w.write_all(&value.to_le_bytes())
+        }
+    }
+
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.separate()?;
+        if self.ascii {
+            write!(self.w, "{value}")
+        } else {
+            self.w.write_all(&[value])
+        }
+    }
+
+    fn end_row(&mut self) -> io::Result<()> {
+        self.first = true;
+        if self.ascii {
+            self.w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// Get the properties of the position of a vertex.
+fn position_properties() -> Vec<String> {
+    ["float x", "float y", "float z"].map(String::from).to_vec()
+}
+
+impl Mesh {
+    /// Write the mesh in the PLY format.
+    ///
+    /// The vertices have a position, and if the mesh has them a normal
+    /// `nx ny nz`, texture coordinates `s t`, and a color `red green blue
+    /// alpha` written as 8-bit values. The faces are written as lists of 3
+    /// vertex indices.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, PlyOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    /// let mesh = grid.greedy_mesh(&MeshOptions::default());
+    ///
+    /// let mut ply = vec![];
+    /// mesh.write_ply(&mut ply, &PlyOptions { ascii: true }).unwrap();
+    /// let ply = String::from_utf8(ply).unwrap();
+    /// assert!(ply.contains("element vertex 24\n"));
+    /// assert!(ply.contains("element face 12\n"));
+    /// ```
+    pub fn write_ply<W: Write>(&self, writer: W, options: &PlyOptions) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write_ply").entered();
+
+        let count = self.vertex_count();
+        let has_normals = self.normals.len() == count;
+        let has_uvs = self.uvs.len() == count;
+        let has_colors = self.colors.len() == count;
+        let mut properties = position_properties();
+        if has_normals {
+            properties.extend(["float nx", "float ny", "float nz"].map(String::from));
+        }
+        if has_uvs {
+            properties.extend(["float s", "float t"].map(String::from));
+        }
+        if has_colors {
+            properties.extend(
+                ["uchar red", "uchar green", "uchar blue", "uchar alpha"].map(String::from),
+            );
+        }
+        let faces = vec!["list uchar uint vertex_indices".to_string()];
+
+        let mut w = RowWriter::new(BufWriter::new(writer), options);
+        w.header(&[
+            ("vertex", count, properties),
+            ("face", self.triangle_count(), faces),
+        ])?;
+        for i in 0..count {
+            for x in self.positions[i].to_array() {
+                w.f32(x)?;
+            }
+            if has_normals {
+                for x in self.normals[i].to_array() {
+                    w.f32(x)?;
+                }
+            }
+            if has_uvs {
+                for x in self.uvs[i].to_array() {
+                    w.f32(x)?;
+                }
+            }
+            if has_colors {
+                for x in self.colors[i].to_array() {
+                    w.u8((x.clamp(0., 1.) * 255.).round() as u8)?;
+                }
+            }
+            w.end_row()?;
+        }
+        for tri in self.triangles() {
+            w.u8(3)?;
+            for i in tri {
+                w.u32(i)?;
+            }
+            w.end_row()?;
+        }
+        w.finish()
+    }
+
+    /// Save the mesh to a PLY file.
+    ///
+    /// See [`write_ply()`] for details.
+    ///
+    /// [`write_ply()`]: Self::write_ply
+    pub fn save_ply(&self, path: impl AsRef<Path>, options: &PlyOptions) -> io::Result<()> {
+        self.write_ply(File::create(path)?, options)
+    }
+}
+
+impl PointCloud {
+    /// Write the point cloud in the PLY format.
+    ///
+    /// Each point is a vertex with a position, and a property for each
+    /// attribute, named after it. Attribute names must not contain any
+    /// whitespace, and each attribute must have one value per point.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, Neighborhood, PlyOptions, PointCloudOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    /// let options = PointCloudOptions {
+    ///     neighbors: Some(Neighborhood::Moore),
+    ///     ..Default::default()
+    /// };
+    /// let points = grid.point_cloud(&MeshOptions::default(), &options);
+    ///
+    /// let mut ply = vec![];
+    /// points.write_ply(&mut ply, &PlyOptions { ascii: true }).unwrap();
+    /// let ply = String::from_utf8(ply).unwrap();
+    /// assert!(ply.contains("element vertex 64\nproperty float x\n"));
+    /// assert!(ply.contains("property uchar neighbors\n"));
+    /// ```
+    pub fn write_ply<W: Write>(&self, writer: W, options: &PlyOptions) -> io::Result<()> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write_ply").entered();
+
+        let count = self.point_count();
+        let mut properties = position_properties();
+        for (name, values) in &self.attributes {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid PLY property name {name:?}"),
+                ));
+            }
+            if values.len() != count {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "attribute {name} has {} values for {count} points",
+                        values.len()
+                    ),
+                ));
+            }
+            let ty = match values {
+                PointValues::U8(_) => "uchar",
+                PointValues::U32(_) => "uint",
+                PointValues::F32(_) => "float",
+            };
+            properties.push(format!("{ty} {name}"));
+        }
+
+        let mut w = RowWriter::new(BufWriter::new(writer), options);
+        w.header(&[("vertex", count, properties)])?;
+        for (i, p) in self.positions.iter().enumerate() {
+            for x in p.to_array() {
+                w.f32(x)?;
+            }
+            for (_, values) in &self.attributes {
+                match values {
+                    PointValues::U8(values) => w.u8(values[i])?,
+                    PointValues::U32(values) => w.u32(values[i])?,
+                    PointValues::F32(values) => w.f32(values[i])?,
+                }
+            }
+            w.end_row()?;
+        }
+        w.finish()
+    }
+
+    /// Save the point cloud to a PLY file.
+    ///
+    /// See [`write_ply()`] for details.
+    ///
+    /// [`write_ply()`]: Self::write_ply
+    pub fn save_ply(&self, path: impl AsRef<Path>, options: &PlyOptions) -> io::Result<()> {
+        self.write_ply(File::create(path)?, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec3, Vec3, Vec4};
+
+    use super::*;
+    use crate::{Grid3, MeshOptions, PointCloudOptions, TempDir};
+
+    /// Split a PLY file into its header lines and its body.
+    fn split(ply: &[u8]) -> (Vec<String>, &[u8]) {
+        let end = b"end_header\n";
+        let pos = ply.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = std::str::from_utf8(&ply[..pos]).unwrap();
+        (header.lines().map(String::from).collect(), &ply[pos..])
+    }
+
+    #[test]
+    fn mesh() {
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mut mesh = grid.greedy_mesh(&MeshOptions::default());
+        mesh.compute_box_uvs(0.5);
+        mesh.colors = vec![Vec4::new(1., 0.5, 0., 2.); mesh.vertex_count()];
+
+        let mut ply = vec![];
+        mesh.write_ply(&mut ply, &PlyOptions::default()).unwrap();
+        let (header, body) = split(&ply);
+        assert_eq!(header[1], "format binary_little_endian 1.0");
+        assert!(header.contains(&"element vertex 24".to_string()));
+        assert!(header.contains(&"property float t".to_string()));
+        assert!(header.contains(&"property uchar alpha".to_string()));
+        assert!(header.contains(&"property list uchar uint vertex_indices".to_string()));
+        // Position, normal, UV and color of each vertex, then the faces
+        let vertex = 4 * (3 + 3 + 2) + 4;
+        assert_eq!(body.len(), 24 * vertex + 12 * (1 + 3 * 4));
+        let p = &body[..12];
+        let x = f32::from_le_bytes(p[..4].try_into().unwrap());
+        assert_eq!(x, mesh.positions[0].x);
+        assert_eq!(body[vertex - 4..vertex], [255, 128, 0, 255]);
+        let face = &body[24 * vertex..24 * vertex + 13];
+        assert_eq!(face[0], 3);
+        let i = u32::from_le_bytes(face[5..9].try_into().unwrap());
+        assert_eq!(i, mesh.indices[1]);
+
+        let mut ply = vec![];
+        mesh.normals.clear();
+        mesh.uvs.clear();
+        mesh.colors.clear();
+        mesh.write_ply(&mut ply, &PlyOptions { ascii: true })
+            .unwrap();
+        let (header, body) = split(&ply);
+        assert_eq!(header[1], "format ascii 1.0");
+        assert_eq!(
+            header.iter().filter(|l| l.starts_with("property")).count(),
+            4
+        );
+        let body = std::str::from_utf8(body).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 24 + 12);
+        let p = mesh.positions[0];
+        assert_eq!(lines[0], format!("{} {} {}", p.x, p.y, p.z));
+        let [a, b, c] = mesh.triangles().last().unwrap();
+        assert_eq!(lines[35], format!("3 {a} {b} {c}"));
+    }
+
+    #[test]
+    fn points() {
+        let mut grid = Grid3::new(UVec3::splat(3));
+        grid.fill(true);
+        let options = PointCloudOptions {
+            neighbors: Some(crate::Neighborhood::VonNeumann),
+            regions: true,
+            distance: true,
+        };
+        let mut points = grid.point_cloud(&MeshOptions::default(), &options);
+        points.attributes.push((
+            "weight".to_string(),
+            PointValues::F32(vec![0.25; points.point_count()]),
+        ));
+
+        let mut ply = vec![];
+        points
+            .write_ply(&mut ply, &PlyOptions { ascii: true })
+            .unwrap();
+        let (header, body) = split(&ply);
+        assert_eq!(
+            header[3..],
+            [
+                "element vertex 27",
+                "property float x",
+                "property float y",
+                "property float z",
+                "property uchar neighbors",
+                "property uint region",
+                "property uint distance",
+                "property float weight",
+                "end_header",
+            ]
+        );
+        let body = std::str::from_utf8(body).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 27);
+        assert_eq!(lines[0], "0.5 0.5 0.5 3 0 1 0.25");
+        assert_eq!(lines[13], "1.5 1.5 1.5 6 0 2 0.25");
+
+        let mut ply = vec![];
+        points.write_ply(&mut ply, &PlyOptions::default()).unwrap();
+        let (_, body) = split(&ply);
+        let point = 12 + 1 + 4 + 4 + 4;
+        assert_eq!(body.len(), 27 * point);
+        let center = &body[13 * point..14 * point];
+        let x = f32::from_le_bytes(center[..4].try_into().unwrap());
+        assert_eq!(Vec3::splat(x), points.positions[13]);
+        assert_eq!(center[12], 6);
+        assert_eq!(center[17..21], 2u32.to_le_bytes());
+
+        // Invalid attributes are reported
+        points.attributes[3].0 = "bad name".to_string();
+        assert!(points
+            .write_ply(&mut vec![], &PlyOptions::default())
+            .is_err());
+        points.attributes[3] = ("weight".to_string(), PointValues::U8(vec![]));
+        assert!(points
+            .write_ply(&mut vec![], &PlyOptions::default())
+            .is_err());
+    }
+
+    #[test]
+    fn save() {
+        let dir = TempDir::new("ply");
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mesh = grid.marching_cubes(&MeshOptions::default());
+        mesh.save_ply(dir.join("box.ply"), &PlyOptions::default())
+            .unwrap();
+        let ply = std::fs::read(dir.join("box.ply")).unwrap();
+        assert!(ply.starts_with(b"ply\nformat binary_little_endian 1.0\n"));
+        let points = grid.point_cloud(&MeshOptions::default(), &PointCloudOptions::default());
+        points
+            .save_ply(dir.join("points.ply"), &PlyOptions { ascii: true })
+            .unwrap();
+        let ply = std::fs::read_to_string(dir.join("points.ply")).unwrap();
+        assert!(ply.contains("element vertex 64\n"));
+    }
+}
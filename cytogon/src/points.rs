@@ -0,0 +1,335 @@
+//! Point sets made of one point per alive cell of a [`Grid3`], with optional
+//! per-point attributes derived from the grid.
+
+use glam::{IVec3, Vec3};
+
+use crate::{Grid3, MeshOptions, Neighborhood};
+
+/// Values of an attribute of a [`PointCloud`], one per point.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PointValues {
+    /// Unsigned 8-bit values.
+    U8(Vec<u8>),
+    /// Unsigned 32-bit values.
+    U32(Vec<u32>),
+    /// Floating-point values.
+    F32(Vec<f32>),
+}
+
+impl PointValues {
+    /// Get the number of values.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U8(values) => values.len(),
+            Self::U32(values) => values.len(),
+            Self::F32(values) => values.len(),
+        }
+    }
+
+    /// Check if there is no value.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Set of points with named attributes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PointCloud {
+    /// Position of each point.
+    pub positions: Vec<Vec3>,
+    /// Named attributes, each with one value per point.
+    pub attributes: Vec<(String, PointValues)>,
+}
+
+impl PointCloud {
+    /// Get the number of points.
+    #[inline]
+    pub fn point_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Check if the point cloud has no point.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Get the values of the attribute with the given name, if any.
+    pub fn attribute(&self, name: &str) -> Option<&PointValues> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, values)| values)
+    }
+}
+
+/// Options controlling the attributes of the point clouds generated from a
+/// grid.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PointCloudOptions {
+    /// Add a `neighbors` attribute with the number of alive neighbors of each
+    /// cell in this neighborhood, as counted when applying a rule.
+    pub neighbors: Option<Neighborhood>,
+    /// Add a `region` attribute with the index of the region of each cell,
+    /// where regions are sets of alive cells connected by their faces.
+    pub regions: bool,
+    /// Add a `distance` attribute with the distance of each cell to the
+    /// surface, as the number of steps across faces to reach a dead cell.
+    pub distance: bool,
+}
+
+impl Grid3 {
+    /// Create a point cloud with one point at the center of each alive cell.
+    ///
+    /// The points are ordered like the cells, in X-major and Z-minor order.
+    /// Cells outside the grid are considered dead for the `region` and
+    /// `distance` attributes, while the `neighbors` attribute uses the
+    /// [`boundary`] condition of the grid. Region indices are assigned in the
+    /// order of the first cell of each region.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, PointCloudOptions, PointValues, UVec3};
+    /// let mut grid = Grid3::new(UVec3::new(3, 3, 3));
+    /// grid.fill(true);
+    ///
+    /// let options = PointCloudOptions {
+    ///     distance: true,
+    ///     ..Default::default()
+    /// };
+    /// let points = grid.point_cloud(&MeshOptions::default(), &options);
+    /// assert_eq!(points.point_count(), 27);
+    /// let Some(PointValues::U32(distance)) = points.attribute("distance") else {
+    ///     panic!();
+    /// };
+    /// // Only the center cell isn't on the surface
+    /// assert_eq!(distance.iter().filter(|d| **d == 2).count(), 1);
+    /// ```
+    ///
+    /// [`boundary`]: Self::boundary
+    pub fn point_cloud(
+        &self,
+        mesh_options: &MeshOptions,
+        options: &PointCloudOptions,
+    ) -> PointCloud {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("point_cloud").entered();
+
+        let size = self.size.as_ivec3();
+        let alive = |pos: IVec3| !self.data.is_empty() && self.cell(pos).unwrap_or(false);
+        let index = |pos: IVec3| ((pos.z * size.y + pos.y) * size.x + pos.x) as usize;
+        let mut cells = vec![];
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let pos = IVec3::new(x, y, z);
+                    if alive(pos) {
+                        cells.push(pos);
+                    }
+                }
+            }
+        }
+
+        let mut points = PointCloud {
+            positions: cells
+                .iter()
+                .map(|pos| mesh_options.to_mesh(pos.as_vec3() + 0.5))
+                .collect(),
+            attributes: vec![],
+        };
+
+        if let Some(neighborhood) = options.neighbors {
+            let values = if cells.is_empty() {
+                vec![]
+            } else {
+                let counts = self.count_neighbors(neighborhood, self.boundary);
+                cells
+                    .iter()
+                    .map(|pos| {
+                        let (ib, bit) = self.resolve(*pos).unwrap();
+                        counts[ib * 64 + bit as usize]
+                    })
+                    .collect()
+            };
+            points
+                .attributes
+                .push(("neighbors".to_string(), PointValues::U8(values)));
+        }
+
+        if options.regions {
+            let mut regions = vec![u32::MAX; size.element_product().max(0) as usize];
+            let mut count = 0;
+            let mut stack = vec![];
+            for pos in &cells {
+                if regions[index(*pos)] != u32::MAX {
+                    continue;
+                }
+                regions[index(*pos)] = count;
+                stack.push(*pos);
+                while let Some(pos) = stack.pop() {
+                    for d in FACES {
+                        let next = pos + d;
+                        if alive(next) && regions[index(next)] == u32::MAX {
+                            regions[index(next)] = count;
+                            stack.push(next);
+                        }
+                    }
+                }
+                count += 1;
+            }
+            let values = cells.iter().map(|pos| regions[index(*pos)]).collect();
+            points
+                .attributes
+                .push(("region".to_string(), PointValues::U32(values)));
+        }
+
+        if options.distance {
+            // Breadth-first search from the cells on the surface
+            let mut distance = vec![u32::MAX; size.element_product().max(0) as usize];
+            let mut front: Vec<IVec3> = cells
+                .iter()
+                .copied()
+                .filter(|pos| FACES.iter().any(|d| !alive(*pos + *d)))
+                .collect();
+            for pos in &front {
+                distance[index(*pos)] = 1;
+            }
+            let mut d = 1;
+            while !front.is_empty() {
+                d += 1;
+                let mut next_front = vec![];
+                for pos in front {
+                    for f in FACES {
+                        let next = pos + f;
+                        if alive(next) && distance[index(next)] == u32::MAX {
+                            distance[index(next)] = d;
+                            next_front.push(next);
+                        }
+                    }
+                }
+                front = next_front;
+            }
+            let values = cells.iter().map(|pos| distance[index(*pos)]).collect();
+            points
+                .attributes
+                .push(("distance".to_string(), PointValues::U32(values)));
+        }
+
+        points
+    }
+}
+
+/// Offsets to the 6 cells sharing a face with a cell.
+const FACES: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::Boundary;
+
+    #[test]
+    fn neighbors() {
+        let mut grid = Grid3::new(UVec3::new(13, 8, 21));
+        grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+        for boundary in [Boundary::Dead, Boundary::Periodic] {
+            grid.boundary = boundary;
+            for neighborhood in [Neighborhood::Moore, Neighborhood::VonNeumann] {
+                let options = PointCloudOptions {
+                    neighbors: Some(neighborhood),
+                    ..Default::default()
+                };
+                let mesh_options = MeshOptions {
+                    voxel_size: Vec3::new(0.5, 1., 2.),
+                    origin: Vec3::new(3., -2., 1.),
+                };
+                let points = grid.point_cloud(&mesh_options, &options);
+                let alive = grid.data.iter().map(|b| b.count_ones()).sum::<u32>();
+                assert_eq!(points.point_count(), alive as usize);
+                let Some(PointValues::U8(counts)) = points.attribute("neighbors") else {
+                    panic!("missing neighbors");
+                };
+                for (p, count) in points.positions.iter().zip(counts) {
+                    let pos = ((*p - mesh_options.origin) / mesh_options.voxel_size)
+                        .floor()
+                        .as_ivec3();
+                    assert_eq!(grid.cell(pos), Some(true));
+                    assert_eq!(
+                        *count,
+                        grid.count_neighbors_single(pos, neighborhood, boundary)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn regions() {
+        let mut grid = Grid3::new(UVec3::splat(8));
+        grid.fill(false);
+        // An L shape, a cell touching it only along an edge, and a bar
+        for pos in [
+            IVec3::new(0, 0, 0),
+            IVec3::new(1, 0, 0),
+            IVec3::new(1, 1, 0),
+            IVec3::new(2, 2, 0),
+            IVec3::new(5, 5, 5),
+            IVec3::new(5, 5, 6),
+        ] {
+            grid.set_cell(pos, true);
+        }
+        let options = PointCloudOptions {
+            regions: true,
+            distance: true,
+            ..Default::default()
+        };
+        let points = grid.point_cloud(&MeshOptions::default(), &options);
+        assert_eq!(points.positions[0], Vec3::splat(0.5));
+        assert_eq!(
+            points.attribute("region"),
+            Some(&PointValues::U32(vec![0, 0, 0, 1, 2, 2]))
+        );
+        assert_eq!(
+            points.attribute("distance"),
+            Some(&PointValues::U32(vec![1; 6]))
+        );
+        assert!(points.attribute("neighbors").is_none());
+    }
+
+    #[test]
+    fn distance() {
+        let mut grid = Grid3::new(UVec3::new(7, 7, 9));
+        grid.fill(true);
+        let options = PointCloudOptions {
+            distance: true,
+            ..Default::default()
+        };
+        let points = grid.point_cloud(&MeshOptions::default(), &options);
+        let Some(PointValues::U32(distance)) = points.attribute("distance") else {
+            panic!("missing distance");
+        };
+        for (p, d) in points.positions.iter().zip(distance) {
+            let pos = p.floor().as_ivec3();
+            let expected = pos.min(grid.size.as_ivec3() - 1 - pos).min_element() + 1;
+            assert_eq!(*d, expected as u32);
+        }
+
+        // Unallocated grids have no point, but still all attributes
+        let options = PointCloudOptions {
+            neighbors: Some(Neighborhood::Moore),
+            regions: true,
+            distance: true,
+        };
+        let points = Grid3::new(UVec3::ONE).point_cloud(&MeshOptions::default(), &options);
+        assert!(points.is_empty());
+        assert_eq!(points.attributes.len(), 3);
+    }
+}
@@ -0,0 +1,469 @@
+//! Post-processing of generated [`Mesh`]es: vertex welding, normals, and
+//! Taubin smoothing.
+
+use std::collections::HashMap;
+
+use glam::{IVec3, Vec3};
+
+use crate::Mesh;
+
+/// Options controlling the Taubin smoothing of a [`Mesh`].
+///
+/// Each iteration moves every vertex toward the average of its neighbors by a
+/// factor `lambda`, which shrinks the mesh, then away from it by a factor `mu`,
+/// which inflates it back. With `mu = 0`, this is plain Laplacian smoothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothOptions {
+    /// Number of smoothing iterations.
+    pub iterations: u32,
+    /// Shrinking factor, between 0 and 1.
+    pub lambda: f32,
+    /// Inflating factor, negative, and slightly larger than `lambda` in
+    /// absolute value.
+    pub mu: f32,
+    /// Scale each closed part of the mesh after smoothing, so that it encloses
+    /// the same volume as before.
+    pub preserve_volume: bool,
+}
+
+impl Default for SmoothOptions {
+    fn default() -> Self {
+        Self {
+            iterations: 10,
+            lambda: 0.5,
+            mu: -0.53,
+            preserve_volume: false,
+        }
+    }
+}
+
+impl Mesh {
+    /// Build a mesh from a triangle soup, where each group of 3 consecutive
+    /// positions forms a triangle, without sharing vertices.
+    ///
+    /// The normals are left empty. Use [`weld()`] to share the vertices.
+    ///
+    /// [`weld()`]: Self::weld
+    pub fn from_triangles(positions: Vec<Vec3>) -> Self {
+        let count = (positions.len() / 3 * 3) as u32;
+        Self {
+            positions,
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            indices: (0..count).collect(),
+        }
+    }
+
+    /// Merge the vertices which are closer than `epsilon`, or at the exact same
+    /// position if `epsilon` is zero.
+    ///
+    /// The normals of merged vertices are averaged, and the other attributes
+    /// of the first one are kept. Triangles which become degenerate are
+    /// removed, as well as vertices no longer used by any triangle.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    ///
+    /// // The greedy mesh of a box has 4 vertices per side, shared once welded
+    /// let mut mesh = grid.greedy_mesh(&MeshOptions::default());
+    /// assert_eq!(mesh.vertex_count(), 6 * 4);
+    /// mesh.weld(0.);
+    /// assert_eq!(mesh.vertex_count(), 8);
+    /// ```
+    pub fn weld(&mut self, epsilon: f32) {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("weld").entered();
+
+        // Map each vertex to the first vertex close enough to it
+        let mut remap = Vec::with_capacity(self.positions.len());
+        let mut kept: Vec<u32> = vec![];
+        if epsilon > 0. {
+            let mut cells: HashMap<IVec3, Vec<u32>> = HashMap::new();
+            for (i, p) in self.positions.iter().enumerate() {
+                let cell = (*p / epsilon).floor().as_ivec3();
+                let mut found = None;
+                'search: for z in -1..=1 {
+                    for y in -1..=1 {
+                        for x in -1..=1 {
+                            let Some(list) = cells.get(&(cell + IVec3::new(x, y, z))) else {
+                                continue;
+                            };
+                            for j in list {
+                                if self.positions[kept[*j as usize] as usize].distance(*p)
+                                    <= epsilon
+                                {
+                                    found = Some(*j);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+                let j = found.unwrap_or_else(|| {
+                    kept.push(i as u32);
+                    let j = kept.len() as u32 - 1;
+                    cells.entry(cell).or_default().push(j);
+                    j
+                });
+                remap.push(j);
+            }
+        } else {
+            let mut exact: HashMap<[u32; 3], u32> = HashMap::new();
+            for (i, p) in self.positions.iter().enumerate() {
+                let j = *exact
+                    .entry(p.to_array().map(f32::to_bits))
+                    .or_insert_with(|| {
+                        kept.push(i as u32);
+                        kept.len() as u32 - 1
+                    });
+                remap.push(j);
+            }
+        }
+
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for [a, b, c] in self.triangles() {
+            let tri = [a, b, c].map(|i| remap[i as usize]);
+            if tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0] {
+                indices.extend_from_slice(&tri);
+            }
+        }
+
+        // Drop the vertices not used by any triangle anymore
+        let mut used = vec![u32::MAX; kept.len()];
+        let mut source = vec![];
+        let mut normals = vec![];
+        for i in &mut indices {
+            if used[*i as usize] == u32::MAX {
+                used[*i as usize] = source.len() as u32;
+                source.push(kept[*i as usize]);
+            }
+            *i = used[*i as usize];
+        }
+        let positions: Vec<Vec3> = source.iter().map(|i| self.positions[*i as usize]).collect();
+        if self.normals.len() == self.positions.len() {
+            normals.resize(positions.len(), Vec3::ZERO);
+            for (i, n) in self.normals.iter().enumerate() {
+                let j = used[remap[i] as usize];
+                if j != u32::MAX {
+                    normals[j as usize] += *n;
+                }
+            }
+            for n in &mut normals {
+                *n = n.normalize_or_zero();
+            }
+        }
+
+        self.copy_attributes(&source);
+        self.positions = positions;
+        self.normals = normals;
+        self.indices = indices;
+    }
+
+    /// Recompute flat normals, duplicating the vertices so that each triangle
+    /// has its own 3 vertices with the normal of the triangle.
+    pub fn compute_flat_normals(&mut self) {
+        let mut positions = Vec::with_capacity(self.indices.len());
+        let mut normals = Vec::with_capacity(self.indices.len());
+        for [a, b, c] in self.triangles() {
+            let [a, b, c] = [a, b, c].map(|i| self.positions[i as usize]);
+            let n = (b - a).cross(c - a).normalize_or_zero();
+            positions.extend_from_slice(&[a, b, c]);
+            normals.extend_from_slice(&[n; 3]);
+        }
+        self.copy_attributes(&self.indices.clone());
+        self.indices = (0..positions.len() as u32).collect();
+        self.positions = positions;
+        self.normals = normals;
+    }
+
+    /// Smooth the mesh with the Taubin algorithm, then recompute smooth
+    /// normals.
+    ///
+    /// The mesh must be welded, since only vertices shared by triangles are
+    /// considered neighbors. Vertices on the boundary of open meshes, that is
+    /// on edges used by a single triangle, are kept in place.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, SmoothOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 8);
+    /// grid.fill(true);
+    ///
+    /// let mut mesh = grid.greedy_mesh(&MeshOptions::default());
+    /// mesh.weld(0.);
+    /// mesh.smooth(&SmoothOptions::default());
+    /// ```
+    pub fn smooth(&mut self, options: &SmoothOptions) {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("smooth").entered();
+
+        let n = self.positions.len();
+
+        // Neighbors of each vertex, and vertices on a boundary edge
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for [a, b, c] in self.triangles() {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((u.min(v), u.max(v))).or_insert(0) += 1;
+            }
+        }
+        // Sort the edges so that the neighbors of each vertex, and therefore the
+        // sums of their positions, are in a deterministic order
+        let mut edges: Vec<((u32, u32), u32)> = edges.into_iter().collect();
+        edges.sort_unstable();
+        let mut fixed = vec![false; n];
+        let mut offsets = vec![0usize; n + 1];
+        for ((u, v), count) in &edges {
+            if *count == 1 {
+                fixed[*u as usize] = true;
+                fixed[*v as usize] = true;
+            }
+            offsets[*u as usize + 1] += 1;
+            offsets[*v as usize + 1] += 1;
+        }
+        for i in 0..n {
+            offsets[i + 1] += offsets[i];
+        }
+        let mut neighbors = vec![0u32; offsets[n]];
+        let mut fill = offsets.clone();
+        for ((u, v), _) in &edges {
+            neighbors[fill[*u as usize]] = *v;
+            fill[*u as usize] += 1;
+            neighbors[fill[*v as usize]] = *u;
+            fill[*v as usize] += 1;
+        }
+
+        let volumes_before = options.preserve_volume.then(|| {
+            let parts = self.connected_parts(&offsets, &neighbors);
+            let volumes = self.part_volumes(&parts);
+            (parts, volumes)
+        });
+
+        let mut next = self.positions.clone();
+        for _ in 0..options.iterations {
+            for factor in [options.lambda, options.mu] {
+                for i in 0..n {
+                    let list = &neighbors[offsets[i]..offsets[i + 1]];
+                    if fixed[i] || list.is_empty() {
+                        continue;
+                    }
+                    let sum: Vec3 = list.iter().map(|j| self.positions[*j as usize]).sum();
+                    let p = self.positions[i];
+                    next[i] = p + (sum / list.len() as f32 - p) * factor;
+                }
+                std::mem::swap(&mut self.positions, &mut next);
+            }
+        }
+
+        if let Some((parts, before)) = volumes_before {
+            let after = self.part_volumes(&parts);
+            for ((vertices, v0), v1) in parts.vertices.iter().zip(&before).zip(&after) {
+                if vertices.iter().any(|i| fixed[*i as usize]) || *v0 * *v1 <= 0. {
+                    continue;
+                }
+                let scale = (*v0 / *v1).cbrt();
+                let center = vertices
+                    .iter()
+                    .map(|i| self.positions[*i as usize])
+                    .sum::<Vec3>()
+                    / vertices.len() as f32;
+                for i in vertices {
+                    let p = &mut self.positions[*i as usize];
+                    *p = center + (*p - center) * scale;
+                }
+            }
+        }
+
+        self.compute_normals();
+    }
+
+    /// Find the connected parts of the mesh, given the neighbors of each
+    /// vertex.
+    fn connected_parts(&self, offsets: &[usize], neighbors: &[u32]) -> Parts {
+        let n = self.positions.len();
+        let mut part = vec![u32::MAX; n];
+        let mut count = 0;
+        let mut stack = vec![];
+        for start in 0..n {
+            if part[start] != u32::MAX {
+                continue;
+            }
+            part[start] = count;
+            stack.push(start);
+            while let Some(i) = stack.pop() {
+                for j in &neighbors[offsets[i]..offsets[i + 1]] {
+                    if part[*j as usize] == u32::MAX {
+                        part[*j as usize] = count;
+                        stack.push(*j as usize);
+                    }
+                }
+            }
+            count += 1;
+        }
+        let mut vertices = vec![vec![]; count as usize];
+        for (i, part) in part.iter().enumerate() {
+            vertices[*part as usize].push(i as u32);
+        }
+        Parts { part, vertices }
+    }
+
+    /// Compute the signed volume enclosed by each connected part of the mesh.
+    fn part_volumes(&self, parts: &Parts) -> Vec<f32> {
+        let mut volumes = vec![0.; parts.vertices.len()];
+        for [a, b, c] in self.triangles() {
+            let [pa, pb, pc] = [a, b, c].map(|i| self.positions[i as usize]);
+            volumes[parts.part[a as usize] as usize] += pa.dot(pb.cross(pc)) / 6.;
+        }
+        volumes
+    }
+}
+
+/// Connected parts of a mesh.
+struct Parts {
+    /// Part of each vertex.
+    part: Vec<u32>,
+    /// Vertices of each part.
+    vertices: Vec<Vec<u32>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{Grid3, MeshOptions};
+
+    fn ball(radius: f32) -> Grid3 {
+        let mut grid = Grid3::new(UVec3::splat(16));
+        grid.fill(false);
+        let center = Vec3::splat(8.);
+        for z in 0..16 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    let pos = IVec3::new(x, y, z);
+                    if (pos.as_vec3() + 0.5).distance(center) < radius {
+                        grid.set_cell(pos, true);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn weld() {
+        let mut grid = Grid3::new(UVec3::new(13, 8, 21));
+        grid.fill_rand(0.4, StdRng::seed_from_u64(42));
+        let mc = grid.marching_cubes(&MeshOptions::default());
+
+        // Welding a triangle soup gives back the shared vertices
+        let soup: Vec<Vec3> = mc
+            .triangles()
+            .flatten()
+            .map(|i| mc.positions[i as usize])
+            .collect();
+        let mut mesh = Mesh::from_triangles(soup);
+        assert_eq!(mesh.vertex_count(), mc.triangle_count() * 3);
+        mesh.weld(0.);
+        assert_eq!(mesh.vertex_count(), mc.vertex_count());
+        assert_eq!(mesh.triangle_count(), mc.triangle_count());
+        assert!(mesh.is_closed());
+        assert!(mesh.normals.is_empty());
+
+        // Welding with a tolerance merges close vertices, and drops the
+        // collapsed triangles
+        let mut mesh = mc.clone();
+        mesh.positions[0] += Vec3::splat(1e-4);
+        mesh.weld(1e-3);
+        assert_eq!(mesh.vertex_count(), mc.vertex_count());
+        let mut mesh = mc.clone();
+        mesh.weld(0.8);
+        assert!(mesh.vertex_count() < mc.vertex_count());
+        assert!(mesh.triangle_count() < mc.triangle_count());
+        assert_eq!(mesh.normals.len(), mesh.vertex_count());
+        assert!(mesh
+            .indices
+            .iter()
+            .all(|i| (*i as usize) < mesh.vertex_count()));
+
+        // Welded greedy meshes are closed, but may have T-junctions
+        let mut mesh = ball(6.).greedy_mesh(&MeshOptions::default());
+        mesh.weld(0.);
+        assert!(mesh.volume() > 0.);
+        assert_eq!(mesh.normals.len(), mesh.vertex_count());
+    }
+
+    #[test]
+    fn flat_normals() {
+        let mut mesh = ball(5.).marching_cubes(&MeshOptions::default());
+        let volume = mesh.volume();
+        let triangles = mesh.triangle_count();
+        mesh.compute_flat_normals();
+        assert_eq!(mesh.vertex_count(), triangles * 3);
+        assert_eq!(mesh.triangle_count(), triangles);
+        assert!((mesh.volume() - volume).abs() < 1e-3);
+        for [a, b, c] in mesh.triangles() {
+            let n = mesh.normals[a as usize];
+            assert_eq!(n, mesh.normals[b as usize]);
+            assert_eq!(n, mesh.normals[c as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|i| mesh.positions[i as usize]);
+            assert!(n.dot(pb - pa).abs() < 1e-5 && n.dot(pc - pa).abs() < 1e-5);
+            assert!((n.length() - 1.).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn smooth() {
+        let center = Vec3::splat(8.);
+        let radius = 6.;
+        let mut blocky = ball(radius).surface_nets(&MeshOptions::default());
+        blocky.weld(0.);
+        let roughness = |mesh: &Mesh| {
+            let dist: Vec<f32> = mesh.positions.iter().map(|p| p.distance(center)).collect();
+            let mean = dist.iter().sum::<f32>() / dist.len() as f32;
+            dist.iter().map(|d| (d - mean).abs()).sum::<f32>() / dist.len() as f32
+        };
+
+        let mut laplacian = blocky.clone();
+        laplacian.smooth(&SmoothOptions {
+            mu: 0.,
+            ..Default::default()
+        });
+        let mut taubin = blocky.clone();
+        taubin.smooth(&SmoothOptions::default());
+        let mut preserved = blocky.clone();
+        preserved.smooth(&SmoothOptions {
+            preserve_volume: true,
+            ..Default::default()
+        });
+
+        // Smoothing makes the surface rounder, and Taubin smoothing changes its
+        // volume much less than Laplacian smoothing, which shrinks it
+        for mesh in [&laplacian, &taubin, &preserved] {
+            assert!(roughness(mesh) < roughness(&blocky) * 0.75);
+            assert_eq!(mesh.normals.len(), mesh.vertex_count());
+            assert!(mesh.is_closed());
+        }
+        let volume = blocky.volume();
+        assert!(laplacian.volume() < volume);
+        assert!((taubin.volume() - volume).abs() < (laplacian.volume() - volume).abs() / 5.);
+        assert!((preserved.volume() - volume).abs() < volume * 1e-3);
+
+        // Smoothing is deterministic
+        let mut again = blocky.clone();
+        again.smooth(&SmoothOptions::default());
+        assert_eq!(again, taubin);
+
+        // Boundary vertices are fixed
+        let mut open = blocky.clone();
+        open.indices.truncate(open.indices.len() - 3 * 10);
+        let before = open.positions.clone();
+        open.smooth(&SmoothOptions::default());
+        let [a, b, c] = blocky.triangles().last().unwrap();
+        for i in [a, b, c] {
+            assert_eq!(open.positions[i as usize], before[i as usize]);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Reusable steppers applying a cellular automaton rule to a grid repeatedly.
+
+use crate::{Grid2, Grid3, Rule2, Rule3, Scratch2, Scratch3};
+
+/// Simulation applying a [`Rule2`] to a [`Grid2`] repeatedly.
+///
+/// The simulation owns two buffers of cells, the front one holding the current
+/// state of the grid and the back one receiving the next state, which are
+/// swapped after each step. Together with the scratch storage for the neighbor
+/// counts, this means stepping never allocates after the first step, unlike
+/// [`Grid2::apply_rule()`].
+#[derive(Clone)]
+pub struct Simulation2 {
+    rule: Rule2,
+    front: Grid2,
+    back: Vec<u64>,
+    scratch: Scratch2,
+    generation: u64,
+}
+
+impl Simulation2 {
+    /// Create a new simulation of the given rule, starting from `grid`.
+    pub fn new(grid: Grid2, rule: Rule2) -> Self {
+        Self {
+            rule,
+            front: grid,
+            back: vec![],
+            scratch: Scratch2::default(),
+            generation: 0,
+        }
+    }
+
+    /// Get the current state of the grid.
+    #[inline]
+    pub fn grid(&self) -> &Grid2 {
+        &self.front
+    }
+
+    /// Get the current state of the grid, to modify it between two steps.
+    #[inline]
+    pub fn grid_mut(&mut self) -> &mut Grid2 {
+        &mut self.front
+    }
+
+    /// Consume the simulation and return the current state of the grid.
+    pub fn into_grid(self) -> Grid2 {
+        self.front
+    }
+
+    /// Get the rule applied at each step.
+    #[inline]
+    pub fn rule(&self) -> &Rule2 {
+        &self.rule
+    }
+
+    /// Change the rule applied at each subsequent step.
+    #[inline]
+    pub fn set_rule(&mut self, rule: Rule2) {
+        self.rule = rule;
+    }
+
+    /// Get the number of steps applied since the simulation was created.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Apply the rule once to the entire grid.
+    pub fn step(&mut self) {
+        self.front
+            .step_into(&self.rule, &mut self.back, &mut self.scratch);
+        std::mem::swap(&mut self.front.data, &mut self.back);
+        self.generation += 1;
+    }
+
+    /// Apply the rule `n` times to the entire grid.
+    pub fn step_n(&mut self, n: usize) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+}
+
+/// Simulation applying a [`Rule3`] to a [`Grid3`] repeatedly.
+///
+/// The simulation owns two buffers of cells, the front one holding the current
+/// state of the grid and the back one receiving the next state, which are
+/// swapped after each step. Together with the scratch storage for the neighbor
+/// counts, this means stepping never allocates after the first step, unlike
+/// [`Grid3::apply_rule()`].
+///
+/// ```
+/// # use cytogon::{Grid3, Rule3, Simulation3, UVec3};
+/// # use rand::{rngs::StdRng, SeedableRng};
+/// let mut cave = Grid3::new(UVec3::ONE * 32);
+/// cave.fill_rand(0.5, StdRng::seed_from_u64(42));
+///
+/// let mut sim = Simulation3::new(cave, Rule3::SMOOTH);
+/// sim.step_n(20);
+/// assert_eq!(sim.generation(), 20);
+/// let cave = sim.into_grid();
+/// ```
+#[derive(Clone)]
+pub struct Simulation3 {
+    rule: Rule3,
+    front: Grid3,
+    back: Vec<u64>,
+    scratch: Scratch3,
+    generation: u64,
+}
+
+impl Simulation3 {
+    /// Create a new simulation of the given rule, starting from `grid`.
+    pub fn new(grid: Grid3, rule: Rule3) -> Self {
+        Self {
+            rule,
+            front: grid,
+            back: vec![],
+            scratch: Scratch3::default(),
+            generation: 0,
+        }
+    }
+
+    /// Get the current state of the grid.
+    #[inline]
+    pub fn grid(&self) -> &Grid3 {
+        &self.front
+    }
+
+    /// Get the current state of the grid, to modify it between two steps.
+    #[inline]
+    pub fn grid_mut(&mut self) -> &mut Grid3 {
+        &mut self.front
+    }
+
+    /// Consume the simulation and return the current state of the grid.
+    pub fn into_grid(self) -> Grid3 {
+        self.front
+    }
+
+    /// Get the rule applied at each step.
+    #[inline]
+    pub fn rule(&self) -> &Rule3 {
+        &self.rule
+    }
+
+    /// Change the rule applied at each subsequent step.
+    #[inline]
+    pub fn set_rule(&mut self, rule: Rule3) {
+        self.rule = rule;
+    }
+
+    /// Get the number of steps applied since the simulation was created.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Apply the rule once to the entire grid.
+    pub fn step(&mut self) {
+        self.front
+            .step_into(&self.rule, &mut self.back, &mut self.scratch);
+        std::mem::swap(&mut self.front.data, &mut self.back);
+        self.generation += 1;
+    }
+
+    /// Apply the rule `n` times to the entire grid.
+    pub fn step_n(&mut self, n: usize) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, UVec3};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::Boundary;
+
+    #[test]
+    fn step2() {
+        let mut grid = Grid2::new(UVec2::new(37, 21));
+        grid.boundary = Boundary::Periodic;
+        grid.fill_rand(0.45, StdRng::seed_from_u64(42));
+        let life: Rule2 = "B3/S23".parse().unwrap();
+
+        let mut sim = Simulation2::new(grid.clone(), life);
+        sim.step_n(10);
+        for _ in 0..10 {
+            grid.apply_rule(&life);
+        }
+        assert_eq!(sim.generation(), 10);
+        assert_eq!(sim.grid().data, grid.data);
+
+        // Changing the rule applies to subsequent steps only
+        sim.set_rule(Rule2::SMOOTH);
+        sim.step();
+        grid.apply_rule(&Rule2::SMOOTH);
+        assert_eq!(sim.into_grid().data, grid.data);
+    }
+
+    #[test]
+    fn step3() {
+        for rule in [Rule3::SMOOTH, "S1-6/B1,3/N".parse().unwrap()] {
+            let mut grid = Grid3::new(UVec3::new(13, 8, 23));
+            grid.boundary = Boundary::Mirror;
+            grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+
+            let mut sim = Simulation3::new(grid.clone(), rule);
+            sim.step_n(5);
+            for _ in 0..5 {
+                grid.apply_rule(&rule);
+            }
+            assert_eq!(sim.generation(), 5);
+            assert_eq!(sim.grid().data, grid.data);
+        }
+    }
+
+    #[test]
+    fn step2_no_alloc() {
+        for boundary in [
+            Boundary::Dead,
+            Boundary::Alive,
+            Boundary::Periodic,
+            Boundary::Mirror,
+        ] {
+            let mut grid = Grid2::new(UVec2::new(37, 21));
+            grid.boundary = boundary;
+            grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+            let mut sim = Simulation2::new(grid, Rule2::SMOOTH);
+            sim.step();
+
+            // The two cell buffers are swapped but never reallocated
+            let mut buffers = [sim.front.data.as_ptr(), sim.back.as_ptr()];
+            buffers.sort();
+            let planes = sim.scratch.planes.as_ptr();
+            let row = sim.scratch.row.as_ptr();
+            for _ in 0..5 {
+                sim.step();
+                let mut new_buffers = [sim.front.data.as_ptr(), sim.back.as_ptr()];
+                new_buffers.sort();
+                assert_eq!(new_buffers, buffers);
+            }
+            assert_eq!(sim.scratch.planes.as_ptr(), planes);
+            assert_eq!(sim.scratch.row.as_ptr(), row);
+        }
+    }
+
+    #[test]
+    fn step3_no_alloc() {
+        for boundary in [
+            Boundary::Dead,
+            Boundary::Alive,
+            Boundary::Periodic,
+            Boundary::Mirror,
+        ] {
+            let mut grid = Grid3::new(UVec3::ONE * 16);
+            grid.boundary = boundary;
+            grid.fill_rand(0.5, StdRng::seed_from_u64(42));
+            let mut sim = Simulation3::new(grid, Rule3::SMOOTH);
+            sim.step();
+
+            // The two cell buffers are swapped but never reallocated
+            let mut buffers = [sim.front.data.as_ptr(), sim.back.as_ptr()];
+            buffers.sort();
+            let counts = sim.scratch.counts.as_ptr();
+            let planes = sim.scratch.planes.as_ptr();
+            let layer = sim.scratch.layer.as_ptr();
+            for _ in 0..5 {
+                sim.step();
+                let mut new_buffers = [sim.front.data.as_ptr(), sim.back.as_ptr()];
+                new_buffers.sort();
+                assert_eq!(new_buffers, buffers);
+            }
+            assert_eq!(sim.scratch.counts.as_ptr(), counts);
+            assert_eq!(sim.scratch.planes.as_ptr(), planes);
+            assert_eq!(sim.scratch.layer.as_ptr(), layer);
+
+            // Editing the grid between steps is picked up by the next step
+            sim.grid_mut().fill(false);
+            let mut grid = sim.grid().clone();
+            sim.step();
+            grid.apply_rule(&Rule3::SMOOTH);
+            assert_eq!(sim.grid().data, grid.data);
+        }
+    }
+}
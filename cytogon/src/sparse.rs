@@ -0,0 +1,708 @@
+//! Sparse, unbounded grids made of fixed-size chunks allocated on demand.
+//!
+//! A sparse grid stores only the chunks containing at least one alive cell, in
+//! a map indexed by the chunk coordinates. All cells outside those chunks are
+//! dead, so the grid extends infinitely in all directions, and mostly empty
+//! worlds are cheap to represent.
+//!
+//! To apply a rule, each chunk is copied along with a one-block halo taken from
+//! its adjacent chunks into a small dense grid, which is stepped with the same
+//! implementation as [`Grid2`] and [`Grid3`]. This way cells on the seams
+//! between chunks see all their neighbors, and the result is identical to the
+//! one of a dense grid large enough to never reach its boundary.
+
+use std::collections::{HashMap, HashSet};
+
+use glam::{IVec2, IVec3, UVec2, UVec3};
+
+use crate::{Grid2, Grid3, Rule2, Rule3, Scratch2, Scratch3};
+
+/// Number of bit blocks along each axis of a chunk of [`SparseGrid2`].
+const CHUNK_BLOCKS2: i32 = 4;
+
+/// Number of bit blocks along each axis of a chunk of [`SparseGrid2`] and its
+/// halo.
+const HALO_BLOCKS2: i32 = CHUNK_BLOCKS2 + 2;
+
+/// Bit blocks of a chunk of [`SparseGrid2`], in X-major order.
+type Chunk2 = [u64; (CHUNK_BLOCKS2 * CHUNK_BLOCKS2) as usize];
+
+/// Number of bit blocks along each axis of a chunk of [`SparseGrid3`].
+const CHUNK_BLOCKS3: i32 = 4;
+
+/// Number of bit blocks along each axis of a chunk of [`SparseGrid3`] and its
+/// halo.
+const HALO_BLOCKS3: i32 = CHUNK_BLOCKS3 + 2;
+
+/// Bit blocks of a chunk of [`SparseGrid3`], in X-major and Z-minor order.
+type Chunk3 = [u64; (CHUNK_BLOCKS3 * CHUNK_BLOCKS3 * CHUNK_BLOCKS3) as usize];
+
+/// Sparse, unbounded 2D cellular automaton grid.
+///
+/// The grid is made of chunks of 4x4 bit blocks, that is 32x32 cells, which are
+/// allocated when a cell inside them becomes alive and freed when all their
+/// cells are dead.
+///
+/// ```
+/// # use cytogon::{IVec2, Rule2, SparseGrid2};
+/// let mut grid = SparseGrid2::new();
+/// // Glider moving toward X+ and Y-
+/// for pos in [(1, 0), (2, -1), (0, -2), (1, -2), (2, -2)] {
+///     grid.set_cell(IVec2::new(pos.0, pos.1), true);
+/// }
+///
+/// let life: Rule2 = "B3/S23".parse().unwrap();
+/// for _ in 0..4 * 16 {
+///     grid.apply_rule(&life);
+/// }
+/// assert_eq!(grid.population(), 5);
+/// assert!(grid.cell(IVec2::new(17, -16)));
+/// ```
+#[derive(Clone)]
+pub struct SparseGrid2 {
+    /// Allocated chunks, indexed by their coordinates in number of chunks.
+    chunks: HashMap<IVec2, Box<Chunk2>>,
+    /// Dense grid receiving a chunk and its halo.
+    halo: Grid2,
+    /// Next state of the halo grid.
+    next: Vec<u64>,
+    /// Scratch storage for the neighbor counts of the halo grid.
+    scratch: Scratch2,
+}
+
+impl Default for SparseGrid2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseGrid2 {
+    /// Size of a chunk along each axis, in number of cells.
+    pub const CHUNK_SIZE: i32 = CHUNK_BLOCKS2 * 8;
+
+    /// Create a new empty grid.
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            halo: Grid2::new(UVec2::splat(HALO_BLOCKS2 as u32 * 8)),
+            next: vec![],
+            scratch: Scratch2::default(),
+        }
+    }
+
+    /// Resolve the position of a cell to the coordinates of its chunk, and its
+    /// block index and bit inside that chunk.
+    #[inline]
+    fn resolve(pos: IVec2) -> (IVec2, usize, u64) {
+        let cpos = pos.div_euclid(IVec2::splat(Self::CHUNK_SIZE));
+        let local = pos.rem_euclid(IVec2::splat(Self::CHUNK_SIZE));
+        let index = (local.y / 8 * CHUNK_BLOCKS2 + local.x / 8) as usize;
+        let bit = (local.x & 0x7) | ((local.y & 0x7) << 3);
+        (cpos, index, 1u64 << bit)
+    }
+
+    /// Get the state of the cell at the given position.
+    #[inline]
+    pub fn cell(&self, pos: IVec2) -> bool {
+        let (cpos, index, bit) = Self::resolve(pos);
+        self.chunks
+            .get(&cpos)
+            .is_some_and(|chunk| chunk[index] & bit != 0)
+    }
+
+    /// Set the state of the cell at the given position.
+    ///
+    /// This allocates the chunk of the cell if needed, and frees it if all its
+    /// cells are now dead.
+    pub fn set_cell(&mut self, pos: IVec2, value: bool) {
+        if let Some(cpos) = self.write_cell(pos, value) {
+            if self.chunks[&cpos].iter().all(|b| *b == 0) {
+                self.chunks.remove(&cpos);
+            }
+        }
+    }
+
+    /// Set the state of the cell at the given position, without freeing its
+    /// chunk if it becomes empty.
+    ///
+    /// Returns the coordinates of the chunk if the cell was killed in an
+    /// allocated chunk.
+    #[inline]
+    fn write_cell(&mut self, pos: IVec2, value: bool) -> Option<IVec2> {
+        let (cpos, index, bit) = Self::resolve(pos);
+        if value {
+            let chunk = self.chunks.entry(cpos).or_insert_with(|| Box::new([0; 16]));
+            chunk[index] |= bit;
+            None
+        } else {
+            let chunk = self.chunks.get_mut(&cpos)?;
+            chunk[index] &= !bit;
+            Some(cpos)
+        }
+    }
+
+    /// Free all chunks whose cells are all dead.
+    fn free_empty_chunks(&mut self) {
+        self.chunks.retain(|_, chunk| chunk.iter().any(|b| *b != 0));
+    }
+
+    /// Kill all cells and free all chunks.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Check if all cells of the grid are dead.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Get the number of allocated chunks.
+    #[inline]
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Iterate over the coordinates of the allocated chunks, in number of
+    /// chunks, in arbitrary order.
+    pub fn chunks(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Get the number of alive cells.
+    pub fn population(&self) -> u64 {
+        self.chunks
+            .values()
+            .flat_map(|chunk| chunk.iter())
+            .map(|b| b.count_ones() as u64)
+            .sum()
+    }
+
+    /// Get the bounds of the allocated chunks, in number of cells, as the
+    /// minimum position and the maximum position plus one.
+    ///
+    /// All alive cells are inside those bounds. Returns `None` if the grid is
+    /// empty.
+    pub fn bounds(&self) -> Option<(IVec2, IVec2)> {
+        let min = self.chunks.keys().copied().reduce(IVec2::min)?;
+        let max = self.chunks.keys().copied().reduce(IVec2::max)?;
+        Some((min * Self::CHUNK_SIZE, (max + 1) * Self::CHUNK_SIZE))
+    }
+
+    /// Copy all cells of a dense grid into this grid, with the cell (0,0) of
+    /// the dense grid at `origin`.
+    ///
+    /// Both alive and dead cells are copied, so the area covered by the dense
+    /// grid is entirely overwritten.
+    pub fn copy_from_grid(&mut self, origin: IVec2, grid: &Grid2) {
+        if grid.data.is_empty() {
+            return;
+        }
+        for y in 0..grid.size.y as i32 {
+            for x in 0..grid.size.x as i32 {
+                let pos = IVec2::new(x, y);
+                self.write_cell(origin + pos, grid.cell(pos).unwrap());
+            }
+        }
+        self.free_empty_chunks();
+    }
+
+    /// Copy the cells of the area of the given size starting at `origin` into
+    /// a new dense grid.
+    pub fn to_grid(&self, origin: IVec2, size: UVec2) -> Grid2 {
+        let mut grid = Grid2::new(size);
+        grid.fill(false);
+        for y in 0..size.y as i32 {
+            for x in 0..size.x as i32 {
+                let pos = IVec2::new(x, y);
+                if self.cell(origin + pos) {
+                    grid.set_cell(pos, true);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Copy the chunk at `cpos` and its halo into `dst`.
+    ///
+    /// Returns `false` if all cells copied are dead.
+    fn gather_halo(chunks: &HashMap<IVec2, Box<Chunk2>>, cpos: IVec2, dst: &mut Vec<u64>) -> bool {
+        let mut adjacent = [None; 9];
+        for (i, adjacent) in adjacent.iter_mut().enumerate() {
+            let offset = IVec2::new(i as i32 % 3, i as i32 / 3) - 1;
+            *adjacent = chunks.get(&(cpos + offset));
+        }
+
+        dst.clear();
+        let mut any = 0;
+        for hy in 0..HALO_BLOCKS2 {
+            for hx in 0..HALO_BLOCKS2 {
+                let bpos = IVec2::new(hx, hy) - 1;
+                let offset = bpos.div_euclid(IVec2::splat(CHUNK_BLOCKS2)) + 1;
+                let local = bpos.rem_euclid(IVec2::splat(CHUNK_BLOCKS2));
+                let b = adjacent[(offset.y * 3 + offset.x) as usize].map_or(0, |chunk| {
+                    chunk[(local.y * CHUNK_BLOCKS2 + local.x) as usize]
+                });
+                any |= b;
+                dst.push(b);
+            }
+        }
+        any != 0
+    }
+
+    /// Apply the given cellular automaton rule once to the entire grid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rule makes cells with no alive neighbor become alive,
+    /// since this would fill the infinite empty space of the grid.
+    pub fn apply_rule(&mut self, rule: &Rule2) {
+        assert!(
+            rule.birth.to_bits() & 1 == 0,
+            "Sparse grids do not support rules with birth on 0 neighbor."
+        );
+
+        // Any chunk adjacent to an allocated one may receive new cells
+        let mut candidates = HashSet::with_capacity(self.chunks.len() * 4);
+        for cpos in self.chunks.keys() {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    candidates.insert(*cpos + IVec2::new(x, y));
+                }
+            }
+        }
+
+        let mut chunks = HashMap::with_capacity(self.chunks.len());
+        for cpos in candidates {
+            if !Self::gather_halo(&self.chunks, cpos, &mut self.halo.data) {
+                continue;
+            }
+            self.halo.step_into(rule, &mut self.next, &mut self.scratch);
+
+            let mut chunk = [0; 16];
+            let mut any = 0;
+            for y in 0..CHUNK_BLOCKS2 {
+                for x in 0..CHUNK_BLOCKS2 {
+                    let b = self.next[((y + 1) * HALO_BLOCKS2 + x + 1) as usize];
+                    chunk[(y * CHUNK_BLOCKS2 + x) as usize] = b;
+                    any |= b;
+                }
+            }
+            if any != 0 {
+                chunks.insert(cpos, Box::new(chunk));
+            }
+        }
+        self.chunks = chunks;
+    }
+}
+
+/// Sparse, unbounded 3D cellular automaton grid.
+///
+/// The grid is made of chunks of 4x4x4 bit blocks, that is 16x16x16 cells,
+/// which are allocated when a cell inside them becomes alive and freed when all
+/// their cells are dead.
+///
+/// ```
+/// # use cytogon::{IVec3, Rule3, SparseGrid3};
+/// let mut cave = SparseGrid3::new();
+/// cave.set_cell(IVec3::new(-1, 0, 0), true);
+/// cave.set_cell(IVec3::new(0, 0, 0), true);
+/// assert_eq!(cave.chunk_count(), 2);
+///
+/// // Grow the cave outward across the chunk seams
+/// let rule: Rule3 = "S0-26/B1".parse().unwrap();
+/// for _ in 0..8 {
+///     cave.apply_rule(&rule);
+/// }
+/// assert!(cave.cell(IVec3::new(8, 8, 8)));
+/// assert_eq!(cave.chunk_count(), 8);
+/// ```
+#[derive(Clone)]
+pub struct SparseGrid3 {
+    /// Allocated chunks, indexed by their coordinates in number of chunks.
+    chunks: HashMap<IVec3, Box<Chunk3>>,
+    /// Dense grid receiving a chunk and its halo.
+    halo: Grid3,
+    /// Next state of the halo grid.
+    next: Vec<u64>,
+    /// Scratch storage for the neighbor counts of the halo grid.
+    scratch: Scratch3,
+}
+
+impl Default for SparseGrid3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseGrid3 {
+    /// Size of a chunk along each axis, in number of cells.
+    pub const CHUNK_SIZE: i32 = CHUNK_BLOCKS3 * 4;
+
+    /// Create a new empty grid.
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            halo: Grid3::new(UVec3::splat(HALO_BLOCKS3 as u32 * 4)),
+            next: vec![],
+            scratch: Scratch3::default(),
+        }
+    }
+
+    /// Resolve the position of a cell to the coordinates of its chunk, and its
+    /// block index and bit inside that chunk.
+    #[inline]
+    fn resolve(pos: IVec3) -> (IVec3, usize, u64) {
+        let cpos = pos.div_euclid(IVec3::splat(Self::CHUNK_SIZE));
+        let local = pos.rem_euclid(IVec3::splat(Self::CHUNK_SIZE));
+        let bpos = local / 4;
+        let index = ((bpos.z * CHUNK_BLOCKS3 + bpos.y) * CHUNK_BLOCKS3 + bpos.x) as usize;
+        let bit = (local.x & 0x3) | ((local.y & 0x3) << 2) | ((local.z & 0x3) << 4);
+        (cpos, index, 1u64 << bit)
+    }
+
+    /// Get the state of the cell at the given position.
+    #[inline]
+    pub fn cell(&self, pos: IVec3) -> bool {
+        let (cpos, index, bit) = Self::resolve(pos);
+        self.chunks
+            .get(&cpos)
+            .is_some_and(|chunk| chunk[index] & bit != 0)
+    }
+
+    /// Set the state of the cell at the given position.
+    ///
+    /// This allocates the chunk of the cell if needed, and frees it if all its
+    /// cells are now dead.
+    pub fn set_cell(&mut self, pos: IVec3, value: bool) {
+        if let Some(cpos) = self.write_cell(pos, value) {
+            if self.chunks[&cpos].iter().all(|b| *b == 0) {
+                self.chunks.remove(&cpos);
+            }
+        }
+    }
+
+    /// Set the state of the cell at the given position, without freeing its
+    /// chunk if it becomes empty.
+    ///
+    /// Returns the coordinates of the chunk if the cell was killed in an
+    /// allocated chunk.
+    #[inline]
+    fn write_cell(&mut self, pos: IVec3, value: bool) -> Option<IVec3> {
+        let (cpos, index, bit) = Self::resolve(pos);
+        if value {
+            let chunk = self.chunks.entry(cpos).or_insert_with(|| Box::new([0; 64]));
+            chunk[index] |= bit;
+            None
+        } else {
+            let chunk = self.chunks.get_mut(&cpos)?;
+            chunk[index] &= !bit;
+            Some(cpos)
+        }
+    }
+
+    /// Free all chunks whose cells are all dead.
+    fn free_empty_chunks(&mut self) {
+        self.chunks.retain(|_, chunk| chunk.iter().any(|b| *b != 0));
+    }
+
+    /// Kill all cells and free all chunks.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Check if all cells of the grid are dead.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Get the number of allocated chunks.
+    #[inline]
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Iterate over the coordinates of the allocated chunks, in number of
+    /// chunks, in arbitrary order.
+    pub fn chunks(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Get the number of alive cells.
+    pub fn population(&self) -> u64 {
+        self.chunks
+            .values()
+            .flat_map(|chunk| chunk.iter())
+            .map(|b| b.count_ones() as u64)
+            .sum()
+    }
+
+    /// Get the bounds of the allocated chunks, in number of cells, as the
+    /// minimum position and the maximum position plus one.
+    ///
+    /// All alive cells are inside those bounds. Returns `None` if the grid is
+    /// empty.
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        let min = self.chunks.keys().copied().reduce(IVec3::min)?;
+        let max = self.chunks.keys().copied().reduce(IVec3::max)?;
+        Some((min * Self::CHUNK_SIZE, (max + 1) * Self::CHUNK_SIZE))
+    }
+
+    /// Copy all cells of a dense grid into this grid, with the cell (0,0,0) of
+    /// the dense grid at `origin`.
+    ///
+    /// Both alive and dead cells are copied, so the area covered by the dense
+    /// grid is entirely overwritten.
+    pub fn copy_from_grid(&mut self, origin: IVec3, grid: &Grid3) {
+        if grid.data.is_empty() {
+            return;
+        }
+        for z in 0..grid.size.z as i32 {
+            for y in 0..grid.size.y as i32 {
+                for x in 0..grid.size.x as i32 {
+                    let pos = IVec3::new(x, y, z);
+                    self.write_cell(origin + pos, grid.cell(pos).unwrap());
+                }
+            }
+        }
+        self.free_empty_chunks();
+    }
+
+    /// Copy the cells of the area of the given size starting at `origin` into
+    /// a new dense grid.
+    pub fn to_grid(&self, origin: IVec3, size: UVec3) -> Grid3 {
+        let mut grid = Grid3::new(size);
+        grid.fill(false);
+        for z in 0..size.z as i32 {
+            for y in 0..size.y as i32 {
+                for x in 0..size.x as i32 {
+                    let pos = IVec3::new(x, y, z);
+                    if self.cell(origin + pos) {
+                        grid.set_cell(pos, true);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// Copy the chunk at `cpos` and its halo into `dst`.
+    ///
+    /// Returns `false` if all cells copied are dead.
+    fn gather_halo(chunks: &HashMap<IVec3, Box<Chunk3>>, cpos: IVec3, dst: &mut Vec<u64>) -> bool {
+        let mut adjacent = [None; 27];
+        for (i, adjacent) in adjacent.iter_mut().enumerate() {
+            let i = i as i32;
+            let offset = IVec3::new(i % 3, i / 3 % 3, i / 9) - 1;
+            *adjacent = chunks.get(&(cpos + offset));
+        }
+
+        dst.clear();
+        let mut any = 0;
+        for hz in 0..HALO_BLOCKS3 {
+            for hy in 0..HALO_BLOCKS3 {
+                for hx in 0..HALO_BLOCKS3 {
+                    let bpos = IVec3::new(hx, hy, hz) - 1;
+                    let offset = bpos.div_euclid(IVec3::splat(CHUNK_BLOCKS3)) + 1;
+                    let local = bpos.rem_euclid(IVec3::splat(CHUNK_BLOCKS3));
+                    let index = (local.z * CHUNK_BLOCKS3 + local.y) * CHUNK_BLOCKS3 + local.x;
+                    let b = adjacent[((offset.z * 3 + offset.y) * 3 + offset.x) as usize]
+                        .map_or(0, |chunk| chunk[index as usize]);
+                    any |= b;
+                    dst.push(b);
+                }
+            }
+        }
+        any != 0
+    }
+
+    /// Apply the given cellular automaton rule once to the entire grid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rule makes cells with no alive neighbor become alive,
+    /// since this would fill the infinite empty space of the grid.
+    pub fn apply_rule(&mut self, rule: &Rule3) {
+        assert!(
+            rule.birth.to_bits() & 1 == 0,
+            "Sparse grids do not support rules with birth on 0 neighbor."
+        );
+
+        // Any chunk adjacent to an allocated one may receive new cells
+        let mut candidates = HashSet::with_capacity(self.chunks.len() * 8);
+        for cpos in self.chunks.keys() {
+            for z in -1..=1 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        candidates.insert(*cpos + IVec3::new(x, y, z));
+                    }
+                }
+            }
+        }
+
+        let mut chunks = HashMap::with_capacity(self.chunks.len());
+        for cpos in candidates {
+            if !Self::gather_halo(&self.chunks, cpos, &mut self.halo.data) {
+                continue;
+            }
+            self.halo.step_into(rule, &mut self.next, &mut self.scratch);
+
+            let mut chunk = [0; 64];
+            let mut any = 0;
+            for z in 0..CHUNK_BLOCKS3 {
+                for y in 0..CHUNK_BLOCKS3 {
+                    for x in 0..CHUNK_BLOCKS3 {
+                        let src = ((z + 1) * HALO_BLOCKS3 + y + 1) * HALO_BLOCKS3 + x + 1;
+                        let b = self.next[src as usize];
+                        chunk[((z * CHUNK_BLOCKS3 + y) * CHUNK_BLOCKS3 + x) as usize] = b;
+                        any |= b;
+                    }
+                }
+            }
+            if any != 0 {
+                chunks.insert(cpos, Box::new(chunk));
+            }
+        }
+        self.chunks = chunks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn cell2() {
+        let mut grid = SparseGrid2::new();
+        assert!(grid.is_empty());
+        assert!(grid.bounds().is_none());
+
+        let positions = [IVec2::new(0, 0), IVec2::new(-1, 31), IVec2::new(32, -33)];
+        for pos in positions {
+            grid.set_cell(pos, true);
+        }
+        for pos in positions {
+            assert!(grid.cell(pos));
+        }
+        assert!(!grid.cell(IVec2::new(1, 0)));
+        assert_eq!(grid.chunk_count(), 3);
+        assert_eq!(grid.population(), 3);
+        assert_eq!(
+            grid.bounds(),
+            Some((IVec2::new(-32, -64), IVec2::new(64, 32)))
+        );
+
+        // Killing the last cell of a chunk frees it
+        grid.set_cell(IVec2::new(-1, 31), false);
+        assert_eq!(grid.chunk_count(), 2);
+        grid.clear();
+        assert!(grid.is_empty());
+    }
+
+    #[test]
+    fn cell3() {
+        let mut grid = SparseGrid3::new();
+        let positions = [
+            IVec3::new(0, 0, 0),
+            IVec3::new(-1, 15, 16),
+            IVec3::new(5, -17, -100),
+        ];
+        for pos in positions {
+            grid.set_cell(pos, true);
+        }
+        for pos in positions {
+            assert!(grid.cell(pos));
+        }
+        assert!(!grid.cell(IVec3::new(0, 0, 1)));
+        assert_eq!(grid.chunk_count(), 3);
+        assert_eq!(grid.population(), 3);
+
+        grid.set_cell(IVec3::new(5, -17, -100), false);
+        assert_eq!(grid.chunk_count(), 2);
+    }
+
+    #[test]
+    fn grid_roundtrip3() {
+        let mut dense = Grid3::new(UVec3::new(21, 7, 18));
+        dense.fill_rand(0.5, StdRng::seed_from_u64(42));
+
+        let origin = IVec3::new(-9, 3, -16);
+        let mut grid = SparseGrid3::new();
+        grid.copy_from_grid(origin, &dense);
+        assert_eq!(grid.to_grid(origin, dense.size).data, dense.data);
+
+        // Dead cells overwrite the area
+        dense.fill(false);
+        grid.copy_from_grid(origin, &dense);
+        assert!(grid.is_empty());
+    }
+
+    #[test]
+    fn apply_rule2() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let origin = IVec2::splat(-64);
+        let mut dense = Grid2::new(UVec2::splat(128));
+        dense.fill(false);
+        let mut grid = SparseGrid2::new();
+        for _ in 0..300 {
+            let pos = IVec2::new(rng.gen_range(-12..12), rng.gen_range(-12..12));
+            grid.set_cell(pos, true);
+            dense.set_cell(pos - origin, true);
+        }
+
+        let life: Rule2 = "B3/S23".parse().unwrap();
+        for _ in 0..20 {
+            grid.apply_rule(&life);
+            dense.apply_rule(&life);
+            assert_eq!(grid.to_grid(origin, dense.size).data, dense.data);
+        }
+    }
+
+    #[test]
+    fn apply_rule3() {
+        let rules: [Rule3; 2] = ["S2-6/B4/M".parse().unwrap(), "S1-6/B1,3/N".parse().unwrap()];
+        for rule in rules {
+            let mut rng = StdRng::seed_from_u64(42);
+            let origin = IVec3::splat(-32);
+            let mut dense = Grid3::new(UVec3::splat(64));
+            dense.fill(false);
+            let mut grid = SparseGrid3::new();
+            for _ in 0..200 {
+                let pos = IVec3::new(
+                    rng.gen_range(-4..4),
+                    rng.gen_range(-4..4),
+                    rng.gen_range(-4..4),
+                );
+                grid.set_cell(pos, true);
+                dense.set_cell(pos - origin, true);
+            }
+
+            for _ in 0..6 {
+                grid.apply_rule(&rule);
+                dense.apply_rule(&rule);
+                assert_eq!(grid.to_grid(origin, dense.size).data, dense.data);
+            }
+        }
+    }
+
+    #[test]
+    fn free_chunks3() {
+        let mut grid = SparseGrid3::new();
+        grid.set_cell(IVec3::new(15, 15, 15), true);
+        assert_eq!(grid.chunk_count(), 1);
+
+        // An isolated cell dies, and its chunk is freed
+        grid.apply_rule(&Rule3::SMOOTH);
+        assert!(grid.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn birth_on_zero() {
+        let mut grid = SparseGrid3::new();
+        grid.apply_rule(&Rule3::new(0u8..=0u8, 0u8..=0u8));
+    }
+}
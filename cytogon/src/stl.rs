@@ -0,0 +1,222 @@
+//! Export of [`Mesh`]es to the binary and ASCII STL formats, mostly used for
+//! 3D printing.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::{Mesh, MeshValidity};
+
+/// Options controlling the export of a [`Mesh`] to STL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StlOptions {
+    /// Name of the solid.
+    pub name: String,
+    /// Write the human-readable ASCII variant of the format instead of the
+    /// more compact binary one.
+    pub ascii: bool,
+}
+
+impl Default for StlOptions {
+    fn default() -> Self {
+        Self {
+            name: "mesh".to_string(),
+            ascii: false,
+        }
+    }
+}
+
+impl Mesh {
+    /// Write the mesh in the STL format.
+    ///
+    /// STL stores each triangle with its own vertices and a flat normal, so
+    /// programs reading it weld the vertices at the same position. Slicers
+    /// expect the welded mesh to be watertight and manifold, so the mesh is
+    /// checked with [`validate()`] and the result returned, although the mesh
+    /// is written whatever its validity. Meshes from [`Grid3::cube_mesh()`]
+    /// after [`Grid3::resolve_non_manifold()`], and meshes from
+    /// [`Grid3::marching_cubes()`], are always valid.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, StlOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    /// grid.resolve_non_manifold();
+    /// let mesh = grid.cube_mesh(&MeshOptions::default());
+    ///
+    /// let mut stl = vec![];
+    /// let validity = mesh.write_stl(&mut stl, &StlOptions::default()).unwrap();
+    /// assert!(validity.is_valid());
+    /// // Header, triangle count, and 50 bytes per triangle
+    /// assert_eq!(stl.len(), 84 + 50 * mesh.triangle_count());
+    /// ```
+    ///
+    /// [`validate()`]: Self::validate
+    /// [`Grid3::cube_mesh()`]: crate::Grid3::cube_mesh
+    /// [`Grid3::resolve_non_manifold()`]: crate::Grid3::resolve_non_manifold
+    /// [`Grid3::marching_cubes()`]: crate::Grid3::marching_cubes
+    pub fn write_stl<W: Write>(&self, writer: W, options: &StlOptions) -> io::Result<MeshValidity> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("write_stl").entered();
+
+        let validity = self.validate();
+        let mut w = BufWriter::new(writer);
+        let facets = self.triangles().map(|tri| {
+            let [a, b, c] = tri.map(|i| self.positions[i as usize]);
+            let n = (b - a).cross(c - a).normalize_or_zero();
+            [n, a, b, c]
+        });
+
+        if options.ascii {
+            writeln!(w, "solid {}", options.name)?;
+            for facet in facets {
+                let [n, vertices @ ..] = facet;
+                writeln!(w, "facet normal {:e} {:e} {:e}", n.x, n.y, n.z)?;
+                writeln!(w, "  outer loop")?;
+                for p in vertices {
+                    writeln!(w, "    vertex {:e} {:e} {:e}", p.x, p.y, p.z)?;
+                }
+                writeln!(w, "  endloop")?;
+                writeln!(w, "endfacet")?;
+            }
+            writeln!(w, "endsolid {}", options.name)?;
+        } else {
+            let count = u32::try_from(self.triangle_count())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many triangles"))?;
+            // Readers may take a header starting with "solid" for ASCII STL
+            let mut header = [0u8; 80];
+            let text = format!("cytogon {}", options.name);
+            let len = text.len().min(header.len());
+            header[..len].copy_from_slice(&text.as_bytes()[..len]);
+            w.write_all(&header)?;
+            w.write_all(&count.to_le_bytes())?;
+            for facet in facets {
+                for v in facet {
+                    for x in v.to_array() {
+                        w.write_all(&x.to_le_bytes())?;
+                    }
+                }
+                // Attribute byte count, unused
+                w.write_all(&[0; 2])?;
+            }
+        }
+        w.flush()?;
+        Ok(validity)
+    }
+
+    /// Save the mesh to an STL file.
+    ///
+    /// See [`write_stl()`] for details.
+    ///
+    /// [`write_stl()`]: Self::write_stl
+    pub fn save_stl(
+        &self,
+        path: impl AsRef<Path>,
+        options: &StlOptions,
+    ) -> io::Result<MeshValidity> {
+        self.write_stl(File::create(path)?, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::*;
+    use crate::{Grid3, MeshOptions, TempDir};
+
+    /// Parse the normal and vertices of the facets of a binary STL file.
+    fn parse_binary(stl: &[u8]) -> Vec<[Vec3; 4]> {
+        let count = u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize;
+        assert_eq!(stl.len(), 84 + count * 50);
+        stl[84..]
+            .chunks_exact(50)
+            .map(|facet| {
+                assert_eq!(facet[48..], [0, 0]);
+                std::array::from_fn(|v| {
+                    Vec3::from_array(std::array::from_fn(|i| {
+                        let offset = (v * 3 + i) * 4;
+                        f32::from_le_bytes(facet[offset..offset + 4].try_into().unwrap())
+                    }))
+                })
+            })
+            .collect()
+    }
+
+    /// Parse the normal and vertices of the facets of an ASCII STL file.
+    fn parse_ascii(stl: &str) -> Vec<[Vec3; 4]> {
+        let mut values = vec![];
+        for line in stl.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let coords = match tokens[..] {
+                ["facet", "normal", ..] => &tokens[2..],
+                ["vertex", ..] => &tokens[1..],
+                _ => continue,
+            };
+            let v: Vec<f32> = coords.iter().map(|t| t.parse().unwrap()).collect();
+            values.push(Vec3::new(v[0], v[1], v[2]));
+        }
+        values
+            .chunks_exact(4)
+            .map(|f| [f[0], f[1], f[2], f[3]])
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut grid = Grid3::new(UVec3::splat(6));
+        grid.fill(false);
+        grid.set_cell(IVec3::new(1, 1, 1), true);
+        grid.set_cell(IVec3::new(2, 2, 1), true);
+        grid.set_cell(IVec3::new(4, 4, 4), true);
+        let options = MeshOptions {
+            voxel_size: Vec3::new(0.5, 0.25, 2.),
+            origin: Vec3::new(-1.5, 3., 0.125),
+        };
+        let mesh = grid.cube_mesh(&options);
+        let expected: Vec<[Vec3; 4]> = mesh
+            .triangles()
+            .map(|tri| {
+                let [a, b, c] = tri.map(|i| mesh.positions[i as usize]);
+                [(b - a).cross(c - a).normalize(), a, b, c]
+            })
+            .collect();
+
+        let mut stl = vec![];
+        let validity = mesh.write_stl(&mut stl, &StlOptions::default()).unwrap();
+        assert_eq!(validity.non_manifold_edges, 1);
+        assert!(stl.starts_with(b"cytogon mesh\0"));
+        assert_eq!(parse_binary(&stl), expected);
+
+        let options = StlOptions {
+            name: "cave".to_string(),
+            ascii: true,
+        };
+        let mut stl = vec![];
+        mesh.write_stl(&mut stl, &options).unwrap();
+        let stl = String::from_utf8(stl).unwrap();
+        assert!(stl.starts_with("solid cave\n"));
+        assert!(stl.ends_with("endsolid cave\n"));
+        assert_eq!(parse_ascii(&stl), expected);
+
+        // Once resolved, the mesh is valid
+        grid.resolve_non_manifold();
+        let mesh = grid.cube_mesh(&MeshOptions::default());
+        let validity = mesh.write_stl(&mut vec![], &options).unwrap();
+        assert!(validity.is_valid());
+    }
+
+    #[test]
+    fn save() {
+        let dir = TempDir::new("stl");
+        let mut grid = Grid3::new(UVec3::splat(4));
+        grid.fill(true);
+        let mesh = grid.marching_cubes(&MeshOptions::default());
+        let validity = mesh
+            .save_stl(dir.join("box.stl"), &StlOptions::default())
+            .unwrap();
+        assert!(validity.is_valid());
+        let stl = std::fs::read(dir.join("box.stl")).unwrap();
+        assert_eq!(parse_binary(&stl).len(), mesh.triangle_count());
+    }
+}
@@ -0,0 +1,281 @@
+//! Naive Surface Nets surface extraction for [`Grid3`].
+//!
+//! Like with marching cubes, the grid is sampled at the center of each cell,
+//! and the sampling lattice is made of cubes joining 8 adjacent cell centers.
+//! Instead of triangulating each cube, a single vertex is placed in each cube
+//! crossed by the surface, at the average of the middle of its crossed edges.
+//! Then for each crossed lattice edge, a quad joins the vertices of the 4
+//! cubes around that edge. This gives a quad-dominant mesh with one quad per
+//! face between an alive and a dead cell, like a blocky mesh, but with shared
+//! vertices smoothly placed along the surface.
+
+use std::sync::OnceLock;
+
+use glam::{IVec3, Vec3};
+
+use crate::marching_cubes::{corner, EDGES};
+use crate::{Grid3, Mesh, MeshOptions};
+
+/// Get the position of the vertex of a cube for each set of alive corners,
+/// relative to the minimum corner of the cube.
+fn vertex_table() -> &'static [Vec3; 256] {
+    static TABLE: OnceLock<[Vec3; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|config| {
+            let alive = |c: u8| (config >> c) & 1 != 0;
+            let mut sum = Vec3::ZERO;
+            let mut count = 0;
+            for (c, axis) in EDGES {
+                if alive(c) != alive(c | (1 << axis)) {
+                    let mut p = corner(c).as_vec3();
+                    p[axis as usize] += 0.5;
+                    sum += p;
+                    count += 1;
+                }
+            }
+            if count > 0 {
+                sum / count as f32
+            } else {
+                Vec3::ZERO
+            }
+        })
+    })
+}
+
+/// Push the two triangles of a quad, split along its shortest diagonal.
+fn push_quad(mesh: &mut Mesh, quad: [u32; 4]) {
+    let p = quad.map(|i| mesh.positions[i as usize]);
+    let [a, b, c, d] = quad;
+    if p[0].distance_squared(p[2]) <= p[1].distance_squared(p[3]) {
+        mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+    } else {
+        mesh.indices.extend_from_slice(&[a, b, d, b, c, d]);
+    }
+}
+
+impl Grid3 {
+    /// Extract a smooth mesh of the surface between alive and dead cells with
+    /// the Naive Surface Nets algorithm.
+    ///
+    /// Cells outside the grid are considered dead, so the mesh is always
+    /// watertight, even where alive cells touch the edges of the grid. Its
+    /// normals point from the alive cells toward the dead ones. Where alive
+    /// cells only touch by an edge or a corner, the two surfaces share their
+    /// vertices, so the mesh is not always manifold.
+    ///
+    /// ```
+    /// # use cytogon::{Grid3, MeshOptions, UVec3};
+    /// let mut grid = Grid3::new(UVec3::ONE * 4);
+    /// grid.fill(true);
+    ///
+    /// let mesh = grid.surface_nets(&MeshOptions::default());
+    /// // One vertex per cube of the lattice crossed by the surface, that is on
+    /// // the border of a 5x5x5 lattice of cubes
+    /// assert_eq!(mesh.vertex_count(), 5 * 5 * 5 - 3 * 3 * 3);
+    /// // One quad per face of the cells on the border of the grid
+    /// assert_eq!(mesh.triangle_count(), 6 * 4 * 4 * 2);
+    /// ```
+    pub fn surface_nets(&self, options: &MeshOptions) -> Mesh {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("surface_nets").entered();
+
+        let mut mesh = Mesh::default();
+        if self.data.is_empty() {
+            return mesh;
+        }
+        let table = vertex_table();
+
+        // The lattice has one point per cell center, plus one layer of dead
+        // cells around the grid, and there are n-1 cubes along each axis.
+        let n = self.size.as_ivec3() + 2;
+        let plane_len = (n.x * n.y) as usize;
+        let layer_len = ((n.x - 1) * (n.y - 1)) as usize;
+        let index = |p: IVec3| (p.y * n.x + p.x) as usize;
+        let cube_index = |p: IVec3| (p.y * (n.x - 1) + p.x) as usize;
+        let mut planes = [vec![false; plane_len], vec![false; plane_len]];
+        // Vertex of each cube of the two current layers of cubes
+        let mut layers = [vec![u32::MAX; layer_len], vec![u32::MAX; layer_len]];
+
+        let sample = |z: i32, plane: &mut [bool]| {
+            for y in 0..n.y {
+                for x in 0..n.x {
+                    let pos = IVec3::new(x, y, z) - 1;
+                    plane[index(pos + 1)] = self.cell(pos).unwrap_or(false);
+                }
+            }
+        };
+
+        sample(0, &mut planes[0]);
+        for z in 0..n.z - 1 {
+            let lo = (z & 1) as usize;
+            let hi = lo ^ 1;
+            sample(z + 1, &mut planes[hi]);
+
+            // Place the vertices of the cubes of layer Z
+            for y in 0..n.y - 1 {
+                for x in 0..n.x - 1 {
+                    let base = IVec3::new(x, y, z);
+                    let mut config = 0u8;
+                    for c in 0..8 {
+                        let p = base + corner(c);
+                        if planes[(p.z & 1) as usize][index(p)] {
+                            config |= 1 << c;
+                        }
+                    }
+                    let id = &mut layers[lo][cube_index(base)];
+                    if config == 0 || config == 0xFF {
+                        *id = u32::MAX;
+                    } else {
+                        *id = mesh.positions.len() as u32;
+                        // Lattice point p is the center of cell p-1
+                        let pos = base.as_vec3() - 0.5 + table[config as usize];
+                        mesh.positions.push(options.to_mesh(pos));
+                    }
+                }
+            }
+
+            // Get the vertex of the cube at (x, y) in the layer at Z offset dz
+            // (-1 or 0) from the current one.
+            let vertex = |x: i32, y: i32, dz: i32| {
+                let layer = if dz == 0 { lo } else { hi };
+                layers[layer][cube_index(IVec3::new(x, y, 0))]
+            };
+
+            // Emit a quad for each crossed edge whose 4 cubes are known, that
+            // is the Z edges of layer Z, and the X and Y edges of plane Z. The
+            // quad is counter-clockwise around the axis of the edge if its
+            // start is alive, and clockwise otherwise.
+            for y in 1..n.y - 1 {
+                for x in 1..n.x - 1 {
+                    let p = IVec3::new(x, y, 0);
+                    let alive = planes[lo][index(p)];
+                    if alive != planes[hi][index(p)] {
+                        let mut quad = [
+                            vertex(x - 1, y - 1, 0),
+                            vertex(x, y - 1, 0),
+                            vertex(x, y, 0),
+                            vertex(x - 1, y, 0),
+                        ];
+                        if !alive {
+                            quad.reverse();
+                        }
+                        push_quad(&mut mesh, quad);
+                    }
+                }
+            }
+            if z == 0 {
+                // Plane 0 is made of dead cells only
+                continue;
+            }
+            for y in 0..n.y - 1 {
+                for x in 0..n.x - 1 {
+                    let p = IVec3::new(x, y, 0);
+                    let alive = planes[lo][index(p)];
+                    if y > 0 && alive != planes[lo][index(p + IVec3::X)] {
+                        let mut quad = [
+                            vertex(x, y - 1, -1),
+                            vertex(x, y, -1),
+                            vertex(x, y, 0),
+                            vertex(x, y - 1, 0),
+                        ];
+                        if !alive {
+                            quad.reverse();
+                        }
+                        push_quad(&mut mesh, quad);
+                    }
+                    if x > 0 && alive != planes[lo][index(p + IVec3::Y)] {
+                        let mut quad = [
+                            vertex(x - 1, y, -1),
+                            vertex(x - 1, y, 0),
+                            vertex(x, y, 0),
+                            vertex(x, y, -1),
+                        ];
+                        if !alive {
+                            quad.reverse();
+                        }
+                        push_quad(&mut mesh, quad);
+                    }
+                }
+            }
+        }
+
+        mesh.compute_normals();
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+
+    use super::*;
+    use crate::mesh;
+
+    #[test]
+    fn watertight() {
+        for grid in mesh::random_grids() {
+            let mesh = grid.surface_nets(&MeshOptions::default());
+            assert!(!mesh.is_empty());
+            assert!(mesh.is_watertight());
+            assert!(mesh.volume() > 0.);
+            assert_eq!(mesh.normals.len(), mesh.positions.len());
+
+            // Fewer vertices than marching cubes
+            let mc = grid.marching_cubes(&MeshOptions::default());
+            assert!(mesh.vertex_count() < mc.vertex_count());
+        }
+    }
+
+    #[test]
+    fn smooth_blob() {
+        // Ball of cells touching the edges of the grid
+        let mut grid = Grid3::new(UVec3::splat(12));
+        grid.fill(false);
+        let center = Vec3::splat(6.);
+        for z in 0..12 {
+            for y in 0..12 {
+                for x in 0..12 {
+                    let pos = IVec3::new(x, y, z);
+                    if (pos.as_vec3() + 0.5).distance(center) < 6.5 {
+                        grid.set_cell(pos, true);
+                    }
+                }
+            }
+        }
+        let options = MeshOptions {
+            voxel_size: Vec3::splat(0.25),
+            origin: Vec3::splat(-1.5),
+        };
+        let mesh = grid.surface_nets(&options);
+        assert!(mesh.is_closed());
+        assert!(mesh.volume() > 0.);
+
+        // One quad per face between an alive and a dead cell
+        let mut faces = 0;
+        for z in -1..=12 {
+            for y in -1..=12 {
+                for x in -1..=12 {
+                    let pos = IVec3::new(x, y, z);
+                    let alive = grid.cell(pos).unwrap_or(false);
+                    for d in [IVec3::X, IVec3::Y, IVec3::Z] {
+                        if alive != grid.cell(pos + d).unwrap_or(false) {
+                            faces += 1;
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(mesh.triangle_count(), faces * 2);
+
+        // Normals point outward
+        let center = options.to_mesh(center);
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!((*p - center).dot(*n) > 0.);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        mesh::assert_empty(Grid3::surface_nets);
+    }
+}
@@ -0,0 +1,486 @@
+//! Ear clipping triangulation of [`Polygon`]s with holes.
+//!
+//! Holes are first joined to the outer boundary by bridges, cutting the
+//! polygon into a single simple polygon whose bridge vertices are duplicated.
+//! Then ears, that is convex vertices whose triangle contains no other vertex,
+//! are clipped one by one until a single triangle remains.
+
+use glam::Vec2;
+
+use crate::{ContourOptions, Grid2, Mesh2, Polygon};
+
+/// Vertex of the circular doubly linked list of the polygon being clipped.
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    /// Index of the point in the polygon.
+    index: u32,
+    pos: Vec2,
+    prev: usize,
+    next: usize,
+}
+
+/// Get twice the signed area of triangle `abc`, positive if it's
+/// counter-clockwise.
+#[inline]
+fn orient(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b - a).perp_dot(c - a)
+}
+
+/// Check if `p` is inside triangle `abc` or on its boundary.
+#[inline]
+fn in_triangle(a: Vec2, b: Vec2, c: Vec2, p: Vec2) -> bool {
+    orient(a, b, p) >= 0. && orient(b, c, p) >= 0. && orient(c, a, p) >= 0.
+}
+
+struct Ring {
+    nodes: Vec<Node>,
+}
+
+impl Ring {
+    /// Add a closed loop of points, and get the index of its first node.
+    fn push_loop(&mut self, points: &[Vec2], first_index: u32) -> usize {
+        let start = self.nodes.len();
+        let n = points.len();
+        for (i, pos) in points.iter().enumerate() {
+            self.nodes.push(Node {
+                index: first_index + i as u32,
+                pos: *pos,
+                prev: start + (i + n - 1) % n,
+                next: start + (i + 1) % n,
+            });
+        }
+        start
+    }
+
+    fn remove(&mut self, i: usize) {
+        let Node { prev, next, .. } = self.nodes[i];
+        self.nodes[prev].next = next;
+        self.nodes[next].prev = prev;
+    }
+
+    fn iter(&self, start: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut i = Some(start);
+        std::iter::from_fn(move || {
+            let cur = i?;
+            let next = self.nodes[cur].next;
+            i = (next != start).then_some(next);
+            Some(cur)
+        })
+    }
+
+    /// Check if the direction from node `a` toward `p` goes into the interior
+    /// of the polygon.
+    fn locally_inside(&self, a: usize, p: Vec2) -> bool {
+        let Node {
+            pos, prev, next, ..
+        } = self.nodes[a];
+        let (prev, next) = (self.nodes[prev].pos, self.nodes[next].pos);
+        if orient(prev, pos, next) >= 0. {
+            orient(pos, next, p) >= 0. && orient(pos, p, prev) >= 0.
+        } else {
+            !(orient(pos, prev, p) > 0. && orient(pos, p, next) > 0.)
+        }
+    }
+
+    /// Find the node of the ring starting at `start` to bridge the hole vertex
+    /// at `m` to, with the algorithm of David Eberly.
+    fn find_bridge(&self, start: usize, m: Vec2) -> Option<usize> {
+        // Cast a ray toward +X, and find the closest edge it hits
+        let mut hit: Option<(f32, usize)> = None;
+        for i in self.iter(start) {
+            let a = self.nodes[i].pos;
+            let j = self.nodes[i].next;
+            let b = self.nodes[j].pos;
+            let candidate = if a.y == m.y {
+                (a.x >= m.x).then_some((a.x, i))
+            } else if (a.y > m.y) != (b.y > m.y) && b.y != m.y {
+                let x = a.x + (m.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                (x >= m.x).then_some((x, if a.x > b.x { i } else { j }))
+            } else {
+                None
+            };
+            if let Some((x, node)) = candidate {
+                if hit.map_or(true, |(best, _)| x < best) {
+                    hit = Some((x, node));
+                }
+            }
+        }
+        let (x, mut bridge) = hit?;
+
+        // If the ray hits the inside of an edge, vertices inside the triangle
+        // between the hit point and the end of the edge may hide it, and the
+        // one with the smallest angle to the ray is visible instead.
+        let hit = Vec2::new(x, m.y);
+        let p = self.nodes[bridge].pos;
+        if p != hit {
+            let (t0, t1) = if p.y > m.y { (hit, p) } else { (p, hit) };
+            let mut best = (f32::INFINITY, f32::INFINITY);
+            for i in self.iter(start) {
+                let r = self.nodes[i].pos;
+                if r == p || r.x < m.x || !in_triangle(m, t0, t1, r) {
+                    continue;
+                }
+                let dx = r.x - m.x;
+                let tan = if dx > 0. {
+                    (r.y - m.y).abs() / dx
+                } else {
+                    f32::INFINITY
+                };
+                if (tan, dx) < best {
+                    best = (tan, dx);
+                    bridge = i;
+                }
+            }
+        }
+
+        // The bridge vertex may be duplicated by a previous bridge, so pick the
+        // copy facing the hole
+        let pos = self.nodes[bridge].pos;
+        Some(
+            self.iter(start)
+                .find(|i| self.nodes[*i].pos == pos && self.locally_inside(*i, m))
+                .unwrap_or(bridge),
+        )
+    }
+
+    /// Join the node `b` of a hole to the node `a` of the outer ring, by
+    /// duplicating both nodes.
+    fn split(&mut self, a: usize, b: usize) {
+        let a2 = self.nodes.len();
+        let b2 = a2 + 1;
+        let an = self.nodes[a].next;
+        let bp = self.nodes[b].prev;
+        self.nodes.push(self.nodes[a]);
+        self.nodes.push(self.nodes[b]);
+
+        self.nodes[a].next = b;
+        self.nodes[b].prev = a;
+        self.nodes[a2].next = an;
+        self.nodes[an].prev = a2;
+        self.nodes[b2].next = a2;
+        self.nodes[a2].prev = b2;
+        self.nodes[bp].next = b2;
+        self.nodes[b2].prev = bp;
+    }
+
+    fn is_ear(&self, ear: usize) -> bool {
+        let Node {
+            pos: b, prev, next, ..
+        } = self.nodes[ear];
+        let (a, c) = (self.nodes[prev].pos, self.nodes[next].pos);
+        if orient(a, b, c) <= 0. {
+            return false;
+        }
+        let mut i = self.nodes[next].next;
+        while i != prev {
+            let p = self.nodes[i].pos;
+            if p != a && p != b && p != c && in_triangle(a, b, c, p) {
+                return false;
+            }
+            i = self.nodes[i].next;
+        }
+        true
+    }
+}
+
+impl Polygon {
+    /// Iterate over the points of the outer boundary, then of each hole in
+    /// order.
+    pub fn points(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.outer
+            .points
+            .iter()
+            .chain(self.holes.iter().flat_map(|h| &h.points))
+            .copied()
+    }
+
+    /// Triangulate the polygon by ear clipping.
+    ///
+    /// Triangles are counter-clockwise, and given as indices of the
+    /// [`points()`] of the polygon. Points which are collinear with their
+    /// neighbors may be left out of the triangles.
+    ///
+    /// ```
+    /// # use cytogon::{Contour, Polygon, Vec2};
+    /// let square = |min: f32, max: f32| {
+    ///     let points = vec![
+    ///         Vec2::new(min, min),
+    ///         Vec2::new(max, min),
+    ///         Vec2::new(max, max),
+    ///         Vec2::new(min, max),
+    ///     ];
+    ///     Contour { points }
+    /// };
+    /// let mut hole = square(1., 2.);
+    /// hole.points.reverse();
+    /// let polygon = Polygon {
+    ///     outer: square(0., 3.),
+    ///     holes: vec![hole],
+    /// };
+    /// assert_eq!(polygon.triangulate().len(), 8);
+    /// ```
+    ///
+    /// [`points()`]: Self::points
+    pub fn triangulate(&self) -> Vec<[u32; 3]> {
+        let mut triangles = vec![];
+        if self.outer.points.len() < 3 {
+            return triangles;
+        }
+
+        let mut ring = Ring { nodes: vec![] };
+        let start = ring.push_loop(&self.outer.points, 0);
+        let mut first_index = self.outer.points.len() as u32;
+        let mut holes = vec![];
+        for hole in &self.holes {
+            if hole.points.len() >= 3 {
+                let first = ring.push_loop(&hole.points, first_index);
+                // Rightmost point of the hole
+                let m = (first..first + hole.points.len())
+                    .max_by(|a, b| ring.nodes[*a].pos.x.total_cmp(&ring.nodes[*b].pos.x))
+                    .unwrap();
+                holes.push(m);
+            }
+            first_index += hole.points.len() as u32;
+        }
+
+        // Bridge the holes from right to left, so that each bridge only
+        // crosses the outer boundary and the holes already bridged
+        holes.sort_by(|a, b| ring.nodes[*b].pos.x.total_cmp(&ring.nodes[*a].pos.x));
+        for m in holes {
+            if let Some(bridge) = ring.find_bridge(start, ring.nodes[m].pos) {
+                ring.split(bridge, m);
+            }
+        }
+
+        let mut count = ring.iter(start).count();
+        let mut ear = start;
+        let mut stop = ear;
+        let mut relaxed = false;
+        while count > 2 {
+            let Node {
+                index, prev, next, ..
+            } = ring.nodes[ear];
+            if ring.is_ear(ear) || relaxed {
+                triangles.push([ring.nodes[prev].index, index, ring.nodes[next].index]);
+                ring.remove(ear);
+                count -= 1;
+                relaxed = false;
+                ear = next;
+                stop = next;
+                continue;
+            }
+            ear = next;
+            if ear != stop {
+                continue;
+            }
+
+            // No ear left: drop the degenerate vertices, or as a last resort
+            // clip any convex vertex, which only happens with self-intersecting
+            // polygons
+            let before = count;
+            for i in ring.iter(ear).collect::<Vec<_>>() {
+                let Node {
+                    pos, prev, next, ..
+                } = ring.nodes[i];
+                if count > 2 && orient(ring.nodes[prev].pos, pos, ring.nodes[next].pos) == 0. {
+                    ring.remove(i);
+                    count -= 1;
+                    ear = next;
+                }
+            }
+            if count == before {
+                let convex = ring.iter(ear).find(|i| {
+                    let Node {
+                        pos, prev, next, ..
+                    } = ring.nodes[*i];
+                    orient(ring.nodes[prev].pos, pos, ring.nodes[next].pos) > 0.
+                });
+                match convex {
+                    Some(i) => {
+                        ear = i;
+                        relaxed = true;
+                    }
+                    None => break,
+                }
+            }
+            stop = ear;
+        }
+        triangles
+    }
+}
+
+impl Grid2 {
+    /// Triangulate the regions of alive or dead cells into a 2D mesh.
+    ///
+    /// The regions are bounded by the contours of [`contours()`], and the
+    /// meshes of the alive and dead regions fit together without gap or
+    /// overlap, except along the edges of the grid, where both have 45 degree
+    /// cut corners. The texture coordinates of each vertex are its position in
+    /// cell units, so that textures repeat once per cell.
+    ///
+    /// ```
+    /// # use cytogon::{ContourOptions, Grid2, IVec2, UVec2};
+    /// let mut grid = Grid2::new(UVec2::new(8, 8));
+    /// grid.fill(false);
+    /// grid.set_cell(IVec2::new(3, 4), true);
+    ///
+    /// // Floor of the open area, with a hole around the alive cell
+    /// let floor = grid.area_mesh(false, &ContourOptions::default());
+    /// let walls = grid.area_mesh(true, &ContourOptions::default());
+    /// assert_eq!(walls.area(), 0.5);
+    /// assert_eq!(floor.area() + walls.area(), 64. - 4. * 0.125);
+    /// ```
+    ///
+    /// [`contours()`]: Self::contours
+    pub fn area_mesh(&self, alive: bool, options: &ContourOptions) -> Mesh2 {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("area_mesh").entered();
+
+        let mut mesh = Mesh2::default();
+        for polygon in self.region_polygons(alive, options) {
+            let base = mesh.positions.len() as u32;
+            for p in polygon.points() {
+                mesh.positions.push(p);
+                mesh.uvs.push((p - options.origin) / options.cell_size);
+            }
+            for tri in polygon.triangulate() {
+                mesh.indices.extend(tri.map(|i| base + i));
+            }
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec2, UVec2};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{ContourOptions, Grid2};
+
+    fn area(points: &[Vec2], triangles: &[[u32; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| points[i as usize]);
+                orient(a, b, c) / 2.
+            })
+            .sum()
+    }
+
+    #[test]
+    fn random() {
+        let mut grid = Grid2::new(UVec2::new(45, 37));
+        for fill_ratio in [0.3, 0.5, 0.7] {
+            grid.fill_rand(fill_ratio, StdRng::seed_from_u64(42));
+            for options in [
+                ContourOptions::default(),
+                ContourOptions {
+                    interpolate: true,
+                    simplify: Some(0.),
+                    ..Default::default()
+                },
+            ] {
+                for polygon in grid.polygons(&options) {
+                    let points: Vec<Vec2> = polygon.points().collect();
+                    let triangles = polygon.triangulate();
+                    let expected = polygon.outer.signed_area()
+                        + polygon.holes.iter().map(|h| h.signed_area()).sum::<f32>();
+                    assert!((area(&points, &triangles) - expected).abs() < 1e-3);
+                    for [a, b, c] in triangles.iter().map(|t| t.map(|i| points[i as usize])) {
+                        assert!(orient(a, b, c) > 0.);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn complementary_areas() {
+        let mut grid = Grid2::new(UVec2::new(45, 37));
+        for fill_ratio in [0.3, 0.5, 0.7] {
+            grid.fill_rand(fill_ratio, StdRng::seed_from_u64(42));
+            let options = ContourOptions {
+                cell_size: Vec2::new(2., 0.5),
+                origin: Vec2::new(-4., 7.),
+                ..Default::default()
+            };
+            let alive = grid.area_mesh(true, &options);
+            let dead = grid.area_mesh(false, &options);
+            for mesh in [&alive, &dead] {
+                assert_eq!(mesh.uvs.len(), mesh.positions.len());
+                for [a, b, c] in mesh.triangles() {
+                    let [a, b, c] = [a, b, c].map(|i| mesh.positions[i as usize]);
+                    assert!(orient(a, b, c) > 0.);
+                }
+                for (p, uv) in mesh.positions.iter().zip(&mesh.uvs) {
+                    assert_eq!(options.origin + *uv * options.cell_size, *p);
+                    assert!(uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::new(45., 37.)).all());
+                }
+            }
+
+            // Together, the regions cover the grid, except its 4 cut corners, and
+            // a triangle of a quarter of a cell between each pair of cells in
+            // different states along its edges
+            let mut gaps = 0;
+            for (from, step, count) in [
+                (IVec2::ZERO, IVec2::X, 44),
+                (IVec2::new(0, 36), IVec2::X, 44),
+                (IVec2::ZERO, IVec2::Y, 36),
+                (IVec2::new(44, 0), IVec2::Y, 36),
+            ] {
+                for i in 0..count {
+                    let p = from + step * i;
+                    if grid.cell(p) != grid.cell(p + step) {
+                        gaps += 1;
+                    }
+                }
+            }
+            let cell_area = 2. * 0.5;
+            let total = (45. * 37. - 4. * 0.125 - gaps as f32 * 0.25) * cell_area;
+            assert!((alive.area() + dead.area() - total).abs() < 1e-2);
+
+            // Alive and dead regions share their contours
+            let mut alive_points = alive.positions.clone();
+            let mut dead_points: Vec<Vec2> = dead
+                .positions
+                .iter()
+                .copied()
+                .filter(|p| {
+                    let uv = (*p - options.origin) / options.cell_size;
+                    uv.cmpgt(Vec2::ZERO).all() && uv.cmplt(Vec2::new(45., 37.)).all()
+                })
+                .collect();
+            alive_points.retain(|p| dead_points.contains(p));
+            dead_points.retain(|p| alive_points.contains(p));
+            assert!(!alive_points.is_empty());
+            assert_eq!(alive_points.len(), dead_points.len());
+        }
+    }
+
+    #[test]
+    fn nested_holes() {
+        // Concentric rings, each one inside the hole of the previous one
+        let mut grid = Grid2::new(UVec2::splat(21));
+        grid.fill(false);
+        for y in 0..21 {
+            for x in 0..21 {
+                let d = (IVec2::new(x, y) - 10).abs().max_element();
+                grid.set_cell(IVec2::new(x, y), d % 4 < 2);
+            }
+        }
+        let polygons = grid.polygons(&ContourOptions::default());
+        assert_eq!(polygons.len(), 3);
+        for polygon in polygons {
+            let points: Vec<Vec2> = polygon.points().collect();
+            let triangles = polygon.triangulate();
+            // Without collinear points, n vertices and h holes give n + 2h - 2
+            // triangles
+            assert!(triangles.len() <= points.len() + 2 * polygon.holes.len() - 2);
+            let expected = polygon.outer.signed_area()
+                + polygon.holes.iter().map(|h| h.signed_area()).sum::<f32>();
+            assert!((area(&points, &triangles) - expected).abs() < 1e-3);
+        }
+    }
+}
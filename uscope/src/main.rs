@@ -4,7 +4,7 @@ use bevy::{
     asset::RenderAssetUsages,
     input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
     prelude::*,
-    render::mesh::{PrimitiveTopology, VertexAttributeValues},
+    render::mesh::{Mesh, PrimitiveTopology, VertexAttributeValues},
 };
 use bevy_egui::{
     egui::{self, CollapsingHeader, Rounding},